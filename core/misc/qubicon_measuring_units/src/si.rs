@@ -18,7 +18,8 @@ pub mod symbolic {
             Ohm as Ohm, // :)
             Celsius as C,
             Lumen as Lm,
-            Lux as Lx
+            Lux as Lx,
+            MetrePerSecond as Mps
         }
     };
 }
@@ -68,7 +69,8 @@ pub mod derived_units {
         // Henry ("H"),
         Celsius ("\u{00B0}\u{0043}", "../docs/si/celsius.md"),
         Lumen ("lm", "../docs/si/lumen.md"),
-        Lux ("lx", "../docs/si/lux.md")
+        Lux ("lx", "../docs/si/lux.md"),
+        MetrePerSecond ("m/s", "../docs/si/metre_per_second.md")
         // Becquerel ("Bq"),
         // Gray ("Gy"),
         // Sievert ("Sv"),
@@ -131,6 +133,24 @@ pub mod derived_units {
 
 
 
+    impl<T: Num + Copy + 'static> MetrePerSecond<T> {
+        pub fn from_distance_and_time(dist: base_units::Metre<T>, time: base_units::Second<T>) -> Self {
+            dist / time
+        }
+    }
+
+    impl<T: Num + Copy + 'static> core::ops::Div<base_units::Second<T>> for base_units::Metre<T> {
+        type Output = MetrePerSecond<T>;
+
+        fn div(self, rhs: base_units::Second<T>) -> Self::Output {
+            MetrePerSecond::from( self.as_() / rhs.as_() )
+        }
+    }
+
+
+
+
+
     impl<T: Num + FromPrimitive + Copy + 'static> From<base_units::Kelvin<T>> for Celsius<T> {
         fn from(value: base_units::Kelvin<T>) -> Self {
             Self::from( value.as_() - FromPrimitive::from_f64(273.15).unwrap() )
@@ -227,6 +247,17 @@ mod tests {
         assert_eq!(lux.as_(), lumens.as_() / area.as_());
     }
 
+    #[test]
+    fn metres_and_seconds_2_metres_per_second() {
+        let dist = Metre::from(343.0);
+        let time = Second::from(2.0);
+
+        let speed = MetrePerSecond::from_distance_and_time(dist, time);
+
+        assert_eq!(speed.as_(), 343.0 / 2.0);
+        assert_eq!(dist / time, speed);
+    }
+
     #[test]
     fn print() {
         println!("{}", Celsius::from(36.6));
@@ -105,6 +105,16 @@ mod f32x4 {
         }
     }
 
+    impl From<F32x4> for [f32; 4] {
+        fn from(value: F32x4) -> Self {
+            let mut out = [0.0; 4];
+
+            unsafe { _mm_storeu_ps(out.as_mut_ptr(), value.0) }
+
+            out
+        }
+    }
+
     #[cfg(target_feature = "sse2")]
     impl From<super::super::I32x4> for F32x4 {
         fn from(value: super::super::I32x4) -> Self {
@@ -192,6 +202,13 @@ mod f32x4 {
 
             println!("{:?}\n{:?}\n{:?}\n{:?}\n{:?}", a + b, a - b, a * b, a / b, (b * b).sqrt());
         }
+
+        #[test]
+        fn f32x4_to_array() {
+            let a = F32x4::new(1.0, 2.0, 3.0, 4.0);
+
+            assert_eq!(<[f32; 4]>::from(a * F32x4::new_fill(2.0)), [2.0, 4.0, 6.0, 8.0]);
+        }
     }
 }
 
@@ -0,0 +1,46 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    #[error("io error. {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("unknown file format")]
+    UnknownFormat,
+
+    #[error("unsupported stream. {0}")]
+    Unsupported(&'static str),
+
+    #[error("malformed stream. {0}")]
+    Malformed(String)
+}
+
+impl From<hound::Error> for DecodeError {
+    fn from(value: hound::Error) -> Self {
+        match value {
+            hound::Error::IoError(e) => Self::Io(e),
+            hound::Error::Unsupported => Self::Unsupported("wav encoding"),
+            hound::Error::TooWide => Self::Unsupported("wav sample width"),
+            e => Self::Malformed(e.to_string())
+        }
+    }
+}
+
+impl From<claxon::Error> for DecodeError {
+    fn from(value: claxon::Error) -> Self {
+        match value {
+            claxon::Error::IoError(e) => Self::Io(e),
+            claxon::Error::Unsupported(e) => Self::Unsupported(e),
+            claxon::Error::FormatError(e) => Self::Malformed(e.to_string())
+        }
+    }
+}
+
+impl From<lewton::VorbisError> for DecodeError {
+    fn from(value: lewton::VorbisError) -> Self {
+        match value {
+            lewton::VorbisError::OggError(lewton::OggReadError::ReadError(e)) => Self::Io(e),
+            e => Self::Malformed(e.to_string())
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::ChannelMap;
+use super::{Decoder, DecodeError};
+
+pub(crate) struct FlacDecoder<R: Read + Seek> {
+    // None only if rewind failed
+    reader: Option<claxon::FlacReader<R>>,
+    // reused between blocks
+    block_buffer: Vec<i32>,
+
+    rate: u32,
+    channel_map: ChannelMap,
+    scale: f32
+}
+
+impl<R: Read + Seek> FlacDecoder<R> {
+    pub(crate) fn new(reader: R) -> Result<Self, DecodeError> {
+        let reader = claxon::FlacReader::new(reader)?;
+        let info = reader.streaminfo();
+
+        Ok (
+            Self {
+                reader: Some(reader),
+                block_buffer: Vec::new(),
+
+                rate: info.sample_rate,
+                // FLAC uses the same channel order as WAVE
+                channel_map: super::wave_channel_map(info.channels as usize)?,
+                scale: 1.0 / (1u64 << (info.bits_per_sample - 1)) as f32
+            }
+        )
+    }
+}
+
+impl<R: Read + Seek + Send> Decoder for FlacDecoder<R> {
+    fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    fn channel_map(&self) -> &ChannelMap {
+        &self.channel_map
+    }
+
+    fn decode_chunk(&mut self, out: &mut Vec<f32>) -> Result<bool, DecodeError> {
+        let Some(reader) = self.reader.as_mut() else { return Ok(false) };
+        let buffer = core::mem::take(&mut self.block_buffer);
+
+        let Some(block) = reader.blocks().read_next_or_eof(buffer)? else { return Ok(false) };
+
+        // blocks are stored channel after channel
+        for i in 0..block.duration() {
+            for channel in 0..block.channels() {
+                out.push(block.sample(channel, i) as f32 * self.scale);
+            }
+        }
+
+        self.block_buffer = block.into_buffer();
+
+        Ok(true)
+    }
+
+    fn rewind(&mut self) -> Result<(), DecodeError> {
+        let Some(reader) = self.reader.take() else { return Err(DecodeError::Unsupported("rewind after failed rewind")) };
+
+        // claxon can't seek, so reader is recreated from the beginning
+        let mut inner = reader.into_inner();
+        inner.seek(SeekFrom::Start(0))?;
+
+        self.reader = Some(claxon::FlacReader::new(inner)?);
+
+        Ok(())
+    }
+}
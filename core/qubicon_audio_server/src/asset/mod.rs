@@ -0,0 +1,418 @@
+//! Decoding of sound files. Supports WAV (8, 16, 24, 32 bit int and 32 bit float), FLAC and Ogg Vorbis.
+//! Everything is decoded into interleaved f32, so result can be written into `PlaybackStream<f32>`
+//! or played by [`crate::mixer::Mixer`]
+
+use std::{fs::File, io::{BufReader, Read, Seek, SeekFrom}, path::Path, time::Duration};
+
+use crate::{ChannelMap, ChannelPosition};
+use crate::mixer::{SampleBuffer, BufferSource, Source};
+use crate::convert::{Resampler, ResamplerQuality, Remixer};
+use wav::WavDecoder;
+use flac::FlacDecoder;
+use vorbis::VorbisDecoder;
+
+pub use error::DecodeError;
+
+mod error;
+mod wav;
+mod flac;
+mod vorbis;
+
+// ammount of frames, decoded at once, for formats without natural blocks
+const CHUNK_FRAMES: usize = 4096;
+
+pub(crate) trait Decoder: Send {
+    fn rate(&self) -> u32;
+    fn channel_map(&self) -> &ChannelMap;
+
+    /// Appends next chunk of interleaved samples to `out`. Returns false, if stream is ended
+    fn decode_chunk(&mut self, out: &mut Vec<f32>) -> Result<bool, DecodeError>;
+    /// Moves decoder to the first frame
+    fn rewind(&mut self) -> Result<(), DecodeError>;
+}
+
+/// Detects container by its magic and creates matching decoder
+fn open_decoder<'a, R: Read + Seek + Send + 'a>(mut reader: R) -> Result<Box<dyn Decoder + 'a>, DecodeError> {
+    let start = reader.stream_position()?;
+    let mut magic = [0u8; 12];
+    let mut len = 0;
+
+    // short files are fine, they just won't match anything
+    while len < magic.len() {
+        match reader.read(&mut magic[len..])? {
+            0 => break,
+            n => len += n
+        }
+    }
+
+    reader.seek(SeekFrom::Start(start))?;
+
+    match &magic[..len] {
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E'] => Ok(Box::new(WavDecoder::new(reader)?)),
+        [b'f', b'L', b'a', b'C', ..] => Ok(Box::new(FlacDecoder::new(reader)?)),
+        [b'O', b'g', b'g', b'S', ..] => Ok(Box::new(VorbisDecoder::new(reader)?)),
+        _ => Err(DecodeError::UnknownFormat)
+    }
+}
+
+/// Channel order of WAVE and FLAC without explicit channel mask
+fn wave_channel_map(channels: usize) -> Result<ChannelMap, DecodeError> {
+    use libpulse_sys::pa_channel_position_t::*;
+
+    let positions: &[ChannelPosition] = match channels {
+        1 => &[Mono],
+        2 => &[FrontLeft, FrontRight],
+        3 => &[FrontLeft, FrontRight, FrontCenter],
+        4 => &[FrontLeft, FrontRight, RearLeft, RearRight],
+        5 => &[FrontLeft, FrontRight, FrontCenter, RearLeft, RearRight],
+        6 => &[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight],
+        7 => &[FrontLeft, FrontRight, FrontCenter, Lfe, RearCenter, SideLeft, SideRight],
+        8 => &[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight, SideLeft, SideRight],
+        _ => return Err(DecodeError::Unsupported("channel count"))
+    };
+
+    Ok ( ChannelMap::from_positions(positions) )
+}
+
+/// Channel order of Vorbis I specification
+fn vorbis_channel_map(channels: usize) -> Result<ChannelMap, DecodeError> {
+    use libpulse_sys::pa_channel_position_t::*;
+
+    let positions: &[ChannelPosition] = match channels {
+        1 => &[Mono],
+        2 => &[FrontLeft, FrontRight],
+        3 => &[FrontLeft, FrontCenter, FrontRight],
+        4 => &[FrontLeft, FrontRight, RearLeft, RearRight],
+        5 => &[FrontLeft, FrontCenter, FrontRight, RearLeft, RearRight],
+        6 => &[FrontLeft, FrontCenter, FrontRight, RearLeft, RearRight, Lfe],
+        7 => &[FrontLeft, FrontCenter, FrontRight, SideLeft, SideRight, RearCenter, Lfe],
+        8 => &[FrontLeft, FrontCenter, FrontRight, SideLeft, SideRight, RearLeft, RearRight, Lfe],
+        _ => return Err(DecodeError::Unsupported("channel count"))
+    };
+
+    Ok ( ChannelMap::from_positions(positions) )
+}
+
+
+
+/// Fully decoded sound, stored in memory. Cheap to clone
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sound {
+    rate: u32,
+    buffer: SampleBuffer
+}
+
+impl Sound {
+    /// Decodes whole file. Format is detected by content, not by extension
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, DecodeError> {
+        Self::decode(BufReader::new(File::open(path)?))
+    }
+
+    pub fn decode<R: Read + Seek + Send>(reader: R) -> Result<Self, DecodeError> {
+        let mut decoder = open_decoder(reader)?;
+        let mut samples = Vec::new();
+
+        while decoder.decode_chunk(&mut samples)? {}
+
+        // truncated file may end in the middle of frame
+        let channels = decoder.channel_map().len();
+        samples.truncate(samples.len() - samples.len() % channels);
+
+        Ok (
+            Self {
+                rate: decoder.rate(),
+                buffer: SampleBuffer::new(samples, decoder.channel_map().clone())
+            }
+        )
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    pub fn channel_map(&self) -> &ChannelMap {
+        self.buffer.channel_map()
+    }
+
+    /// Interleaved samples in [-1, 1]
+    pub fn samples(&self) -> &[f32] {
+        self.buffer.samples()
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.buffer.frame_count()
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frame_count() as f64 / self.rate as f64)
+    }
+
+    pub fn buffer(&self) -> &SampleBuffer {
+        &self.buffer
+    }
+
+    /// Creates mixer source, what plays this sound from the beginning
+    pub fn source(&self) -> BufferSource {
+        self.buffer.source()
+    }
+
+    /// Converts sound to other rate and channel layout, so it can be written straight into stream
+    pub fn convert(&self, rate: u32, channel_map: &ChannelMap, quality: ResamplerQuality) -> Self {
+        let mut resampled = Vec::new();
+        let mut resampler = Resampler::new(self.rate, rate, self.channel_map().len(), quality);
+
+        resampler.process(self.samples(), &mut resampled);
+        resampler.flush(&mut resampled);
+
+        let frames = resampled.len() / self.channel_map().len();
+        let mut samples = vec![0.0; frames * channel_map.len()];
+
+        Remixer::new(self.channel_map(), channel_map).process(&resampled, &mut samples);
+
+        Self { rate, buffer: SampleBuffer::new(samples, channel_map.clone()) }
+    }
+}
+
+impl From<Sound> for SampleBuffer {
+    fn from(value: Sound) -> Self {
+        value.buffer
+    }
+}
+
+
+
+/// Sound, what is decoded chunk by chunk while playing. For music and other long files.
+/// Decoding errors end the stream, error can be checked with [`Self::error`]
+pub struct SoundStream {
+    decoder: Box<dyn Decoder>,
+
+    // decoded, but not read yet samples
+    chunk: Vec<f32>,
+    position: usize,
+
+    ended: bool,
+    error: Option<DecodeError>
+}
+
+impl SoundStream {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, DecodeError> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+
+    pub fn new<R: Read + Seek + Send + 'static>(reader: R) -> Result<Self, DecodeError> {
+        Ok (
+            Self {
+                decoder: open_decoder(reader)?,
+
+                chunk: Vec::new(),
+                position: 0,
+
+                ended: false,
+                error: None
+            }
+        )
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.decoder.rate()
+    }
+
+    /// Error, what ended the stream
+    pub fn error(&self) -> Option<&DecodeError> {
+        self.error.as_ref()
+    }
+
+    // decodes next chunk. Returns false, if there is nothing more
+    fn refill(&mut self) -> bool {
+        self.chunk.clear();
+        self.position = 0;
+
+        match self.decoder.decode_chunk(&mut self.chunk) {
+            Ok(more) => self.ended = !more,
+            Err(e) => {
+                self.ended = true;
+                self.error = Some(e);
+            }
+        }
+
+        // partial frame at the end of broken stream is dropped
+        if self.ended {
+            let channels = self.decoder.channel_map().len();
+            self.chunk.truncate(self.chunk.len() - self.chunk.len() % channels);
+        }
+
+        !self.chunk.is_empty()
+    }
+}
+
+impl Source for SoundStream {
+    fn channel_map(&self) -> &ChannelMap {
+        self.decoder.channel_map()
+    }
+
+    fn read(&mut self, out: &mut [f32]) -> usize {
+        let mut total = 0;
+
+        while total < out.len() {
+            if self.position == self.chunk.len() && (self.ended || !self.refill()) {
+                break;
+            }
+
+            let remaining = &self.chunk[self.position..];
+            let ammount = remaining.len().min(out.len() - total);
+
+            out[total..total + ammount].copy_from_slice(&remaining[..ammount]);
+            self.position += ammount;
+            total += ammount;
+        }
+
+        total
+    }
+
+    fn rewind(&mut self) -> bool {
+        match self.decoder.rewind() {
+            Ok(()) => {
+                self.chunk.clear();
+                self.position = 0;
+                self.ended = false;
+                self.error = None;
+
+                true
+            },
+            Err(e) => {
+                self.error = Some(e);
+
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::ChannelPosition;
+    use crate::mixer::{Mixer, Source, VoiceSettings};
+    use crate::convert::ResamplerQuality;
+    use super::{Sound, SoundStream, DecodeError};
+
+    const LEFT: [f32; 8] = [0.0, 0.5, -0.5, 0.25, -0.25, 0.75, -1.0, 0.125];
+    const RIGHT: [f32; 8] = [0.0, -0.5, 0.5, -0.25, 0.25, -0.75, 0.75, -0.125];
+
+    fn test_file(name: &str) -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "test_data", name].iter().collect()
+    }
+
+    fn expected_stereo() -> Vec<f32> {
+        LEFT.iter().zip(RIGHT.iter()).flat_map(| (&l, &r) | [l, r]).collect()
+    }
+
+    fn assert_close(a: &[f32], b: &[f32], tolerance: f32) {
+        assert_eq!(a.len(), b.len());
+
+        for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+            assert!((a - b).abs() <= tolerance, "sample {i}: {a} != {b}");
+        }
+    }
+
+    #[test]
+    fn wav_formats() {
+        // 8 bit can't represent 0.75 and 0.125 exactly
+        for (name, tolerance) in [("stereo_u8.wav", 1.0 / 128.0), ("stereo_i16.wav", 0.0), ("stereo_i24.wav", 0.0), ("stereo_i32.wav", 0.0), ("stereo_f32.wav", 0.0)] {
+            let sound = Sound::open(test_file(name)).unwrap();
+
+            assert_eq!(sound.rate(), 8000, "{name}");
+            assert_eq!(&sound.channel_map()[..], &[ChannelPosition::FrontLeft, ChannelPosition::FrontRight]);
+            assert_eq!(sound.frame_count(), 8);
+            assert_close(sound.samples(), &expected_stereo(), tolerance);
+        }
+    }
+
+    #[test]
+    fn flac() {
+        let sound = Sound::open(test_file("stereo_i16.flac")).unwrap();
+        let expected = [expected_stereo(), expected_stereo()].concat();
+
+        assert_eq!(sound.rate(), 8000);
+        assert_eq!(sound.frame_count(), 16);
+        assert_close(sound.samples(), &expected, 0.0);
+    }
+
+    #[test]
+    fn vorbis() {
+        let sound = Sound::open(test_file("beep.ogg")).unwrap();
+
+        assert_eq!(sound.rate(), 44100);
+        assert_eq!(&sound.channel_map()[..], &[ChannelPosition::Mono]);
+        assert_eq!(sound.frame_count(), 441000);
+        assert_eq!(sound.duration().as_secs(), 10);
+        assert!(sound.samples().iter().all(| s | s.abs() <= 1.0));
+        assert!(sound.samples().iter().any(| s | s.abs() > 0.5));
+    }
+
+    #[test]
+    fn convert() {
+        let sound = Sound::open(test_file("stereo_i16.flac")).unwrap();
+        let mono = crate::ChannelMap::from_positions(&[ChannelPosition::Mono]);
+        let converted = sound.convert(16000, &mono, ResamplerQuality::Linear);
+
+        assert_eq!(converted.rate(), 16000);
+        assert_eq!(converted.frame_count(), 32);
+        // channels of the fixture cancel each other, except the -1.0 frame
+        assert_eq!(converted.samples()[0], 0.0);
+        assert_eq!(converted.samples()[12], -0.125);
+    }
+
+    #[test]
+    fn unknown_format() {
+        let result = Sound::decode(std::io::Cursor::new(b"definitely not a sound file"));
+
+        assert!(matches!(result, Err(DecodeError::UnknownFormat)));
+    }
+
+    #[test]
+    fn stream_matches_sound() {
+        for name in ["stereo_i24.wav", "stereo_i16.flac", "beep.ogg"] {
+            let sound = Sound::open(test_file(name)).unwrap();
+            let mut stream = SoundStream::open(test_file(name)).unwrap();
+            let mut samples = Vec::new();
+            // odd size to cross chunk borders
+            let mut out = [0.0; 1002];
+
+            loop {
+                let ammount = stream.read(&mut out);
+                samples.extend_from_slice(&out[..ammount]);
+
+                if ammount < out.len() {
+                    break;
+                }
+            }
+
+            assert_eq!(samples, sound.samples(), "{name}");
+            assert!(stream.error().is_none());
+
+            // second pass after rewind is the same
+            assert!(stream.rewind());
+
+            let ammount = stream.read(&mut out[..sound.samples().len().min(1002)]);
+            assert_eq!(&out[..ammount], &sound.samples()[..ammount]);
+        }
+    }
+
+    #[test]
+    fn stream_loops_in_mixer() {
+        let stream = SoundStream::open(test_file("stereo_i16.flac")).unwrap();
+        let mut mixer = Mixer::new(8000, stream.channel_map().clone());
+        let expected = [expected_stereo(), expected_stereo(), expected_stereo()].concat();
+
+        mixer.play(stream, VoiceSettings { looping: true, ..Default::default() });
+
+        // 1.5 loops
+        let mut out = vec![0.0; 48];
+        mixer.mix(&mut out);
+
+        assert_close(&out, &expected[..48], 1e-6);
+        assert_eq!(mixer.voice_count(), 1);
+    }
+}
@@ -0,0 +1,52 @@
+use std::io::{Read, Seek};
+
+use lewton::{inside_ogg::OggStreamReader, samples::InterleavedSamples};
+
+use crate::ChannelMap;
+use super::{Decoder, DecodeError};
+
+pub(crate) struct VorbisDecoder<R: Read + Seek> {
+    reader: OggStreamReader<R>,
+
+    channel_map: ChannelMap
+}
+
+impl<R: Read + Seek> VorbisDecoder<R> {
+    pub(crate) fn new(reader: R) -> Result<Self, DecodeError> {
+        let reader = OggStreamReader::new(reader)?;
+        let channel_map = super::vorbis_channel_map(reader.ident_hdr.audio_channels as usize)?;
+
+        Ok ( Self { reader, channel_map } )
+    }
+}
+
+impl<R: Read + Seek + Send> Decoder for VorbisDecoder<R> {
+    fn rate(&self) -> u32 {
+        self.reader.ident_hdr.audio_sample_rate
+    }
+
+    fn channel_map(&self) -> &ChannelMap {
+        &self.channel_map
+    }
+
+    fn decode_chunk(&mut self, out: &mut Vec<f32>) -> Result<bool, DecodeError> {
+        loop {
+            match self.reader.read_dec_packet_generic::<InterleavedSamples<f32>>()? {
+                // first packet of stream is always empty
+                Some(packet) if packet.samples.is_empty() => continue,
+                Some(packet) => {
+                    out.extend_from_slice(&packet.samples);
+
+                    return Ok(true);
+                },
+                None => return Ok(false)
+            }
+        }
+    }
+
+    fn rewind(&mut self) -> Result<(), DecodeError> {
+        self.reader.seek_absgp_pg(0)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use std::io::{Read, Seek};
+
+use crate::ChannelMap;
+use super::{Decoder, DecodeError, CHUNK_FRAMES};
+
+enum SampleKind {
+    // scale to bring integer into [-1, 1)
+    Int { scale: f32 },
+    Float
+}
+
+pub(crate) struct WavDecoder<R: Read + Seek> {
+    reader: hound::WavReader<R>,
+
+    rate: u32,
+    channel_map: ChannelMap,
+    kind: SampleKind
+}
+
+impl<R: Read + Seek> WavDecoder<R> {
+    pub(crate) fn new(reader: R) -> Result<Self, DecodeError> {
+        let reader = hound::WavReader::new(reader)?;
+        let spec = reader.spec();
+
+        let kind = match (spec.sample_format, spec.bits_per_sample) {
+            // hound converts unsigned 8 bit samples to signed
+            (hound::SampleFormat::Int, bits @ 1..=32) => SampleKind::Int { scale: 1.0 / (1u64 << (bits - 1)) as f32 },
+            (hound::SampleFormat::Float, 32) => SampleKind::Float,
+            _ => return Err(DecodeError::Unsupported("wav sample format"))
+        };
+
+        Ok (
+            Self {
+                reader,
+
+                rate: spec.sample_rate,
+                channel_map: super::wave_channel_map(spec.channels as usize)?,
+                kind
+            }
+        )
+    }
+}
+
+impl<R: Read + Seek + Send> Decoder for WavDecoder<R> {
+    fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    fn channel_map(&self) -> &ChannelMap {
+        &self.channel_map
+    }
+
+    fn decode_chunk(&mut self, out: &mut Vec<f32>) -> Result<bool, DecodeError> {
+        let ammount = CHUNK_FRAMES * self.channel_map.len();
+        let len = out.len();
+
+        match self.kind {
+            SampleKind::Int { scale } => for sample in self.reader.samples::<i32>().take(ammount) {
+                out.push(sample? as f32 * scale);
+            },
+            SampleKind::Float => for sample in self.reader.samples::<f32>().take(ammount) {
+                out.push(sample?);
+            }
+        }
+
+        Ok ( out.len() > len )
+    }
+
+    fn rewind(&mut self) -> Result<(), DecodeError> {
+        self.reader.seek(0)?;
+
+        Ok(())
+    }
+}
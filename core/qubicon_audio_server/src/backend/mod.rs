@@ -0,0 +1,46 @@
+//! Backends, what [`crate::AudioServer`] runs on.
+//! [`PulseBackend`] talks to PulseAudio, [`NullBackend`] runs on virtual clock without any device
+
+use std::pin::Pin;
+
+use crate::{Result, ChannelMap, Proplist, StreamFlags, BufferAttributes, Format, StreamRead, StreamWrite};
+
+pub use pulse::PulseBackend;
+pub use null::{NullBackend, NullPlaybackStream, NullRecordStream};
+
+mod pulse;
+mod null;
+
+pub trait Backend {
+    type PlaybackStream<F: Format>: StreamWrite<F>;
+    type RecordStream<F: Format>: StreamRead<F>;
+
+    /// None as `device` means default sink
+    #[allow(clippy::too_many_arguments)]
+    fn create_playback_stream<F: Format>(
+        &self,
+        name: &str,
+        device: Option<&str>,
+        rate: u32,
+        channel_map: &ChannelMap,
+        flags: StreamFlags,
+        properties: Option<&Proplist>,
+        buffer_attributes: Option<&BufferAttributes>
+    ) -> Result<Pin<Box<Self::PlaybackStream<F>>>>;
+
+    /// None as `device` means default source
+    #[allow(clippy::too_many_arguments)]
+    fn create_record_stream<F: Format>(
+        &self,
+        name: &str,
+        device: Option<&str>,
+        rate: u32,
+        channel_map: &ChannelMap,
+        flags: StreamFlags,
+        properties: Option<&Proplist>,
+        buffer_attributes: Option<&BufferAttributes>
+    ) -> Result<Pin<Box<Self::RecordStream<F>>>>;
+
+    /// Processes pending events
+    fn update(&self) -> Result<()>;
+}
@@ -0,0 +1,548 @@
+use std::{collections::VecDeque, io, path::Path, pin::Pin, sync::{Arc, Mutex, MutexGuard, Weak}, time::Duration};
+
+use crate::{Result, ChannelMap, Proplist, StreamFlags, BufferAttributes, Format, Sample, StreamRead, StreamWrite, ReadResult};
+use crate::ring_buffer::{ring_buffer, RingProducer, RingConsumer};
+use super::Backend;
+
+// playback buffer length, if buffer attributes don't say otherwise
+const DEFAULT_BUFFER_LENGTH: Duration = Duration::from_millis(250);
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+// stream, what consumes or produces frames when virtual clock moves
+trait VirtualStream: Send {
+    fn advance(&mut self, clock: VirtualTime);
+}
+
+// Exact time in seconds as a fraction, so frames at any rate are never lost to rounding.
+// Denominator is lcm of 10^9 and all rates, what clock was moved with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct VirtualTime {
+    num: u128,
+    den: u128
+}
+
+impl VirtualTime {
+    const ZERO: Self = Self { num: 0, den: NANOS_PER_SEC };
+
+    fn from_duration(duration: Duration) -> Self {
+        Self { num: duration.as_nanos(), den: NANOS_PER_SEC }
+    }
+
+    fn from_frames(frames: u64, rate: u32) -> Self {
+        Self { num: frames as u128, den: rate as u128 }
+    }
+
+    fn with_den(self, den: u128) -> u128 {
+        self.num * (den / self.den)
+    }
+
+    fn add(self, rhs: Self) -> Self {
+        let den = lcm(self.den, rhs.den);
+
+        Self { num: self.with_den(den) + rhs.with_den(den), den }
+    }
+
+    fn saturating_sub(self, rhs: Self) -> Self {
+        let den = lcm(self.den, rhs.den);
+
+        Self { num: self.with_den(den).saturating_sub(rhs.with_den(den)), den }
+    }
+
+    // whole frames in this time
+    fn frames(self, rate: u32) -> u64 {
+        (self.num * rate as u128 / self.den) as u64
+    }
+
+    fn duration(self) -> Duration {
+        Duration::from_nanos((self.num * NANOS_PER_SEC / self.den) as u64)
+    }
+}
+
+fn lcm(a: u128, b: u128) -> u128 {
+    let (mut x, mut y) = (a, b);
+
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+
+    a / x * b
+}
+
+fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // state stays consistent even if some user panicked while holding the lock
+    mutex.lock().unwrap_or_else(| e | e.into_inner())
+}
+
+// frames between stream start and `clock`
+fn frames_at(start: VirtualTime, clock: VirtualTime, rate: u32) -> u64 {
+    clock.saturating_sub(start).frames(rate)
+}
+
+struct NullState {
+    clock: VirtualTime,
+    streams: Vec<Weak<Mutex<dyn VirtualStream>>>
+}
+
+/// Backend without any device. Streams are played and recorded only when [`Self::advance`] is called,
+/// so the output is exactly the same on every run. Played audio is kept and can be saved as WAV
+#[derive(Clone)]
+pub struct NullBackend {
+    state: Arc<Mutex<NullState>>
+}
+
+impl NullBackend {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(
+                Mutex::new(
+                    NullState {
+                        clock: VirtualTime::ZERO,
+                        streams: Vec::new()
+                    }
+                )
+            )
+        }
+    }
+
+    /// Virtual time since backend creation
+    pub fn time(&self) -> Duration {
+        lock(&self.state).clock.duration()
+    }
+
+    /// Moves virtual clock forward. Every stream plays or records frames for this period
+    pub fn advance(&self, duration: Duration) {
+        self.advance_by(VirtualTime::from_duration(duration));
+    }
+
+    /// Same as [`Self::advance`] by exact ammount of frames at `rate`.
+    /// Streams with other rates get the same period, without rounding to nanoseconds
+    pub fn advance_frames(&self, frames: u64, rate: u32) {
+        self.advance_by(VirtualTime::from_frames(frames, rate));
+    }
+
+    fn advance_by(&self, period: VirtualTime) {
+        let mut state = lock(&self.state);
+
+        state.clock = state.clock.add(period);
+
+        let clock = state.clock;
+
+        state.streams.retain(| stream | match stream.upgrade() {
+            Some(stream) => {
+                lock(&stream).advance(clock);
+
+                true
+            },
+            None => false
+        });
+    }
+
+    fn register(&self, stream: Arc<Mutex<dyn VirtualStream>>) {
+        lock(&self.state).streams.push(Arc::downgrade(&stream));
+    }
+}
+
+impl Default for NullBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backend for NullBackend {
+    type PlaybackStream<F: Format> = NullPlaybackStream<F>;
+    type RecordStream<F: Format> = NullRecordStream<F>;
+
+    fn create_playback_stream<F: Format>(
+        &self,
+        _name: &str,
+        _device: Option<&str>,
+        rate: u32,
+        channel_map: &ChannelMap,
+        _flags: StreamFlags,
+        _properties: Option<&Proplist>,
+        buffer_attributes: Option<&BufferAttributes>
+    ) -> Result<Pin<Box<NullPlaybackStream<F>>>> {
+        let channels = channel_map.len();
+        let capacity = match buffer_attributes.and_then(| a | a.target_length()) {
+            Some(bytes) => bytes as usize / core::mem::size_of::<F>(),
+            None => VirtualTime::from_duration(DEFAULT_BUFFER_LENGTH).frames(rate) as usize * channels
+        };
+
+        let state = Arc::new(
+            Mutex::new(
+                PlaybackState {
+                    rate,
+                    channel_map: channel_map.clone(),
+
+                    start: lock(&self.state).clock,
+                    played_frames: 0,
+
+                    queue: VecDeque::new(),
+                    // at least one frame, or nothing can be written
+                    queue_capacity: (capacity - capacity % channels).max(channels),
+                    buffer: None,
+
+                    underflows: 0,
+                    output: Vec::new()
+                }
+            )
+        );
+
+        self.register(state.clone());
+
+        Ok ( Box::pin(NullPlaybackStream { state }) )
+    }
+
+    fn create_record_stream<F: Format>(
+        &self,
+        _name: &str,
+        _device: Option<&str>,
+        rate: u32,
+        channel_map: &ChannelMap,
+        _flags: StreamFlags,
+        _properties: Option<&Proplist>,
+        _buffer_attributes: Option<&BufferAttributes>
+    ) -> Result<Pin<Box<NullRecordStream<F>>>> {
+        let state = Arc::new(
+            Mutex::new(
+                RecordState {
+                    rate,
+                    channel_map: channel_map.clone(),
+
+                    start: lock(&self.state).clock,
+                    recorded_frames: 0,
+
+                    input: VecDeque::new(),
+                    recorded: VecDeque::new()
+                }
+            )
+        );
+
+        self.register(state.clone());
+
+        Ok ( Box::pin(NullRecordStream { state }) )
+    }
+
+    /// Does nothing, clock is moved only by [`NullBackend::advance`]
+    fn update(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+
+
+struct PlaybackState<F: Format> {
+    rate: u32,
+    channel_map: ChannelMap,
+
+    start: VirtualTime,
+    played_frames: u64,
+
+    // written by StreamWrite::write
+    queue: VecDeque<F>,
+    queue_capacity: usize,
+    // Some in buffered mode
+    buffer: Option<RingConsumer<F>>,
+
+    underflows: u64,
+    output: Vec<f32>
+}
+
+impl<F: Format> VirtualStream for PlaybackState<F> {
+    fn advance(&mut self, clock: VirtualTime) {
+        let frames = frames_at(self.start, clock, self.rate);
+        let len = (frames - self.played_frames) as usize * self.channel_map.len();
+
+        self.played_frames = frames;
+
+        if len == 0 {
+            return;
+        }
+
+        let mut samples = vec![F::SILENCE; len];
+        let mut filled = self.queue.len().min(len);
+
+        for (dst, src) in samples.iter_mut().zip(self.queue.drain(..filled)) {
+            *dst = src;
+        }
+
+        if let Some(buffer) = self.buffer.as_mut() {
+            let channels = self.channel_map.len();
+            // only whole frames, or channels will be shifted after silence
+            let available = (buffer.len() - buffer.len() % channels).min(len - filled);
+
+            filled += buffer.pop(&mut samples[filled..filled + available]);
+        }
+
+        if filled < len {
+            self.underflows += 1;
+        }
+
+        self.output.extend(samples.into_iter().map(Sample::to_f32));
+    }
+}
+
+/// Playback stream of [`NullBackend`]. Keeps everything, what was played, as f32
+pub struct NullPlaybackStream<F: Format> {
+    state: Arc<Mutex<PlaybackState<F>>>
+}
+
+impl<F: Format> NullPlaybackStream<F> {
+    /// Feeds stream from ring buffer, like [`crate::PlaybackStream::start_buffered`]
+    pub fn start_buffered(self: Pin<&mut Self>, capacity: usize) -> RingProducer<F> {
+        let (producer, consumer) = ring_buffer(capacity);
+
+        lock(&self.state).buffer = Some(consumer);
+
+        producer
+    }
+
+    pub fn rate(&self) -> u32 {
+        lock(&self.state).rate
+    }
+
+    pub fn channel_map(&self) -> ChannelMap {
+        lock(&self.state).channel_map.clone()
+    }
+
+    /// How many times stream had not enough data and was padded with silence
+    pub fn underflow_count(&self) -> u64 {
+        lock(&self.state).underflows
+    }
+
+    /// Ammount of frames, what were played since stream creation
+    pub fn played_frames(&self) -> u64 {
+        lock(&self.state).played_frames
+    }
+
+    /// Copy of everything, what was played. Interleaved
+    pub fn output(&self) -> Vec<f32> {
+        lock(&self.state).output.clone()
+    }
+
+    /// Returns played samples and forgets them
+    pub fn take_output(&self) -> Vec<f32> {
+        core::mem::take(&mut lock(&self.state).output)
+    }
+
+    /// Saves played samples as 32 bit float WAV
+    pub fn save_wav(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let state = lock(&self.state);
+        let spec = hound::WavSpec {
+            channels: state.channel_map.len() as u16,
+            sample_rate: state.rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float
+        };
+
+        let mut writer = hound::WavWriter::create(path, spec).map_err(io::Error::other)?;
+
+        for &sample in &state.output {
+            writer.write_sample(sample).map_err(io::Error::other)?;
+        }
+
+        writer.finalize().map_err(io::Error::other)
+    }
+}
+
+impl<F: Format> StreamWrite<F> for NullPlaybackStream<F> {
+    fn available_len(self: Pin<&Self>) -> Result<usize> {
+        let state = lock(&self.state);
+
+        Ok ( state.queue_capacity - state.queue.len() )
+    }
+
+    fn write(self: Pin<&mut Self>, data: &[F]) -> Result<usize> {
+        let mut state = lock(&self.state);
+        let ammount = (state.queue_capacity - state.queue.len()).min(data.len());
+
+        state.queue.extend(&data[..ammount]);
+
+        Ok ( ammount )
+    }
+}
+
+
+
+struct RecordState<F: Format> {
+    rate: u32,
+    channel_map: ChannelMap,
+
+    start: VirtualTime,
+    recorded_frames: u64,
+
+    // scripted input, what is recorded as clock moves
+    input: VecDeque<F>,
+    // recorded, but not read yet
+    recorded: VecDeque<F>
+}
+
+impl<F: Format> VirtualStream for RecordState<F> {
+    fn advance(&mut self, clock: VirtualTime) {
+        let frames = frames_at(self.start, clock, self.rate);
+        let len = (frames - self.recorded_frames) as usize * self.channel_map.len();
+        let from_input = self.input.len().min(len);
+
+        self.recorded_frames = frames;
+
+        self.recorded.extend(self.input.drain(..from_input));
+        self.recorded.extend(core::iter::repeat_n(F::SILENCE, len - from_input));
+    }
+}
+
+/// Record stream of [`NullBackend`]. Records silence, unless something is fed with [`Self::feed`]
+pub struct NullRecordStream<F: Format> {
+    state: Arc<Mutex<RecordState<F>>>
+}
+
+impl<F: Format> NullRecordStream<F> {
+    /// Adds interleaved samples, what will be recorded next
+    pub fn feed(&self, samples: &[F]) {
+        lock(&self.state).input.extend(samples);
+    }
+
+    pub fn rate(&self) -> u32 {
+        lock(&self.state).rate
+    }
+
+    pub fn channel_map(&self) -> ChannelMap {
+        lock(&self.state).channel_map.clone()
+    }
+
+    /// Ammount of frames, what were recorded since stream creation
+    pub fn recorded_frames(&self) -> u64 {
+        lock(&self.state).recorded_frames
+    }
+}
+
+impl<F: Format> StreamRead<F> for NullRecordStream<F> {
+    fn available_len(self: Pin<&Self>) -> Result<usize> {
+        Ok ( lock(&self.state).recorded.len() )
+    }
+
+    fn read(self: Pin<&mut Self>, data: &mut [F]) -> Result<ReadResult> {
+        let mut state = lock(&self.state);
+        let channels = state.channel_map.len();
+        let ammount = state.recorded.len().min(data.len() - data.len() % channels);
+
+        for (dst, src) in data.iter_mut().zip(state.recorded.drain(..ammount)) {
+            *dst = src;
+        }
+
+        Ok ( ReadResult::Data(ammount) )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{AudioServer, ChannelMap, ChannelPosition, NullBackend, StreamRead, StreamWrite, ReadResult, BufferAttributes};
+    use crate::asset::Sound;
+    use crate::mixer::{Mixer, SampleBuffer};
+
+    fn stereo() -> ChannelMap {
+        ChannelMap::from_positions(&[ChannelPosition::FrontLeft, ChannelPosition::FrontRight])
+    }
+
+    #[test]
+    fn plays_on_virtual_clock() {
+        let server = AudioServer::with_backend(NullBackend::new());
+        let mono = ChannelMap::from_positions(&[ChannelPosition::Mono]);
+        // 8 samples of i16
+        let attributes = BufferAttributes::new(u32::MAX, 16, u32::MAX, u32::MAX, u32::MAX);
+        let mut stream = server.create_stream::<i16>("test", 1000, &mono, Default::default(), None, Some(&attributes)).unwrap();
+
+        assert_eq!(stream.as_ref().available_len().unwrap(), 8);
+        assert_eq!(stream.as_mut().write(&[16384, -16384, 8192, 0, 0, 0, 0, 0, 1]).unwrap(), 8);
+
+        server.backend().advance(Duration::from_millis(3));
+
+        assert_eq!(stream.played_frames(), 3);
+        assert_eq!(stream.output(), [0.5, -0.5, 0.25]);
+        assert_eq!(stream.as_ref().available_len().unwrap(), 3);
+
+        // 5 frames are left, the rest is silence
+        server.backend().advance_frames(7, 1000);
+
+        assert_eq!(stream.take_output().len(), 10);
+        assert_eq!(stream.underflow_count(), 1);
+        assert!(stream.output().is_empty());
+    }
+
+    #[test]
+    fn advances_exact_frames() {
+        let server = AudioServer::with_backend(NullBackend::new());
+        let mono = ChannelMap::from_positions(&[ChannelPosition::Mono]);
+        let stream = server.create_record_stream::<f32>("test", 44100, &mono, Default::default(), None, None).unwrap();
+        let other = server.create_record_stream::<f32>("other", 48000, &mono, Default::default(), None, None).unwrap();
+
+        // one frame is 22675.7 ns, so rounding to nanoseconds would lose frames
+        for _ in 0..44100 {
+            server.backend().advance_frames(1, 44100);
+        }
+
+        assert_eq!(stream.recorded_frames(), 44100);
+        assert_eq!(other.recorded_frames(), 48000);
+        assert_eq!(server.backend().time(), Duration::from_secs(1));
+
+        for _ in 0..3 {
+            server.backend().advance_frames(147, 44100);
+        }
+
+        assert_eq!(stream.recorded_frames(), 44100 + 441);
+        assert_eq!(other.recorded_frames(), 48000 + 480);
+    }
+
+    #[test]
+    fn renders_mixer_to_wav() {
+        let server = AudioServer::with_backend(NullBackend::new());
+        let mut stream = server.create_stream::<f32>("test", 8000, &stereo(), Default::default(), None, None).unwrap();
+        let mut producer = stream.as_mut().start_buffered(1024);
+
+        let mut mixer = Mixer::new(8000, stereo());
+        mixer.play(SampleBuffer::new(vec![0.25, -0.25, 0.5, -0.5], stereo()).source(), Default::default());
+
+        // 64 frames
+        for _ in 0..4 {
+            mixer.fill(&mut producer);
+            server.backend().advance(Duration::from_millis(2));
+        }
+
+        let output = stream.output();
+
+        assert_eq!(output.len(), 128);
+        assert_eq!(&output[..6], &[0.25, -0.25, 0.5, -0.5, 0.0, 0.0]);
+        assert!(output[4..].iter().all(| &s | s == 0.0));
+        assert_eq!(stream.underflow_count(), 0);
+
+        let path = std::env::temp_dir().join(format!("qubicon_null_backend_{}.wav", std::process::id()));
+        stream.save_wav(&path).unwrap();
+
+        let sound = Sound::open(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(sound.rate(), 8000);
+        assert_eq!(sound.samples(), output);
+    }
+
+    #[test]
+    fn records_fed_samples() {
+        let server = AudioServer::with_backend(NullBackend::new());
+        let mut stream = server.create_record_stream::<u8>("test", 100, &stereo(), Default::default(), None, None).unwrap();
+
+        stream.feed(&[1, 2, 3, 4]);
+        server.backend().advance(Duration::from_millis(30));
+
+        assert_eq!(stream.as_ref().available_len().unwrap(), 6);
+
+        let mut buf = [0; 5];
+
+        // only whole frames
+        assert_eq!(stream.as_mut().read(&mut buf).unwrap(), ReadResult::Data(4));
+        assert_eq!(&buf[..4], &[1, 2, 3, 4]);
+        assert_eq!(stream.as_mut().read(&mut buf).unwrap(), ReadResult::Data(2));
+        assert_eq!(&buf[..2], &[0x80, 0x80]);
+    }
+}
@@ -0,0 +1,110 @@
+use std::{ffi::CStr, mem::MaybeUninit, pin::Pin};
+
+use crate::{Result, ChannelMap, Proplist, StreamFlags, BufferAttributes, Format, MainloopMode, PlaybackStream, RecordStream};
+use crate::raw::{self, PulseContext, DeviceInfo, ServerInfo, DeviceEvent};
+use super::Backend;
+
+pub struct PulseBackend {
+    data: Pin<Box<PulseContext>>
+}
+
+impl PulseBackend {
+    /// Connects with name of current executable. See [`crate::AudioServerBuilder`] to set application identity
+    pub fn init(mode: MainloopMode) -> Result<Self> {
+        Self::connect(&crate::builder::default_app_name(), &Proplist::new(), mode, true)
+    }
+
+    /// `properties` describe the client, e.g. [`crate::properties::APPLICATION_ICON_NAME`].
+    /// With `reconnect` lost connection is restored in background, and live streams are recreated
+    pub fn connect(app_name: &str, properties: &Proplist, mode: MainloopMode, reconnect: bool) -> Result<Self> {
+        // assume_init on Box is currently night only
+        #[allow(invalid_value, clippy::uninit_assumed_init)]
+        let mut data = Box::pin(
+            unsafe {
+                MaybeUninit::<PulseContext>::uninit().assume_init()
+            }
+        );
+
+        raw::with_c_string(app_name, | name | unsafe { data.as_mut().init(name, properties, mode, reconnect) })?;
+
+        Ok ( Self { data } )
+    }
+
+    /// False, while connection is lost and backend waits for server to come back
+    pub fn is_connected(&self) -> bool {
+        self.data.is_connected()
+    }
+
+    pub fn sinks(&self) -> Result<Vec<DeviceInfo>> {
+        self.data.sinks()
+    }
+
+    pub fn sources(&self) -> Result<Vec<DeviceInfo>> {
+        self.data.sources()
+    }
+
+    pub fn server_info(&self) -> Result<ServerInfo> {
+        self.data.server_info()
+    }
+
+    /// Callback is called on hot-plug and default device changes.
+    /// In polled mode it is called inside of update, in threaded mode from PulseAudio thread.
+    ///
+    /// Callback should not call [`Self::sinks`], [`Self::sources`] or [`Self::server_info`]:
+    /// they wait for the mainloop, what is busy with the callback, and never return
+    pub fn subscribe_devices(&mut self, callback: impl FnMut(DeviceEvent) + Send + 'static) -> Result<()> {
+        self.data.as_mut().set_device_callback(Some(Box::new(callback)))
+    }
+
+    pub fn unsubscribe_devices(&mut self) -> Result<()> {
+        self.data.as_mut().set_device_callback(None)
+    }
+}
+
+// converts optional device name to C string too
+fn with_c_strings<R>(name: &str, device: Option<&str>, op: impl FnOnce(&CStr, Option<&CStr>) -> R) -> R {
+    raw::with_c_string(name, | name | match device {
+        Some(device) => raw::with_c_string(device, | device | op(name, Some(device))),
+        None => op(name, None)
+    })
+}
+
+impl Backend for PulseBackend {
+    type PlaybackStream<F: Format> = PlaybackStream<F>;
+    type RecordStream<F: Format> = RecordStream<F>;
+
+    fn create_playback_stream<F: Format>(
+        &self,
+        name: &str,
+        device: Option<&str>,
+        rate: u32,
+        channel_map: &ChannelMap,
+        flags: StreamFlags,
+        properties: Option<&Proplist>,
+        buffer_attributes: Option<&BufferAttributes>
+    ) -> Result<Pin<Box<PlaybackStream<F>>>> {
+        with_c_strings(name, device, | name, device | {
+            self.data.create_new_playback_stream(name, device, rate, channel_map, flags, properties, buffer_attributes)
+        })
+    }
+
+    fn create_record_stream<F: Format>(
+        &self,
+        name: &str,
+        device: Option<&str>,
+        rate: u32,
+        channel_map: &ChannelMap,
+        flags: StreamFlags,
+        properties: Option<&Proplist>,
+        buffer_attributes: Option<&BufferAttributes>
+    ) -> Result<Pin<Box<RecordStream<F>>>> {
+        with_c_strings(name, device, | name, device | {
+            self.data.create_new_record_stream(name, device, rate, channel_map, flags, properties, buffer_attributes)
+        })
+    }
+
+    /// Does nothing in threaded mode
+    fn update(&self) -> Result<()> {
+        self.data.update()
+    }
+}
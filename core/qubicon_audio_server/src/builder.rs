@@ -0,0 +1,101 @@
+use crate::{ Result, AudioServer, PulseBackend, MainloopMode, Proplist, properties };
+
+/// Name of current executable, used when application has not set its own
+pub(crate) fn default_app_name() -> String {
+    std::env::current_exe()
+        .ok()
+        .and_then(| path | Some(path.file_stem()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "qubicon".into())
+}
+
+/// Identity of the application, what is shown in volume controls, and connection settings
+pub struct AudioServerBuilder {
+    app_name: String,
+    app_id: Option<String>,
+    version: Option<String>,
+    icon_name: Option<String>,
+    role: Option<String>,
+    properties: Option<Proplist>,
+
+    mode: MainloopMode,
+    reconnect: bool
+}
+
+impl AudioServerBuilder {
+    pub fn new(app_name: &str) -> Self {
+        Self {
+            app_name: app_name.into(),
+            app_id: None,
+            version: None,
+            icon_name: None,
+            role: None,
+            properties: None,
+
+            mode: MainloopMode::default(),
+            reconnect: true
+        }
+    }
+
+    /// Reverse domain name, e.g. "org.example.SpaceGame"
+    pub fn app_id(mut self, id: &str) -> Self {
+        self.app_id = Some(id.into());
+        self
+    }
+
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Name of icon from icon theme, e.g. "applications-games"
+    pub fn icon_name(mut self, icon_name: &str) -> Self {
+        self.icon_name = Some(icon_name.into());
+        self
+    }
+
+    /// Media role of all streams, unless stream sets its own. E.g. "game", "music" or "video"
+    pub fn role(mut self, role: &str) -> Self {
+        self.role = Some(role.into());
+        self
+    }
+
+    /// Any other client properties. Values set by other methods win
+    pub fn properties(mut self, properties: Proplist) -> Self {
+        self.properties = Some(properties);
+        self
+    }
+
+    pub fn mainloop(mut self, mode: MainloopMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Enabled by default. Lost connection is restored in background, and live streams are recreated.
+    /// Streams return errors, while there is no connection
+    pub fn reconnect(mut self, reconnect: bool) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    pub fn build(self) -> Result<AudioServer> {
+        let mut properties = self.properties.unwrap_or_default();
+        let values = [
+            (properties::APPLICATION_ID, &self.app_id),
+            (properties::APPLICATION_VERSION, &self.version),
+            (properties::APPLICATION_ICON_NAME, &self.icon_name),
+            (properties::MEDIA_ROLE, &self.role)
+        ];
+
+        properties.set_string(properties::APPLICATION_NAME, &self.app_name)?;
+
+        for (key, value) in values {
+            if let Some(value) = value {
+                properties.set_string(key, value)?;
+            }
+        }
+
+        let backend = PulseBackend::connect(&self.app_name, &properties, self.mode, self.reconnect)?;
+
+        Ok ( AudioServer::with_backend(backend) )
+    }
+}
@@ -0,0 +1,229 @@
+use std::{ collections::VecDeque, time::Duration };
+
+use crate::ring_buffer::{ ring_buffer, RingProducer, RingConsumer };
+use crate::effect::Effect;
+
+// step size of NLMS. Smaller converges slower, but is less disturbed by near end noise
+const STEP: f32 = 0.5;
+// keeps step sane, when far end is silent
+const REGULARIZATION: f32 = 1e-3;
+// Geigel detector. Near end louder than this part of far end peak means somebody is talking
+const DOUBLE_TALK_RATIO: f32 = 0.5;
+
+/// Removes playback, what microphone picks up from speakers. Made by [`echo_canceller`]
+///
+/// Far end signal comes from [`EchoReference`] in order it was mixed, so microphone hears it only after
+/// playback and record latencies. Filter covers only `tail`, so the latencies should be given to [`Self::set_delay`]
+pub struct EchoCanceller {
+    rate: u32,
+    reference: RingConsumer<f32>,
+    far: Vec<f32>,
+    // far end waits here for its echo to reach microphone
+    delayed: VecDeque<f32>,
+
+    weights: Vec<f32>,
+    // far end history, twice, so window of newest samples is always contiguous
+    history: Vec<f32>,
+    position: usize,
+    energy: f32
+}
+
+/// Creates canceller and tap for playback with `playback_channels`, both at `rate`.
+/// Tap should be the last effect of mixer master bus, e.g. pushed to [`crate::mixer::Mixer::master_effects`].
+/// `tail` is the longest echo, what is cancelled. 100 ms is enough for most rooms
+pub fn echo_canceller(rate: u32, playback_channels: usize, tail: Duration) -> (EchoCanceller, EchoReference) {
+    if playback_channels == 0 {
+        panic!("echo reference should have at least one channel");
+    }
+
+    let taps = ((tail.as_secs_f64() * rate as f64).round() as usize).max(1);
+    let (producer, consumer) = ring_buffer(rate as usize);
+
+    let canceller = EchoCanceller {
+        rate,
+        reference: consumer,
+        far: Vec::new(),
+        delayed: VecDeque::new(),
+
+        weights: vec![0.0; taps],
+        history: vec![0.0; taps * 2],
+        position: 0,
+        energy: 0.0
+    };
+
+    let reference = EchoReference {
+        channels: playback_channels,
+        producer,
+        mono: Vec::new()
+    };
+
+    (canceller, reference)
+}
+
+impl EchoCanceller {
+    /// Cancels echo in mono microphone samples. Takes the same ammount of far end samples,
+    /// missing ones are silence
+    pub fn process(&mut self, samples: &mut [f32]) {
+        let taps = self.weights.len();
+
+        self.far.clear();
+        self.far.resize(samples.len(), 0.0);
+        self.reference.pop(&mut self.far);
+
+        self.delayed.extend(self.far.iter());
+
+        for (far, delayed) in self.far.iter_mut().zip(self.delayed.drain(..samples.len())) {
+            *far = delayed;
+        }
+
+        for (sample, &far) in samples.iter_mut().zip(self.far.iter()) {
+            // newest sample goes before the window, the oldest one drops out of it
+            self.position = match self.position {
+                0 => taps - 1,
+                p => p - 1
+            };
+
+            let oldest = self.history[self.position + taps];
+
+            self.history[self.position] = far;
+            self.history[self.position + taps] = far;
+            self.energy = (self.energy + far * far - oldest * oldest).max(0.0);
+
+            let window = &self.history[self.position..self.position + taps];
+            let echo: f32 = self.weights.iter().zip(window).map(| (w, x) | w * x).sum();
+            let error = *sample - echo;
+
+            let far_peak = window.iter().fold(0.0f32, | p, x | p.max(x.abs()));
+
+            // adapting while near end talks makes filter diverge
+            if sample.abs() < far_peak * DOUBLE_TALK_RATIO {
+                let step = STEP * error / (self.energy + REGULARIZATION);
+
+                for (w, x) in self.weights.iter_mut().zip(window) {
+                    *w += step * x;
+                }
+            }
+
+            *sample = error;
+        }
+    }
+
+    /// Sets time between far end sample being mixed and its echo being read from record stream,
+    /// e.g. sum of playback and record stream latencies. Echo later than `delay` + `tail` is not cancelled
+    pub fn set_delay(&mut self, delay: Duration) {
+        let len = (delay.as_secs_f64() * self.rate as f64).round() as usize;
+
+        // shorter delay drops the oldest far end, longer one waits with silence
+        while self.delayed.len() > len {
+            self.delayed.pop_front();
+        }
+
+        while self.delayed.len() < len {
+            self.delayed.push_front(0.0);
+        }
+    }
+
+    /// Forgets learned echo path and far end, what is waiting. Delay is kept
+    pub fn reset(&mut self) {
+        self.reference.clear();
+        self.delayed.iter_mut().for_each(| s | *s = 0.0);
+        self.weights.fill(0.0);
+        self.history.fill(0.0);
+        self.energy = 0.0;
+    }
+}
+
+/// Passes playback unchanged and sends its mono downmix to [`EchoCanceller`].
+/// If capture is not read, far end is dropped, so reset canceller before reading again
+pub struct EchoReference {
+    channels: usize,
+    producer: RingProducer<f32>,
+    mono: Vec<f32>
+}
+
+impl Effect for EchoReference {
+    fn process(&mut self, samples: &mut [f32]) {
+        self.mono.clear();
+        self.mono.extend(samples.chunks_exact(self.channels).map(| f | f.iter().sum::<f32>() / self.channels as f32));
+
+        self.producer.push(&self.mono);
+    }
+
+    fn reset(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rand::{ Rng, SeedableRng, rngs::StdRng };
+
+    use crate::effect::Effect;
+    use super::echo_canceller;
+
+    fn energy(samples: &[f32]) -> f32 {
+        samples.iter().map(| s | s * s).sum()
+    }
+
+    #[test]
+    fn cancels_delayed_echo() {
+        let (mut canceller, mut reference) = echo_canceller(8000, 2, Duration::from_millis(16));
+        let mut rng = StdRng::seed_from_u64(7);
+
+        let far: Vec<f32> = (0..16000).map(| _ | rng.gen_range(-0.5..0.5)).collect();
+        // room returns far end 10 frames later, at half level, with a weak reflection
+        let mic: Vec<f32> = (0..16000usize)
+            .map(| i | 0.5 * far.get(i.wrapping_sub(10)).unwrap_or(&0.0) + 0.1 * far.get(i.wrapping_sub(40)).unwrap_or(&0.0))
+            .collect();
+
+        let mut output = Vec::new();
+
+        for (far, mic) in far.chunks(160).zip(mic.chunks(160)) {
+            // same on both playback channels
+            let mut playback: Vec<f32> = far.iter().flat_map(| &s | [s, s]).collect();
+            let mut mic = mic.to_vec();
+
+            reference.process(&mut playback);
+            canceller.process(&mut mic);
+            output.extend(mic);
+        }
+
+        // the last second has echo reduced by more than 30 dB
+        assert!(energy(&output[8000..]) < energy(&mic[8000..]) * 1e-3);
+
+        // forgotten echo path and no far end, so nothing is removed
+        canceller.reset();
+
+        let mut near = mic[..160].to_vec();
+        canceller.process(&mut near);
+
+        assert_eq!(near, &mic[..160]);
+    }
+
+    #[test]
+    fn cancels_echo_longer_than_tail_with_delay() {
+        let (mut canceller, mut reference) = echo_canceller(8000, 1, Duration::from_millis(16));
+        let mut rng = StdRng::seed_from_u64(11);
+
+        // echo comes 60 ms late, filter covers only 16 ms of it
+        let far: Vec<f32> = (0..16000).map(| _ | rng.gen_range(-0.5..0.5)).collect();
+        let mic: Vec<f32> = (0..16000usize)
+            .map(| i | 0.5 * far.get(i.wrapping_sub(480)).unwrap_or(&0.0))
+            .collect();
+
+        canceller.set_delay(Duration::from_millis(50));
+
+        let mut output = Vec::new();
+
+        for (far, mic) in far.chunks(160).zip(mic.chunks(160)) {
+            let mut playback = far.to_vec();
+            let mut mic = mic.to_vec();
+
+            reference.process(&mut playback);
+            canceller.process(&mut mic);
+            output.extend(mic);
+        }
+
+        assert!(energy(&output[8000..]) < energy(&mic[8000..]) * 1e-3);
+    }
+}
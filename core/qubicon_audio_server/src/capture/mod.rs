@@ -0,0 +1,358 @@
+//! Capture pipeline for voice chat. [`Capture`] reads record stream, downmixes it to mono
+//! and cuts it into frames of fixed duration, what can go straight to Opus encoder
+
+mod vad;
+mod echo;
+
+use std::{ collections::VecDeque, pin::Pin, time::Duration };
+
+use libpulse_sys::PA_CHANNELS_MAX;
+
+use crate::{ Result, Format, StreamRead, ReadResult };
+use crate::effect::{ Effect, NoiseGate, gain_to_db };
+use crate::sample::{ convert_to_f32, convert_from_f32 };
+
+pub use vad::VoiceDetector;
+pub use echo::{ echo_canceller, EchoCanceller, EchoReference };
+
+// about 2 seconds of 20 ms frames. Older frames are dropped, if nobody takes them
+const MAX_QUEUED_FRAMES: usize = 100;
+// samples read from stream at once. Cut down to whole frames, so it should be at least PA_CHANNELS_MAX
+const READ_CHUNK: usize = 960;
+
+/// Frame durations, what Opus can encode and what are good for voice
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameDuration {
+    Ms10,
+    #[default]
+    Ms20
+}
+
+impl FrameDuration {
+    pub fn duration(self) -> Duration {
+        match self {
+            Self::Ms10 => Duration::from_millis(10),
+            Self::Ms20 => Duration::from_millis(20)
+        }
+    }
+
+    /// Ammount of frames at `rate`. E.g. 960 for 20 ms at 48 kHz
+    pub fn frames(self, rate: u32) -> usize {
+        (rate as u64 * self.duration().as_millis() as u64 / 1000) as usize
+    }
+}
+
+/// One frame of mono audio
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureFrame {
+    pub samples: Vec<f32>,
+    /// Decision of [`VoiceDetector`]. Frames without voice can be skipped or sent as DTX
+    pub voice: bool,
+    /// RMS level before noise gate
+    pub level_db: f32
+}
+
+impl CaptureFrame {
+    /// Converts samples for encoders, what take 16 bit input. `out` should be as long as frame
+    pub fn to_i16(&self, out: &mut [i16]) {
+        convert_from_f32(&self.samples, out, None);
+    }
+}
+
+/// Turns interleaved record stream into mono [`CaptureFrame`]s.
+/// Echo is cancelled first, then voice is detected and noise gate is applied last
+pub struct Capture {
+    rate: u32,
+    channels: usize,
+    duration: FrameDuration,
+    frame_len: usize,
+
+    echo: Option<EchoCanceller>,
+    vad: VoiceDetector,
+    gate: Option<NoiseGate>,
+
+    pending: Vec<f32>,
+    frames: VecDeque<CaptureFrame>,
+    scratch: Vec<f32>
+}
+
+impl Capture {
+    /// `channels` of input. Frames are always mono
+    pub fn new(rate: u32, channels: usize, duration: FrameDuration) -> Self {
+        if channels == 0 || channels > PA_CHANNELS_MAX as usize {
+            panic!("capture should have from 1 to {PA_CHANNELS_MAX} channels");
+        }
+
+        let frame_len = duration.frames(rate);
+
+        Self {
+            rate,
+            channels,
+            duration,
+            frame_len,
+
+            echo: None,
+            vad: VoiceDetector::new(duration.duration()),
+            gate: None,
+
+            pending: Vec::with_capacity(frame_len),
+            frames: VecDeque::new(),
+            scratch: Vec::new()
+        }
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    pub fn frame_duration(&self) -> FrameDuration {
+        self.duration
+    }
+
+    /// Ammount of samples in every frame
+    pub fn frame_len(&self) -> usize {
+        self.frame_len
+    }
+
+    /// Gate works on mono signal, so make it with one channel at capture rate
+    pub fn set_noise_gate(&mut self, gate: Option<NoiseGate>) {
+        self.gate = gate;
+    }
+
+    pub fn noise_gate(&mut self) -> Option<&mut NoiseGate> {
+        self.gate.as_mut()
+    }
+
+    /// Canceller should be made at capture rate
+    pub fn set_echo_canceller(&mut self, echo: Option<EchoCanceller>) {
+        self.echo = echo;
+    }
+
+    pub fn echo_canceller(&mut self) -> Option<&mut EchoCanceller> {
+        self.echo.as_mut()
+    }
+
+    pub fn voice_detector(&mut self) -> &mut VoiceDetector {
+        &mut self.vad
+    }
+
+    /// Takes interleaved frames. Incomplete frame at the end is ignored
+    pub fn push(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            self.pending.push(frame.iter().sum::<f32>() / self.channels as f32);
+
+            if self.pending.len() == self.frame_len {
+                self.finish_frame();
+            }
+        }
+    }
+
+    /// Reads everything, what is available in record stream. Holes are skipped.
+    /// Returns ammount of read samples
+    pub fn read_stream<F: Format, S: StreamRead<F> + ?Sized>(&mut self, mut stream: Pin<&mut S>) -> Result<usize> {
+        let mut total = 0;
+        // on stack, so reading does not allocate for any format
+        let mut buffer = [F::SILENCE; READ_CHUNK];
+        // streams hand out whole frames anyway, but partial one would be lost in push
+        let chunk = READ_CHUNK - READ_CHUNK % self.channels;
+
+        loop {
+            let len = stream.as_ref().available_len()?.min(chunk);
+
+            if len == 0 {
+                return Ok ( total );
+            }
+
+            let result = stream.as_mut().read(&mut buffer[..len]);
+
+            if let Ok(ReadResult::Data(ammount)) = result {
+                let mut scratch = core::mem::take(&mut self.scratch);
+
+                scratch.resize(ammount, 0.0);
+                convert_to_f32(&buffer[..ammount], &mut scratch);
+                self.push(&scratch);
+
+                self.scratch = scratch;
+                total += ammount;
+            }
+
+            match result? {
+                ReadResult::Data(0) => return Ok ( total ),
+                _ => continue
+            }
+        }
+    }
+
+    /// The oldest finished frame
+    pub fn pop_frame(&mut self) -> Option<CaptureFrame> {
+        self.frames.pop_front()
+    }
+
+    pub fn queued_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Drops unfinished and queued frames and resets all processing
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.frames.clear();
+        self.vad.reset();
+
+        if let Some(echo) = self.echo.as_mut() {
+            echo.reset();
+        }
+
+        if let Some(gate) = self.gate.as_mut() {
+            gate.reset();
+        }
+    }
+
+    fn finish_frame(&mut self) {
+        let mut samples = core::mem::replace(&mut self.pending, Vec::with_capacity(self.frame_len));
+
+        if let Some(echo) = self.echo.as_mut() {
+            echo.process(&mut samples);
+        }
+
+        let square_sum: f32 = samples.iter().map(| s | s * s).sum();
+        let level_db = gain_to_db((square_sum / samples.len() as f32).sqrt());
+        let voice = self.vad.process(level_db);
+
+        if let Some(gate) = self.gate.as_mut() {
+            gate.process(&mut samples);
+        }
+
+        if self.frames.len() == MAX_QUEUED_FRAMES {
+            self.frames.pop_front();
+        }
+
+        self.frames.push_back(CaptureFrame { samples, voice, level_db });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{ AudioServer, ChannelMap, ChannelPosition, NullBackend, StreamFlags };
+    use crate::effect::NoiseGate;
+    use super::{ Capture, FrameDuration, VoiceDetector };
+
+    fn tone(len: usize, amplitude: f32) -> impl Iterator<Item = f32> {
+        (0..len).map(move | i | (i as f32 * 0.3).sin() * amplitude)
+    }
+
+    #[test]
+    fn fixed_frames() {
+        assert_eq!(FrameDuration::Ms10.frames(48000), 480);
+        assert_eq!(FrameDuration::Ms20.frames(16000), 320);
+
+        let mut capture = Capture::new(8000, 2, FrameDuration::Ms10);
+        // left and right are averaged
+        let samples: Vec<f32> = (0..500).flat_map(| i | [i as f32, i as f32 + 1.0]).collect();
+
+        // chunks, what split capture frames
+        for chunk in samples.chunks(37 * 2) {
+            capture.push(chunk);
+        }
+
+        assert_eq!(capture.queued_frames(), 6);
+
+        let frame = capture.pop_frame().unwrap();
+        assert_eq!(frame.samples.len(), 80);
+        assert_eq!(frame.samples[..3], [0.5, 1.5, 2.5]);
+
+        let frame = capture.pop_frame().unwrap();
+        assert_eq!(frame.samples[0], 80.5);
+
+        capture.reset();
+        assert!(capture.pop_frame().is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn no_channels() {
+        Capture::new(8000, 0, FrameDuration::Ms10);
+    }
+
+    #[test]
+    fn voice_activity() {
+        let mut vad = VoiceDetector::new(Duration::from_millis(20));
+
+        vad.set_hangover(Duration::from_millis(40));
+
+        // steady noise becomes the floor
+        for _ in 0..50 {
+            assert!(!vad.process(-50.0));
+        }
+
+        assert!(vad.process(-20.0));
+        // two frames of hangover
+        assert!(vad.process(-50.0));
+        assert!(vad.process(-50.0));
+        assert!(!vad.process(-50.0));
+
+        // too quiet, however quiet the floor is
+        vad.reset();
+        vad.process(-90.0);
+        assert!(!vad.process(-70.0));
+    }
+
+    #[test]
+    fn gated_voice_frames() {
+        let mut capture = Capture::new(8000, 1, FrameDuration::Ms20);
+
+        capture.set_noise_gate(Some(NoiseGate::new(8000, 1, -30.0)));
+        capture.voice_detector().set_hangover(Duration::ZERO);
+
+        let noise = tone(160 * 10, 0.003);
+        let voice = tone(160 * 5, 0.3);
+
+        capture.push(&noise.chain(voice).collect::<Vec<_>>());
+
+        let frames: Vec<_> = core::iter::from_fn(|| capture.pop_frame()).collect();
+
+        assert_eq!(frames.len(), 15);
+        assert!(frames[..10].iter().all(| f | !f.voice && f.samples.iter().all(| &s | s == 0.0)));
+        assert!(frames[10..].iter().all(| f | f.voice));
+        assert!((frames[12].level_db - (0.3 * core::f32::consts::FRAC_1_SQRT_2).log10() * 20.0).abs() < 0.5);
+        assert!(frames[12].samples.iter().any(| &s | s.abs() > 0.25));
+
+        let mut out = [0i16; 160];
+        frames[12].to_i16(&mut out);
+        assert!(out.iter().any(| &s | s > 8000));
+    }
+
+    #[test]
+    fn reads_record_stream() {
+        let server = AudioServer::with_backend(NullBackend::new());
+        let map = ChannelMap::from_positions(&[ChannelPosition::FrontLeft, ChannelPosition::FrontRight]);
+        let mut stream = server.create_record_stream::<i16>("mic", 1000, &map, StreamFlags::empty(), None, None).unwrap();
+        let mut capture = Capture::new(1000, 2, FrameDuration::Ms10);
+
+        stream.feed(&[i16::MAX / 2; 2 * 25]);
+        server.backend().advance(Duration::from_millis(25));
+
+        assert_eq!(capture.read_stream(stream.as_mut()).unwrap(), 50);
+        assert_eq!(capture.queued_frames(), 2);
+        assert!((capture.pop_frame().unwrap().samples[0] - 0.5).abs() < 1e-3);
+
+        // more than one read chunk
+        server.backend().advance(Duration::from_secs(1));
+
+        assert_eq!(capture.read_stream(stream.as_mut()).unwrap(), 2000);
+
+        // 960 is not a multiple of 7 channels
+        let map = ChannelMap::from_positions(&[
+            ChannelPosition::FrontLeft, ChannelPosition::FrontRight, ChannelPosition::FrontCenter, ChannelPosition::Lfe,
+            ChannelPosition::RearLeft, ChannelPosition::RearRight, ChannelPosition::RearCenter
+        ]);
+        let mut stream = server.create_record_stream::<i16>("surround", 1000, &map, StreamFlags::empty(), None, None).unwrap();
+        let mut capture = Capture::new(1000, 7, FrameDuration::Ms10);
+
+        server.backend().advance(Duration::from_millis(500));
+
+        assert_eq!(capture.read_stream(stream.as_mut()).unwrap(), 3500);
+        assert_eq!(capture.queued_frames(), 50);
+    }
+}
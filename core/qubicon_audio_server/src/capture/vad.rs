@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+/// Energy based voice activity detector. Follows noise floor of the room and reports voice,
+/// when frame is louder than the floor by margin. Works on levels of whole frames
+pub struct VoiceDetector {
+    frame: Duration,
+
+    margin_db: f32,
+    min_level_db: f32,
+    // how fast floor follows louder signal. Falls to quieter signal at once
+    rise_db: f32,
+    hangover: usize,
+
+    noise_floor_db: Option<f32>,
+    hangover_left: usize,
+    active: bool
+}
+
+impl VoiceDetector {
+    /// Starts with 10 dB margin, -60 dB minimum level, 2 dB/s floor rise and 200 ms hangover
+    pub fn new(frame: Duration) -> Self {
+        let mut detector = Self {
+            frame,
+
+            margin_db: 10.0,
+            min_level_db: -60.0,
+            rise_db: 0.0,
+            hangover: 0,
+
+            noise_floor_db: None,
+            hangover_left: 0,
+            active: false
+        };
+
+        detector.set_floor_rise(2.0);
+        detector.set_hangover(Duration::from_millis(200));
+        detector
+    }
+
+    /// How much louder than noise floor voice should be
+    pub fn set_margin(&mut self, margin_db: f32) {
+        self.margin_db = margin_db.max(0.0);
+    }
+
+    /// Frames below this level are never voice, however quiet the room is
+    pub fn set_min_level(&mut self, min_level_db: f32) {
+        self.min_level_db = min_level_db;
+    }
+
+    /// Speed of noise floor rise in dB per second. Too fast rise takes long words for noise
+    pub fn set_floor_rise(&mut self, db_per_second: f32) {
+        self.rise_db = db_per_second.max(0.0) * self.frame.as_secs_f32();
+    }
+
+    /// Voice is reported for this long after it stopped, so pauses between words are kept
+    pub fn set_hangover(&mut self, hangover: Duration) {
+        self.hangover = (hangover.as_secs_f32() / self.frame.as_secs_f32()).round() as usize;
+    }
+
+    /// None before the first frame
+    pub fn noise_floor_db(&self) -> Option<f32> {
+        self.noise_floor_db
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Takes RMS level of next frame and returns true, if it is voice
+    pub fn process(&mut self, level_db: f32) -> bool {
+        let floor = match self.noise_floor_db {
+            Some(floor) if level_db > floor => floor + self.rise_db.min(level_db - floor),
+            _ => level_db
+        };
+
+        self.noise_floor_db = Some(floor);
+
+        let voice = level_db >= self.min_level_db && level_db >= floor + self.margin_db;
+
+        self.active = match (voice, self.hangover_left) {
+            (true, _) => {
+                self.hangover_left = self.hangover;
+                true
+            },
+            (false, 0) => false,
+            (false, _) => {
+                self.hangover_left -= 1;
+                true
+            }
+        };
+
+        self.active
+    }
+
+    pub fn reset(&mut self) {
+        self.noise_floor_db = None;
+        self.hangover_left = 0;
+        self.active = false;
+    }
+}
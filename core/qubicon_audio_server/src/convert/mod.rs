@@ -0,0 +1,275 @@
+//! Sample rate conversion and channel remixing of interleaved f32 frames.
+//! Lets sounds with any rate and layout play through one output
+
+use crate::ChannelMap;
+use crate::mixer::Source;
+
+pub use resampler::{Resampler, ResamplerQuality};
+pub use remix::Remixer;
+pub(crate) use remix::{horizontal_position, remix_matrix};
+
+mod resampler;
+mod remix;
+
+// ammount of frames, what is read from inner source at once
+const SOURCE_CHUNK_FRAMES: usize = 1024;
+
+/// Mixer source, what resamples another source to mixer rate
+pub struct Resampled<S: Source> {
+    source: S,
+    resampler: Resampler,
+
+    // reused buffer for frames of inner source
+    input: Vec<f32>,
+    // resampled, but not read yet samples
+    pending: Vec<f32>,
+    position: usize,
+
+    ended: bool
+}
+
+impl<S: Source> Resampled<S> {
+    pub fn new(source: S, from: u32, to: u32, quality: ResamplerQuality) -> Self {
+        let channels = source.channel_map().len();
+
+        Self {
+            source,
+            resampler: Resampler::new(from, to, channels, quality),
+
+            input: Vec::new(),
+            pending: Vec::new(),
+            position: 0,
+
+            ended: false
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.source
+    }
+
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<S: Source> Source for Resampled<S> {
+    fn channel_map(&self) -> &ChannelMap {
+        self.source.channel_map()
+    }
+
+    fn read(&mut self, out: &mut [f32]) -> usize {
+        let mut total = 0;
+
+        loop {
+            let remaining = &self.pending[self.position..];
+            let ammount = remaining.len().min(out.len() - total);
+
+            out[total..total + ammount].copy_from_slice(&remaining[..ammount]);
+            self.position += ammount;
+            total += ammount;
+
+            if total == out.len() || self.ended {
+                break;
+            }
+
+            self.pending.clear();
+            self.position = 0;
+
+            self.input.resize(SOURCE_CHUNK_FRAMES * self.resampler.channels(), 0.0);
+            let read = self.source.read(&mut self.input);
+
+            self.resampler.process(&self.input[..read], &mut self.pending);
+
+            if read < self.input.len() {
+                self.resampler.flush(&mut self.pending);
+                self.ended = true;
+            }
+        }
+
+        total
+    }
+
+    fn rewind(&mut self) -> bool {
+        if !self.source.rewind() {
+            return false;
+        }
+
+        self.resampler.reset();
+        self.pending.clear();
+        self.position = 0;
+        self.ended = false;
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::{PI, FRAC_1_SQRT_2};
+
+    use crate::{ChannelMap, ChannelPosition};
+    use crate::mixer::{Mixer, SampleBuffer, Source};
+    use super::{Resampler, ResamplerQuality, Remixer, Resampled};
+
+    fn map(positions: &[ChannelPosition]) -> ChannelMap {
+        ChannelMap::from_positions(positions)
+    }
+
+    fn sine(freq: f32, rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames).map(| i | (2.0 * PI * freq * i as f32 / rate as f32).sin()).collect()
+    }
+
+    fn resample_all(resampler: &mut Resampler, input: &[f32]) -> Vec<f32> {
+        let mut output = Vec::new();
+
+        resampler.process(input, &mut output);
+        resampler.flush(&mut output);
+
+        output
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(| s | s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn resampled_sine() {
+        for (quality, tolerance) in [(ResamplerQuality::Linear, 2e-2), (ResamplerQuality::Sinc, 1e-3)] {
+            let input = sine(1000.0, 44100, 4410);
+            let mut resampler = Resampler::new(44100, 48000, 1, quality);
+            let output = resample_all(&mut resampler, &input);
+            let expected = sine(1000.0, 48000, 4800);
+
+            assert_eq!(output.len(), 4800);
+
+            // edges are filtered together with silence around the sound
+            for (i, (a, b)) in output.iter().zip(expected.iter()).enumerate().skip(100).take(4600) {
+                assert!((a - b).abs() < tolerance, "{quality:?}, frame {i}: {a} != {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn downsampling_filters_aliases() {
+        // above nyquist of output rate
+        let input = sine(15000.0, 48000, 4800);
+        let mut resampler = Resampler::new(48000, 22050, 1, ResamplerQuality::Sinc);
+        let output = resample_all(&mut resampler, &input);
+
+        assert_eq!(output.len(), 2205);
+        assert!(rms(&output[100..2100]) < 1e-2);
+    }
+
+    #[test]
+    fn chunked_input() {
+        let left = sine(440.0, 22050, 2000);
+        let right = sine(3000.0, 22050, 2000);
+        let input: Vec<f32> = left.iter().zip(right.iter()).flat_map(| (&l, &r) | [l, r]).collect();
+
+        let mut resampler = Resampler::new(22050, 48000, 2, ResamplerQuality::Sinc);
+        let whole = resample_all(&mut resampler, &input);
+
+        let mut chunked = Vec::new();
+        for chunk in input.chunks(2 * 37) {
+            resampler.process(chunk, &mut chunked);
+        }
+        resampler.flush(&mut chunked);
+
+        assert_eq!(whole.len(), 2 * 4354);
+        assert_eq!(whole, chunked);
+    }
+
+    #[test]
+    fn surround_downmix() {
+        use libpulse_sys::pa_channel_position_t::*;
+
+        let remixer = Remixer::new(
+            &map(&[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight]),
+            &map(&[FrontLeft, FrontRight])
+        );
+
+        let expected = [
+            [1.0, 0.0],
+            [0.0, 1.0],
+            [FRAC_1_SQRT_2, FRAC_1_SQRT_2],
+            [0.0, 0.0],
+            [FRAC_1_SQRT_2, 0.0],
+            [0.0, FRAC_1_SQRT_2]
+        ];
+
+        for (i, gains) in expected.iter().enumerate() {
+            for (o, &gain) in gains.iter().enumerate() {
+                assert!((remixer.gain(i, o) - gain).abs() < 1e-6, "{i} -> {o}");
+            }
+        }
+
+        let mut out = [0.0; 2];
+        remixer.process(&[0.1, 0.2, 0.3, 1.0, 0.4, 0.5], &mut out);
+
+        assert!((out[0] - (0.1 + (0.3 + 0.4) * FRAC_1_SQRT_2)).abs() < 1e-6);
+        assert!((out[1] - (0.2 + (0.3 + 0.5) * FRAC_1_SQRT_2)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn simple_layouts() {
+        use libpulse_sys::pa_channel_position_t::*;
+
+        let stereo = map(&[FrontLeft, FrontRight]);
+        let mono = map(&[Mono]);
+
+        let up = Remixer::new(&mono, &stereo);
+        assert_eq!((up.gain(0, 0), up.gain(0, 1)), (1.0, 1.0));
+
+        let down = Remixer::new(&stereo, &mono);
+        assert_eq!((down.gain(0, 0), down.gain(1, 0)), (0.5, 0.5));
+
+        // upmix keeps fronts and leaves the rest silent
+        let surround = map(&[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight]);
+        let up = Remixer::new(&stereo, &surround);
+        let mut out = [1.0; 6];
+
+        up.process(&[0.25, -0.25], &mut out);
+        assert_eq!(out, [0.25, -0.25, 0.0, 0.0, 0.0, 0.0]);
+
+        // 7.1 sides move to rears of 5.1
+        let wide = map(&[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight, SideLeft, SideRight]);
+        let down = Remixer::new(&wide, &surround);
+
+        assert_eq!(down.gain(6, 4), 1.0);
+        assert_eq!(down.gain(7, 5), 1.0);
+        assert_eq!(down.gain(6, 0), 0.0);
+    }
+
+    #[test]
+    fn different_rates_in_one_mixer() {
+        use libpulse_sys::pa_channel_position_t::*;
+
+        let stereo = map(&[FrontLeft, FrontRight]);
+        let mut mixer = Mixer::new(48000, stereo.clone());
+
+        // 0.1 s each
+        let effect = SampleBuffer::new(vec![0.5; 2205], map(&[Mono]));
+        let music = SampleBuffer::new(vec![0.25; 9600], stereo);
+
+        let mut effect = Resampled::new(effect.source(), 22050, 48000, ResamplerQuality::Sinc);
+        let mut out = vec![0.0; 9600];
+
+        assert_eq!(effect.read(&mut out), 4800);
+        assert!(effect.rewind());
+
+        mixer.play(effect, Default::default());
+        mixer.play_resampled(music.source(), 48000, Default::default());
+
+        let mut out = vec![0.0; 9600 + 2];
+        mixer.mix(&mut out);
+
+        // middle of sound, where filter sees no edges. Mono voice is panned to center
+        let expected = 0.25 + 0.5 * FRAC_1_SQRT_2;
+
+        assert!((out[2400 * 2] - expected).abs() < 1e-3);
+        assert!((out[2400 * 2 + 1] - expected).abs() < 1e-3);
+        assert_eq!(&out[9600..], &[0.0, 0.0]);
+        assert_eq!(mixer.voice_count(), 0);
+    }
+}
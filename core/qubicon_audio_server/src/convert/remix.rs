@@ -0,0 +1,215 @@
+use crate::{ChannelMap, ChannelPosition};
+
+// -3 dB
+const HALF_POWER: f32 = core::f32::consts::FRAC_1_SQRT_2;
+
+/// Horizontal placement of speaker. -1 is left, 1 is right.
+/// None for channels without direction, like LFE or AUX
+pub(crate) fn horizontal_position(position: ChannelPosition) -> Option<f32> {
+    use libpulse_sys::pa_channel_position_t::*;
+
+    match position {
+        Mono | FrontCenter | RearCenter | TopCenter | TopFrontCenter | TopRearCenter => Some(0.0),
+        FrontLeft | RearLeft | SideLeft | TopFrontLeft | TopRearLeft => Some(-1.0),
+        FrontRight | RearRight | SideRight | TopFrontRight | TopRearRight => Some(1.0),
+        FrontLeftOfCenter => Some(-0.5),
+        FrontRightOfCenter => Some(0.5),
+        _ => None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Group {
+    Front,
+    Side,
+    Rear,
+    Top
+}
+
+fn group(position: ChannelPosition) -> Option<Group> {
+    use libpulse_sys::pa_channel_position_t::*;
+
+    match position {
+        FrontLeft | FrontRight | FrontCenter | FrontLeftOfCenter | FrontRightOfCenter => Some(Group::Front),
+        SideLeft | SideRight => Some(Group::Side),
+        RearLeft | RearRight | RearCenter => Some(Group::Rear),
+        TopCenter | TopFrontLeft | TopFrontRight | TopFrontCenter | TopRearLeft | TopRearRight | TopRearCenter => Some(Group::Top),
+        _ => None
+    }
+}
+
+// where channel goes, if there is no such speaker. Gain is applied on top of usual panning
+fn fallbacks(group: Group) -> &'static [(Group, f32)] {
+    match group {
+        Group::Front => &[(Group::Front, 1.0)],
+        Group::Side => &[(Group::Side, 1.0), (Group::Rear, 1.0), (Group::Front, HALF_POWER)],
+        Group::Rear => &[(Group::Rear, 1.0), (Group::Side, 1.0), (Group::Front, HALF_POWER)],
+        Group::Top => &[(Group::Top, 1.0), (Group::Front, HALF_POWER)]
+    }
+}
+
+// -1 for left, 0 for center, 1 for right
+fn side(x: f32) -> i8 {
+    if x < 0.0 { -1 } else if x > 0.0 { 1 } else { 0 }
+}
+
+// nearest speaker of the group to `x`, what is on the same side
+fn nearest(output: &ChannelMap, group: Group, x: f32) -> Option<usize> {
+    output.iter()
+        .enumerate()
+        .filter(| (_, &p) | self::group(p) == Some(group))
+        .filter_map(| (i, &p) | horizontal_position(p).map(| px | (i, px)))
+        .filter(| &(_, px) | side(px) == side(x))
+        .min_by(| a, b | (a.1 - x).abs().total_cmp(&(b.1 - x).abs()))
+        .map(| (i, _) | i)
+}
+
+// tries to place channel at `x` into speakers of `group`
+fn place(output: &ChannelMap, group: Group, x: f32, gain: f32, gains: &mut [f32]) -> bool {
+    if let Some(o) = nearest(output, group, x) {
+        gains[o] += gain;
+
+        return true;
+    }
+
+    if x == 0.0 {
+        // phantom center
+        if let (Some(l), Some(r)) = (nearest(output, group, -1.0), nearest(output, group, 1.0)) {
+            gains[l] += gain * HALF_POWER;
+            gains[r] += gain * HALF_POWER;
+
+            return true;
+        }
+    } else if let Some(c) = nearest(output, group, 0.0) {
+        gains[c] += gain * HALF_POWER;
+
+        return true;
+    }
+
+    false
+}
+
+// output has nothing, but a mono speaker
+fn is_mono_only(output: &ChannelMap) -> bool {
+    output.contains(&ChannelPosition::Mono) && !output.iter().any(| &p | group(p).is_some())
+}
+
+/// Builds input-major remix matrix with ITU-R BS.775 style coefficients.
+/// Center and surrounds are folded into fronts at -3 dB, LFE is dropped, if there is no LFE speaker.
+/// Upmixing does not invent channels, missing ones are left silent.
+/// Result is not normalized, so downmix of loud multichannel audio can clip
+pub(crate) fn remix_matrix(input: &ChannelMap, output: &ChannelMap) -> Vec<f32> {
+    use libpulse_sys::pa_channel_position_t::*;
+
+    if is_mono_only(output) {
+        // downmix to stereo first, then average
+        let stereo = ChannelMap::from_positions(&[FrontLeft, FrontRight]);
+        let to_stereo = remix_matrix(input, &stereo);
+        let mono = output.iter().position(| &p | p == Mono).unwrap();
+        let mut matrix = vec![0.0; input.len() * output.len()];
+
+        for i in 0..input.len() {
+            let gain = match input[i] {
+                // don't lose level on mono -> mono
+                Mono => 1.0,
+                _ => (to_stereo[i * 2] + to_stereo[i * 2 + 1]) * 0.5
+            };
+
+            matrix[i * output.len() + mono] = gain;
+        }
+
+        return matrix;
+    }
+
+    let mut matrix = vec![0.0; input.len() * output.len()];
+
+    for (i, &position) in input.iter().enumerate() {
+        let gains = &mut matrix[i * output.len()..(i + 1) * output.len()];
+
+        if let Some(o) = output.iter().position(| &p | p == position) {
+            gains[o] = 1.0;
+
+            continue;
+        }
+
+        match position {
+            // mono is copied to both fronts, as PulseAudio does
+            Mono => {
+                if let Some(c) = output.iter().position(| &p | p == FrontCenter) {
+                    gains[c] = 1.0;
+                } else {
+                    for (o, &p) in output.iter().enumerate() {
+                        if matches!(p, FrontLeft | FrontRight) {
+                            gains[o] = 1.0;
+                        }
+                    }
+                }
+            },
+            // LFE and AUX channels without a pair have nowhere to go
+            _ => if let (Some(group), Some(x)) = (group(position), horizontal_position(position)) {
+                for &(fallback, gain) in fallbacks(group) {
+                    if place(output, fallback, x, gain, gains) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    matrix
+}
+
+/// Converts interleaved frames from one channel layout to another
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remixer {
+    inputs: usize,
+    outputs: usize,
+    // input-major
+    matrix: Vec<f32>
+}
+
+impl Remixer {
+    pub fn new(input: &ChannelMap, output: &ChannelMap) -> Self {
+        if input.is_empty() || output.is_empty() {
+            panic!("channel map should contain at least one channel");
+        }
+
+        Self {
+            inputs: input.len(),
+            outputs: output.len(),
+            matrix: remix_matrix(input, output)
+        }
+    }
+
+    pub fn input_channels(&self) -> usize {
+        self.inputs
+    }
+
+    pub fn output_channels(&self) -> usize {
+        self.outputs
+    }
+
+    /// Gain from input channel to output channel
+    pub fn gain(&self, input: usize, output: usize) -> f32 {
+        self.matrix[input * self.outputs + output]
+    }
+
+    /// Remixes all frames of `input` into `output`. Output should have place for the same ammount of frames
+    pub fn process(&self, input: &[f32], output: &mut [f32]) {
+        let frames = input.len() / self.inputs;
+
+        if output.len() != frames * self.outputs {
+            panic!("output has place for {} samples, but {} is required", output.len(), frames * self.outputs);
+        }
+
+        for (in_frame, out_frame) in input.chunks_exact(self.inputs).zip(output.chunks_exact_mut(self.outputs)) {
+            out_frame.fill(0.0);
+
+            for (&sample, gains) in in_frame.iter().zip(self.matrix.chunks_exact(self.outputs)) {
+                for (out, &gain) in out_frame.iter_mut().zip(gains) {
+                    *out += sample * gain;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use core::f64::consts::PI;
+
+// zero crossings of sinc on each side at full bandwidth
+const SINC_HALF_TAPS: usize = 16;
+// kernel phases, stored in table. Values between them are interpolated
+const SINC_PHASES: usize = 256;
+// part of the band, what is kept. The rest is transition band of the filter
+const SINC_ROLLOFF: f64 = 0.95;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResamplerQuality {
+    /// Linear interpolation. Cheap, but aliases and dulls high frequencies
+    Linear,
+    /// Blackman windowed sinc. Low-pass filters when downsampling
+    #[default]
+    Sinc
+}
+
+#[derive(Debug, Clone)]
+enum Kernel {
+    Linear,
+    Sinc {
+        // taps on each side of position
+        half: usize,
+        // (SINC_PHASES + 1) rows of `half * 2` taps
+        table: Vec<f32>
+    }
+}
+
+impl Kernel {
+    fn new(quality: ResamplerQuality, step: f64) -> Self {
+        match quality {
+            ResamplerQuality::Linear => Self::Linear,
+            ResamplerQuality::Sinc => {
+                // cutoff relative to input nyquist
+                let cutoff = SINC_ROLLOFF * (1.0 / step).min(1.0);
+                let half = (SINC_HALF_TAPS as f64 / cutoff).ceil() as usize;
+                let mut table = Vec::with_capacity((SINC_PHASES + 1) * half * 2);
+
+                for phase in 0..=SINC_PHASES {
+                    let fraction = phase as f64 / SINC_PHASES as f64;
+                    let row_start = table.len();
+
+                    for tap in 0..half * 2 {
+                        // distance from position to input frame of this tap
+                        let d = tap as f64 - half as f64 + 1.0 - fraction;
+
+                        table.push((cutoff * sinc(cutoff * d) * blackman(d / half as f64)) as f32);
+                    }
+
+                    // unity gain at DC for every phase
+                    let sum: f32 = table[row_start..].iter().sum();
+                    table[row_start..].iter_mut().for_each(| t | *t /= sum);
+                }
+
+                Self::Sinc { half, table }
+            }
+        }
+    }
+
+    fn half(&self) -> usize {
+        match self {
+            Self::Linear => 1,
+            Self::Sinc { half, .. } => *half
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// window over [-1, 1]
+fn blackman(x: f64) -> f64 {
+    if x.abs() >= 1.0 {
+        0.0
+    } else {
+        0.42 + 0.5 * (PI * x).cos() + 0.08 * (2.0 * PI * x).cos()
+    }
+}
+
+/// Streaming sample rate converter for interleaved f32 frames.
+/// Input can be fed in chunks of any size, output is the same as if everything was processed at once
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    from: u32,
+    to: u32,
+    channels: usize,
+
+    kernel: Kernel,
+
+    // interleaved input frames, what are still needed. Starts with history of the previous frames
+    buffer: Vec<f32>,
+    // position of next output frame in `buffer` is `position + phase / to` frames.
+    // Kept as fraction, so it never drifts
+    position: usize,
+    phase: u64
+}
+
+impl Resampler {
+    pub fn new(from: u32, to: u32, channels: usize, quality: ResamplerQuality) -> Self {
+        if from == 0 || to == 0 {
+            panic!("sample rate should be non zero");
+        }
+        if channels == 0 {
+            panic!("channel count should be non zero");
+        }
+
+        let mut resampler = Self {
+            from,
+            to,
+            channels,
+
+            kernel: Kernel::new(quality, from as f64 / to as f64),
+
+            buffer: Vec::new(),
+            position: 0,
+            phase: 0
+        };
+
+        resampler.reset();
+
+        resampler
+    }
+
+    pub fn input_rate(&self) -> u32 {
+        self.from
+    }
+
+    pub fn output_rate(&self) -> u32 {
+        self.to
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Delay between input and output, in input frames.
+    /// Output is aligned with input, but can't be produced until this ammount of frames after it arrives
+    pub fn latency(&self) -> usize {
+        if self.from == self.to { 0 } else { self.kernel.half() }
+    }
+
+    /// Drops all state, so next input is treated as beginning of new stream
+    pub fn reset(&mut self) {
+        let half = self.kernel.half();
+
+        // silence before the first frame
+        self.buffer.clear();
+        self.buffer.resize(half * self.channels, 0.0);
+        self.position = half;
+        self.phase = 0;
+    }
+
+    /// Resamples `input` and appends result to `output`.
+    /// Length of `input` should be a multiple of channel count
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        if !input.len().is_multiple_of(self.channels) {
+            panic!("sample count {} is not a multiple of channel count {}", input.len(), self.channels);
+        }
+
+        if self.from == self.to {
+            output.extend_from_slice(input);
+
+            return;
+        }
+
+        self.buffer.extend_from_slice(input);
+
+        let end = self.buffer.len() / self.channels;
+        self.produce(end, output);
+    }
+
+    /// Outputs the rest of frames, what are waiting for more input, and resets resampler.
+    /// Call at the end of stream
+    pub fn flush(&mut self, output: &mut Vec<f32>) {
+        if self.from != self.to {
+            let end = self.buffer.len() / self.channels;
+
+            // silence after the last frame
+            self.buffer.resize(self.buffer.len() + self.kernel.half() * self.channels, 0.0);
+            self.produce(end, output);
+        }
+
+        self.reset();
+    }
+
+    // makes every output frame, what has enough input after it and lies before `end`
+    fn produce(&mut self, end: usize, output: &mut Vec<f32>) {
+        let half = self.kernel.half();
+        let frames = self.buffer.len() / self.channels;
+
+        while self.position + half < frames && self.position < end {
+            let base = self.position;
+            let fraction = self.phase as f64 / self.to as f64;
+
+            match &self.kernel {
+                Kernel::Linear => {
+                    let a = &self.buffer[base * self.channels..(base + 1) * self.channels];
+                    let b = &self.buffer[(base + 1) * self.channels..(base + 2) * self.channels];
+                    let fraction = fraction as f32;
+
+                    output.extend(a.iter().zip(b).map(| (&a, &b) | a + (b - a) * fraction));
+                },
+                Kernel::Sinc { half, table } => {
+                    let taps = half * 2;
+                    let phase = fraction * SINC_PHASES as f64;
+                    let row = (phase as usize).min(SINC_PHASES - 1);
+                    let t = (phase - row as f64) as f32;
+
+                    let row_a = &table[row * taps..(row + 1) * taps];
+                    let row_b = &table[(row + 1) * taps..(row + 2) * taps];
+                    let first = base + 1 - half;
+
+                    for channel in 0..self.channels {
+                        let mut sum = 0.0;
+
+                        for (tap, (&a, &b)) in row_a.iter().zip(row_b).enumerate() {
+                            sum += self.buffer[(first + tap) * self.channels + channel] * (a + (b - a) * t);
+                        }
+
+                        output.push(sum);
+                    }
+                }
+            }
+
+            self.phase += self.from as u64;
+            self.position += (self.phase / self.to as u64) as usize;
+            self.phase %= self.to as u64;
+        }
+
+        // keep only history, what next frames need
+        let drop = (self.position + 1).saturating_sub(half).min(frames);
+
+        self.buffer.drain(..drop * self.channels);
+        self.position -= drop;
+    }
+}
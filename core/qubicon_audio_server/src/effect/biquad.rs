@@ -0,0 +1,300 @@
+use core::f32::consts::PI;
+
+use num_traits::AsPrimitive;
+use qubicon_measuring_units::si::derived_units::Hertz;
+
+use super::{ Effect, lanes::{ self, Lanes } };
+
+/// Response of [`Biquad`]. Coefficients are from RBJ Audio EQ Cookbook
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterKind {
+    LowPass,
+    HighPass,
+    /// Constant 0 dB peak gain
+    BandPass,
+    Notch,
+    Peak { gain_db: f32 },
+    LowShelf { gain_db: f32 },
+    HighShelf { gain_db: f32 }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32
+}
+
+impl Coefficients {
+    fn new(kind: FilterKind, rate: u32, frequency: f32, q: f32) -> Self {
+        // filter is unstable at nyquist
+        let frequency = frequency.clamp(1.0, rate as f32 * 0.49);
+        let q = q.max(0.01);
+
+        let w = 2.0 * PI * frequency / rate as f32;
+        let (sin, cos) = w.sin_cos();
+        let alpha = sin / (2.0 * q);
+
+        let amplitude = | gain_db: f32 | 10f32.powf(gain_db / 40.0);
+
+        let (b, a) = match kind {
+            FilterKind::LowPass => ([(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha]),
+            FilterKind::HighPass => ([(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha]),
+            FilterKind::BandPass => ([alpha, 0.0, -alpha], [1.0 + alpha, -2.0 * cos, 1.0 - alpha]),
+            FilterKind::Notch => ([1.0, -2.0 * cos, 1.0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha]),
+            FilterKind::Peak { gain_db } => {
+                let a = amplitude(gain_db);
+
+                ([1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a], [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a])
+            },
+            FilterKind::LowShelf { gain_db } => {
+                let a = amplitude(gain_db);
+                let k = 2.0 * a.sqrt() * alpha;
+
+                (
+                    [a * ((a + 1.0) - (a - 1.0) * cos + k), 2.0 * a * ((a - 1.0) - (a + 1.0) * cos), a * ((a + 1.0) - (a - 1.0) * cos - k)],
+                    [(a + 1.0) + (a - 1.0) * cos + k, -2.0 * ((a - 1.0) + (a + 1.0) * cos), (a + 1.0) + (a - 1.0) * cos - k]
+                )
+            },
+            FilterKind::HighShelf { gain_db } => {
+                let a = amplitude(gain_db);
+                let k = 2.0 * a.sqrt() * alpha;
+
+                (
+                    [a * ((a + 1.0) + (a - 1.0) * cos + k), -2.0 * a * ((a - 1.0) + (a + 1.0) * cos), a * ((a + 1.0) + (a - 1.0) * cos - k)],
+                    [(a + 1.0) - (a - 1.0) * cos + k, 2.0 * ((a - 1.0) - (a + 1.0) * cos), (a + 1.0) - (a - 1.0) * cos - k]
+                )
+            }
+        };
+
+        Self {
+            b0: b[0] / a[0],
+            b1: b[1] / a[0],
+            b2: b[2] / a[0],
+            a1: a[1] / a[0],
+            a2: a[2] / a[0]
+        }
+    }
+}
+
+/// Second order IIR filter. Up to four channels are filtered at once
+pub struct Biquad {
+    rate: u32,
+    channels: usize,
+
+    kind: FilterKind,
+    frequency: Hertz<f32>,
+    q: f32,
+    coefficients: Coefficients,
+
+    // transposed direct form II state. One lane per channel, four channels in a group
+    z1: Vec<Lanes>,
+    z2: Vec<Lanes>
+}
+
+impl Biquad {
+    /// `q` of 0.707 gives flat pass band for low and high pass
+    pub fn new(kind: FilterKind, rate: u32, channels: usize, frequency: Hertz<f32>, q: f32) -> Self {
+        if channels == 0 {
+            panic!("biquad should have at least one channel");
+        }
+
+        let groups = channels.div_ceil(4);
+
+        Self {
+            rate,
+            channels,
+
+            kind,
+            frequency,
+            q,
+            coefficients: Coefficients::new(kind, rate, frequency.as_(), q),
+
+            z1: vec![Lanes::new_fill(0.0); groups],
+            z2: vec![Lanes::new_fill(0.0); groups]
+        }
+    }
+
+    pub fn low_pass(rate: u32, channels: usize, frequency: Hertz<f32>) -> Self {
+        Self::new(FilterKind::LowPass, rate, channels, frequency, core::f32::consts::FRAC_1_SQRT_2)
+    }
+
+    pub fn high_pass(rate: u32, channels: usize, frequency: Hertz<f32>) -> Self {
+        Self::new(FilterKind::HighPass, rate, channels, frequency, core::f32::consts::FRAC_1_SQRT_2)
+    }
+
+    pub fn band_pass(rate: u32, channels: usize, frequency: Hertz<f32>, q: f32) -> Self {
+        Self::new(FilterKind::BandPass, rate, channels, frequency, q)
+    }
+
+    pub fn kind(&self) -> FilterKind {
+        self.kind
+    }
+
+    pub fn frequency(&self) -> Hertz<f32> {
+        self.frequency
+    }
+
+    pub fn q(&self) -> f32 {
+        self.q
+    }
+
+    /// Changes response without clearing filter state, so it can be swept while playing
+    pub fn set(&mut self, kind: FilterKind, frequency: Hertz<f32>, q: f32) {
+        self.kind = kind;
+        self.frequency = frequency;
+        self.q = q;
+        self.coefficients = Coefficients::new(kind, self.rate, frequency.as_(), q);
+    }
+}
+
+impl Effect for Biquad {
+    fn process(&mut self, samples: &mut [f32]) {
+        let c = self.coefficients;
+        let (b0, b1, b2) = (Lanes::new_fill(c.b0), Lanes::new_fill(c.b1), Lanes::new_fill(c.b2));
+        let (a1, a2) = (Lanes::new_fill(c.a1), Lanes::new_fill(c.a2));
+
+        for frame in samples.chunks_exact_mut(self.channels) {
+            for ((group, z1), z2) in frame.chunks_mut(4).zip(self.z1.iter_mut()).zip(self.z2.iter_mut()) {
+                let x = lanes::load(group);
+                let y = b0 * x + *z1;
+
+                *z1 = b1 * x - a1 * y + *z2;
+                *z2 = b2 * x - a2 * y;
+
+                lanes::store(y, group);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.z1.fill(Lanes::new_fill(0.0));
+        self.z2.fill(Lanes::new_fill(0.0));
+    }
+}
+
+/// One band of [`ParametricEq`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EqBand {
+    pub kind: FilterKind,
+    pub frequency: Hertz<f32>,
+    pub q: f32
+}
+
+/// Biquads in series
+pub struct ParametricEq {
+    rate: u32,
+    channels: usize,
+
+    bands: Vec<EqBand>,
+    filters: Vec<Biquad>
+}
+
+impl ParametricEq {
+    pub fn new(rate: u32, channels: usize, bands: &[EqBand]) -> Self {
+        if channels == 0 {
+            panic!("equalizer should have at least one channel");
+        }
+
+        Self {
+            rate,
+            channels,
+
+            bands: bands.to_vec(),
+            filters: bands.iter()
+                .map(| b | Biquad::new(b.kind, rate, channels, b.frequency, b.q))
+                .collect()
+        }
+    }
+
+    pub fn bands(&self) -> &[EqBand] {
+        &self.bands
+    }
+
+    pub fn push_band(&mut self, band: EqBand) {
+        self.filters.push(Biquad::new(band.kind, self.rate, self.channels, band.frequency, band.q));
+        self.bands.push(band);
+    }
+
+    /// Does nothing, if there is no such band
+    pub fn set_band(&mut self, index: usize, band: EqBand) {
+        if let (Some(b), Some(filter)) = (self.bands.get_mut(index), self.filters.get_mut(index)) {
+            *b = band;
+            filter.set(band.kind, band.frequency, band.q);
+        }
+    }
+}
+
+impl Effect for ParametricEq {
+    fn process(&mut self, samples: &mut [f32]) {
+        for filter in self.filters.iter_mut() {
+            filter.process(samples);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.filters.iter_mut().for_each(| f | f.reset());
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::excessive_precision)]
+mod tests {
+    use qubicon_measuring_units::si::derived_units::Hertz;
+
+    use crate::effect::{ Effect, tests::{ impulse_response, assert_response } };
+    use super::{ Biquad, FilterKind, ParametricEq, EqBand };
+
+    // RBJ cookbook filters at 48 kHz and 1 kHz, computed in f64 with direct form I
+    const LOW_PASS: [f32; 8] = [0.00391613, 0.01494136, 0.02778547, 0.03802375, 0.04593619, 0.05179191, 0.05584675, 0.05834153];
+    const HIGH_PASS: [f32; 8] = [0.91158667, -0.16833261, -0.15152805, -0.13518975, -0.11949485, -0.10458048, -0.09054835, -0.07746917];
+    // q = 0.707
+    const BAND_PASS: [f32; 8] = [0.08449721, 0.15339125, 0.12374258, 0.09716600, 0.07355866, 0.05278857, 0.03470160, 0.01912764];
+    // +6 dB, q = 1
+    const PEAK: [f32; 8] = [1.04395309, 0.08330520, 0.07386603, 0.06405252, 0.05405824, 0.04406258, 0.03422917, 0.02470448];
+
+    #[test]
+    fn impulse_responses() {
+        let khz = Hertz::from(1000.0);
+
+        assert_response(&impulse_response(&mut Biquad::low_pass(48000, 1, khz), 1, 8), &LOW_PASS);
+        assert_response(&impulse_response(&mut Biquad::high_pass(48000, 1, khz), 1, 8), &HIGH_PASS);
+        assert_response(&impulse_response(&mut Biquad::band_pass(48000, 1, khz, core::f32::consts::FRAC_1_SQRT_2), 1, 8), &BAND_PASS);
+        assert_response(&impulse_response(&mut Biquad::new(FilterKind::Peak { gain_db: 6.0 }, 48000, 1, khz, 1.0), 1, 8), &PEAK);
+    }
+
+    #[test]
+    fn channels_are_independent() {
+        // more channels than one group of lanes
+        let mut filter = Biquad::low_pass(48000, 6, Hertz::from(1000.0));
+        let mut samples = vec![0.0; 6 * 8];
+
+        samples[5] = 1.0;
+        filter.process(&mut samples);
+
+        let last: Vec<f32> = samples.iter().skip(5).step_by(6).copied().collect();
+
+        assert_response(&last, &LOW_PASS);
+        assert!(samples.iter().enumerate().all(| (i, &s) | i % 6 == 5 || s == 0.0));
+
+        filter.reset();
+        assert_response(&impulse_response(&mut filter, 6, 8), &LOW_PASS);
+    }
+
+    #[test]
+    fn eq() {
+        let peak = EqBand { kind: FilterKind::Peak { gain_db: 6.0 }, frequency: Hertz::from(1000.0), q: 1.0 };
+        // 0 dB shelf does nothing
+        let shelf = EqBand { kind: FilterKind::LowShelf { gain_db: 0.0 }, frequency: Hertz::from(200.0), q: 0.707 };
+
+        let mut eq = ParametricEq::new(48000, 2, &[shelf, peak]);
+
+        assert_response(&impulse_response(&mut eq, 2, 8), &PEAK);
+
+        eq.set_band(1, EqBand { kind: FilterKind::HighShelf { gain_db: 0.0 }, ..peak });
+        eq.reset();
+        assert_response(&impulse_response(&mut eq, 2, 8), &[1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use super::{ Effect, lanes::{ self, Lanes } };
+
+/// Echo with feedback
+pub struct Delay {
+    rate: u32,
+    channels: usize,
+
+    // interleaved frames, what will be heard after `frames`
+    buffer: Vec<f32>,
+    frames: usize,
+    position: usize,
+
+    feedback: f32,
+    mix: f32
+}
+
+impl Delay {
+    /// `feedback` is gain of every next echo. `mix` is 0 for dry signal only and 1 for echoes only
+    pub fn new(rate: u32, channels: usize, time: Duration, feedback: f32, mix: f32) -> Self {
+        if channels == 0 {
+            panic!("delay should have at least one channel");
+        }
+
+        let mut delay = Self {
+            rate,
+            channels,
+
+            buffer: Vec::new(),
+            frames: 0,
+            position: 0,
+
+            feedback: 0.0,
+            mix: 0.0
+        };
+
+        delay.set_time(time);
+        delay.set_feedback(feedback);
+        delay.set_mix(mix);
+
+        delay
+    }
+
+    pub fn time(&self) -> Duration {
+        Duration::from_secs_f64(self.frames as f64 / self.rate as f64)
+    }
+
+    /// Clears echoes, what are already in the buffer. At least one frame long
+    pub fn set_time(&mut self, time: Duration) {
+        self.frames = ((time.as_secs_f64() * self.rate as f64).round() as usize).max(1);
+        self.buffer = vec![0.0; self.frames * self.channels];
+        self.position = 0;
+    }
+
+    /// Clamped below 1, so echoes always die out
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback = feedback.clamp(0.0, 0.99);
+    }
+
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+}
+
+impl Effect for Delay {
+    fn process(&mut self, samples: &mut [f32]) {
+        let channels = self.channels;
+        let (dry, wet) = (Lanes::new_fill(1.0 - self.mix), Lanes::new_fill(self.mix));
+        let feedback = Lanes::new_fill(self.feedback);
+
+        for frame in samples.chunks_exact_mut(channels) {
+            let delayed_frame = &mut self.buffer[self.position * channels..(self.position + 1) * channels];
+
+            for (group, delayed_group) in frame.chunks_mut(4).zip(delayed_frame.chunks_mut(4)) {
+                let x = lanes::load(group);
+                let delayed = lanes::load(delayed_group);
+
+                lanes::store(x * dry + delayed * wet, group);
+                lanes::store(x + delayed * feedback, delayed_group);
+            }
+
+            self.position = (self.position + 1) % self.frames;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.position = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::effect::tests::{ impulse_response, assert_response };
+    use super::Delay;
+
+    #[test]
+    fn impulse_response_with_feedback() {
+        let mut delay = Delay::new(1000, 2, Duration::from_millis(3), 0.5, 0.25);
+
+        assert_response(
+            &impulse_response(&mut delay, 2, 10),
+            &[0.75, 0.0, 0.0, 0.25, 0.0, 0.0, 0.125, 0.0, 0.0, 0.0625]
+        );
+
+        assert_eq!(delay.time(), Duration::from_millis(3));
+    }
+}
@@ -0,0 +1,537 @@
+use std::{ collections::VecDeque, time::Duration };
+
+use super::{ Effect, lanes, db_to_gain, gain_to_db, time_coefficient };
+
+// loudest sample of the frame. Channels are linked, so stereo image does not move
+fn frame_peak(frame: &[f32]) -> f32 {
+    frame.iter().fold(0.0, | peak, s | s.abs().max(peak))
+}
+
+// repeats gain of every frame for all of its channels, so it can be applied in lanes
+fn expand_gains(frame_gains: &[f32], channels: usize, gains: &mut Vec<f32>) {
+    gains.clear();
+    gains.extend(frame_gains.iter().flat_map(| &g | core::iter::repeat_n(g, channels)));
+}
+
+/// Feed forward peak compressor with soft knee
+pub struct Compressor {
+    rate: u32,
+    channels: usize,
+
+    threshold_db: f32,
+    ratio: f32,
+    knee_db: f32,
+    makeup_db: f32,
+    attack: f32,
+    release: f32,
+
+    // gain reduction in dB, always <= 0
+    envelope: f32,
+
+    frame_gains: Vec<f32>,
+    gains: Vec<f32>
+}
+
+impl Compressor {
+    /// Starts with 4:1 ratio, 6 dB knee, 10 ms attack and 100 ms release
+    pub fn new(rate: u32, channels: usize, threshold_db: f32) -> Self {
+        if channels == 0 {
+            panic!("compressor should have at least one channel");
+        }
+
+        Self {
+            rate,
+            channels,
+
+            threshold_db,
+            ratio: 4.0,
+            knee_db: 6.0,
+            makeup_db: 0.0,
+            attack: time_coefficient(Duration::from_millis(10), rate),
+            release: time_coefficient(Duration::from_millis(100), rate),
+
+            envelope: 0.0,
+
+            frame_gains: Vec::new(),
+            gains: Vec::new()
+        }
+    }
+
+    pub fn set_threshold(&mut self, threshold_db: f32) {
+        self.threshold_db = threshold_db;
+    }
+
+    /// Ammount of input dB above threshold for one output dB. Clamped to at least 1
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio.max(1.0);
+    }
+
+    /// Width of soft knee around threshold. 0 is hard knee
+    pub fn set_knee(&mut self, knee_db: f32) {
+        self.knee_db = knee_db.max(0.0);
+    }
+
+    /// Gain after compression
+    pub fn set_makeup(&mut self, makeup_db: f32) {
+        self.makeup_db = makeup_db;
+    }
+
+    pub fn set_attack(&mut self, attack: Duration) {
+        self.attack = time_coefficient(attack, self.rate);
+    }
+
+    pub fn set_release(&mut self, release: Duration) {
+        self.release = time_coefficient(release, self.rate);
+    }
+
+    /// Current gain reduction in dB. Zero or negative
+    pub fn gain_reduction(&self) -> f32 {
+        self.envelope
+    }
+
+    // static curve, returns gain reduction in dB
+    fn reduction(&self, level_db: f32) -> f32 {
+        let over = level_db - self.threshold_db;
+        let slope = 1.0 / self.ratio - 1.0;
+
+        if 2.0 * over <= -self.knee_db {
+            0.0
+        } else if 2.0 * over.abs() <= self.knee_db {
+            slope * (over + self.knee_db / 2.0).powi(2) / (2.0 * self.knee_db)
+        } else {
+            slope * over
+        }
+    }
+}
+
+impl Effect for Compressor {
+    fn process(&mut self, samples: &mut [f32]) {
+        let makeup = db_to_gain(self.makeup_db);
+
+        self.frame_gains.clear();
+
+        for frame in samples.chunks_exact(self.channels) {
+            let target = self.reduction(gain_to_db(frame_peak(frame)));
+            let coefficient = match target < self.envelope {
+                true => self.attack,
+                false => self.release
+            };
+
+            self.envelope = target + (self.envelope - target) * coefficient;
+            self.frame_gains.push(db_to_gain(self.envelope) * makeup);
+        }
+
+        expand_gains(&self.frame_gains, self.channels, &mut self.gains);
+        lanes::multiply(samples, &self.gains);
+    }
+
+    fn reset(&mut self) {
+        self.envelope = 0.0;
+    }
+}
+
+/// Brickwall lookahead limiter. Output never goes above ceiling.
+/// Signal is delayed by lookahead, so gain is already down, when peak comes
+pub struct Limiter {
+    rate: u32,
+    channels: usize,
+
+    ceiling: f32,
+    release: f32,
+
+    // interleaved frames, what wait for their gain
+    delay: Vec<f32>,
+    lookahead: usize,
+    position: usize,
+
+    // frame number and gain, what it needs. Increasing gains, so front is minimum of window
+    window: VecDeque<(u64, f32)>,
+    frame: u64,
+    gain: f32,
+
+    frame_gains: Vec<f32>,
+    gains: Vec<f32>
+}
+
+impl Limiter {
+    /// Starts with 5 ms lookahead and 50 ms release
+    pub fn new(rate: u32, channels: usize, ceiling_db: f32) -> Self {
+        if channels == 0 {
+            panic!("limiter should have at least one channel");
+        }
+
+        let mut limiter = Self {
+            rate,
+            channels,
+
+            ceiling: db_to_gain(ceiling_db),
+            release: time_coefficient(Duration::from_millis(50), rate),
+
+            delay: Vec::new(),
+            lookahead: 0,
+            position: 0,
+
+            window: VecDeque::new(),
+            frame: 0,
+            gain: 1.0,
+
+            frame_gains: Vec::new(),
+            gains: Vec::new()
+        };
+
+        limiter.set_lookahead(Duration::from_millis(5));
+        limiter
+    }
+
+    pub fn set_ceiling(&mut self, ceiling_db: f32) {
+        self.ceiling = db_to_gain(ceiling_db);
+    }
+
+    pub fn set_release(&mut self, release: Duration) {
+        self.release = time_coefficient(release, self.rate);
+    }
+
+    /// Changes latency of limiter and resets it
+    pub fn set_lookahead(&mut self, lookahead: Duration) {
+        self.lookahead = (lookahead.as_secs_f64() * self.rate as f64).round() as usize;
+        self.delay = vec![0.0; self.lookahead * self.channels];
+        self.reset();
+    }
+
+    pub fn lookahead(&self) -> Duration {
+        Duration::from_secs_f64(self.lookahead as f64 / self.rate as f64)
+    }
+}
+
+impl Effect for Limiter {
+    fn process(&mut self, samples: &mut [f32]) {
+        let channels = self.channels;
+
+        self.frame_gains.clear();
+
+        for frame in samples.chunks_exact_mut(channels) {
+            let required = (self.ceiling / frame_peak(frame)).min(1.0);
+
+            while self.window.back().is_some_and(| &(_, g) | g >= required) {
+                self.window.pop_back();
+            }
+            self.window.push_back((self.frame, required));
+
+            // peak, what left window, is already out
+            while self.window.front().is_some_and(| &(f, _) | f + (self.lookahead as u64) < self.frame) {
+                self.window.pop_front();
+            }
+
+            let target = self.window.front().map_or(1.0, | &(_, g) | g);
+
+            // instant attack, lookahead makes it smooth enough
+            self.gain = match target < self.gain {
+                true => target,
+                false => target + (self.gain - target) * self.release
+            };
+
+            // swap current frame with the one, what was delayed
+            if self.lookahead > 0 {
+                let delayed = &mut self.delay[self.position * channels..(self.position + 1) * channels];
+
+                frame.swap_with_slice(delayed);
+                self.position = (self.position + 1) % self.lookahead;
+            }
+
+            self.frame += 1;
+            self.frame_gains.push(self.gain);
+        }
+
+        expand_gains(&self.frame_gains, channels, &mut self.gains);
+        lanes::multiply(samples, &self.gains);
+
+        // rounding can leave samples a bit above ceiling
+        samples.iter_mut().for_each(| s | *s = s.clamp(-self.ceiling, self.ceiling));
+    }
+
+    fn reset(&mut self) {
+        self.delay.fill(0.0);
+        self.position = 0;
+        self.window.clear();
+        self.frame = 0;
+        self.gain = 1.0;
+    }
+
+    fn latency(&self) -> usize {
+        self.lookahead
+    }
+}
+
+/// Silences signal below threshold. Gate is kept open for hold time after signal drops,
+/// so ends of words and decays of notes are not cut
+pub struct NoiseGate {
+    rate: u32,
+    channels: usize,
+
+    open_threshold: f32,
+    close_threshold: f32,
+    floor: f32,
+    attack: f32,
+    release: f32,
+    hold: usize,
+
+    open: bool,
+    hold_left: usize,
+    gain: f32,
+
+    frame_gains: Vec<f32>,
+    gains: Vec<f32>
+}
+
+impl NoiseGate {
+    /// Starts with 1 ms attack, 100 ms hold, 50 ms release, 6 dB of hysteresis and full silence when closed
+    pub fn new(rate: u32, channels: usize, threshold_db: f32) -> Self {
+        if channels == 0 {
+            panic!("noise gate should have at least one channel");
+        }
+
+        let mut gate = Self {
+            rate,
+            channels,
+
+            open_threshold: 0.0,
+            close_threshold: 0.0,
+            floor: 0.0,
+            attack: time_coefficient(Duration::from_millis(1), rate),
+            release: time_coefficient(Duration::from_millis(50), rate),
+            hold: 0,
+
+            open: false,
+            hold_left: 0,
+            gain: 0.0,
+
+            frame_gains: Vec::new(),
+            gains: Vec::new()
+        };
+
+        gate.set_threshold(threshold_db, 6.0);
+        gate.set_hold(Duration::from_millis(100));
+        gate
+    }
+
+    /// Gate opens above `threshold_db` and closes below it minus `hysteresis_db`
+    pub fn set_threshold(&mut self, threshold_db: f32, hysteresis_db: f32) {
+        self.open_threshold = db_to_gain(threshold_db);
+        self.close_threshold = db_to_gain(threshold_db - hysteresis_db.max(0.0));
+    }
+
+    /// Gain of closed gate. Something like -20 dB sounds more natural than silence
+    pub fn set_range(&mut self, range_db: f32) {
+        self.floor = db_to_gain(range_db).min(1.0);
+    }
+
+    pub fn set_attack(&mut self, attack: Duration) {
+        self.attack = time_coefficient(attack, self.rate);
+    }
+
+    pub fn set_hold(&mut self, hold: Duration) {
+        self.hold = (hold.as_secs_f64() * self.rate as f64).round() as usize;
+    }
+
+    pub fn set_release(&mut self, release: Duration) {
+        self.release = time_coefficient(release, self.rate);
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+}
+
+impl Effect for NoiseGate {
+    fn process(&mut self, samples: &mut [f32]) {
+        self.frame_gains.clear();
+
+        for frame in samples.chunks_exact(self.channels) {
+            let peak = frame_peak(frame);
+
+            if peak >= self.open_threshold {
+                self.open = true;
+                self.hold_left = self.hold;
+            } else if peak < self.close_threshold && self.open {
+                match self.hold_left {
+                    0 => self.open = false,
+                    _ => self.hold_left -= 1
+                }
+            }
+
+            let (target, coefficient) = match self.open {
+                true => (1.0, self.attack),
+                false => (self.floor, self.release)
+            };
+
+            self.gain = target + (self.gain - target) * coefficient;
+            self.frame_gains.push(self.gain);
+        }
+
+        expand_gains(&self.frame_gains, self.channels, &mut self.gains);
+        lanes::multiply(samples, &self.gains);
+    }
+
+    fn reset(&mut self) {
+        self.open = false;
+        self.hold_left = 0;
+        self.gain = self.floor;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::effect::{ Effect, db_to_gain, tests::{ impulse_response, assert_response } };
+    use super::{ Compressor, Limiter, NoiseGate };
+
+    #[test]
+    fn compressor_below_threshold() {
+        let mut compressor = Compressor::new(1000, 2, -6.0);
+
+        compressor.set_makeup(6.0);
+
+        let mut reference = [0.0; 8];
+        reference[0] = db_to_gain(6.0);
+
+        // impulse of 1 is above threshold, so use smaller one
+        let mut samples = vec![0.0; 16];
+        samples[..2].fill(0.25);
+        compressor.process(&mut samples);
+
+        let response: Vec<f32> = samples.iter().step_by(2).map(| s | s / 0.25).collect();
+
+        assert_response(&response, &reference);
+    }
+
+    #[test]
+    fn compressor_static_curve_and_attack() {
+        let rate = 1000;
+        let mut compressor = Compressor::new(rate, 1, -20.0);
+
+        compressor.set_knee(0.0);
+        compressor.set_attack(Duration::from_millis(10));
+
+        let mut samples = vec![1.0; 10];
+        compressor.process(&mut samples);
+
+        // 20 dB above threshold at 4:1 is 15 dB of reduction. After attack time 1 - 1/e of it is reached
+        let expected = -15.0 * (1.0 - (-1.0f32).exp());
+        assert!((compressor.gain_reduction() - expected).abs() < 0.01, "{}", compressor.gain_reduction());
+
+        let mut samples = vec![1.0; rate as usize];
+        compressor.process(&mut samples);
+
+        assert!((samples[samples.len() - 1] - db_to_gain(-15.0)).abs() < 1e-4);
+
+        // soft knee reduces a bit at threshold
+        compressor.set_knee(6.0);
+        compressor.reset();
+
+        let mut samples = vec![db_to_gain(-20.0); rate as usize];
+        compressor.process(&mut samples);
+
+        assert!((compressor.gain_reduction() - -0.75 * 9.0 / 12.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn limiter_impulse_response() {
+        let mut limiter = Limiter::new(1000, 2, 0.0);
+
+        limiter.set_lookahead(Duration::from_millis(3));
+        assert_eq!(limiter.latency(), 3);
+
+        let mut samples = vec![0.0; 16];
+        samples[..2].fill(4.0);
+        limiter.process(&mut samples);
+
+        let response: Vec<f32> = samples.iter().step_by(2).copied().collect();
+
+        assert_response(&response, &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]);
+
+        // quiet signal is only delayed
+        limiter.reset();
+        assert_response(&impulse_response(&mut limiter, 2, 6), &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn compressor_step_response() {
+        let mut compressor = Compressor::new(1000, 1, -20.0);
+
+        compressor.set_knee(0.0);
+        compressor.set_attack(Duration::from_millis(2));
+        compressor.set_release(Duration::from_millis(5));
+
+        // 20 dB above threshold, then 20 dB below it
+        let mut samples = [1.0, 1.0, 1.0, 1.0, 0.01, 0.01, 0.01, 0.01];
+        compressor.process(&mut samples);
+
+        assert_response(&samples, &[0.506872, 0.335668, 0.261425, 0.224647, 0.002945, 0.003675, 0.004407, 0.005112]);
+    }
+
+    #[test]
+    fn limiter_step_response() {
+        let mut limiter = Limiter::new(1000, 1, 0.0);
+
+        limiter.set_lookahead(Duration::from_millis(2));
+        limiter.set_release(Duration::from_millis(5));
+
+        let mut samples = [0.5, 0.5, 0.5, 2.0, 2.0, 2.0, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5];
+        limiter.process(&mut samples);
+
+        // gain is down before the step comes out, and goes up after it
+        assert_response(&samples, &[0.0, 0.0, 0.5, 0.25, 0.25, 1.0, 1.0, 1.0, 0.295317, 0.332420, 0.362797, 0.387668]);
+    }
+
+    #[test]
+    fn limiter_never_clips() {
+        let rate = 48000;
+        let mut limiter = Limiter::new(rate, 1, -1.0);
+        let ceiling = db_to_gain(-1.0);
+
+        let mut samples: Vec<f32> = (0..rate)
+            .map(| i | (i as f32 * 0.05).sin() * (1.0 + (i % 7919) as f32 / 1000.0))
+            .collect();
+
+        limiter.process(&mut samples);
+
+        assert!(samples.iter().all(| s | s.abs() <= ceiling));
+        // gain is not just thrown away
+        assert!(samples.iter().fold(0.0f32, | p, s | p.max(s.abs())) > ceiling * 0.99);
+    }
+
+    #[test]
+    fn noise_gate() {
+        let mut gate = NoiseGate::new(1000, 1, -20.0);
+
+        gate.set_attack(Duration::ZERO);
+        gate.set_release(Duration::ZERO);
+        gate.set_hold(Duration::from_millis(2));
+
+        // noise below threshold is removed
+        let mut samples = vec![0.02; 4];
+        gate.process(&mut samples);
+        assert_eq!(samples, [0.0; 4]);
+
+        // signal opens gate, hold keeps it open for two frames, then it closes
+        let mut samples = vec![0.5, 0.02, 0.02, 0.02, 0.02];
+        gate.process(&mut samples);
+        assert_eq!(samples, [0.5, 0.02, 0.02, 0.0, 0.0]);
+        assert!(!gate.is_open());
+
+        // closed gate lets range through
+        gate.set_range(-6.0);
+        gate.reset();
+
+        let mut samples = vec![0.02; 2];
+        gate.process(&mut samples);
+        assert!((samples[1] - 0.02 * db_to_gain(-6.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    #[should_panic]
+    fn no_channels() {
+        Compressor::new(1000, 0, 0.0);
+    }
+}
@@ -0,0 +1,96 @@
+//! Four f32 lanes, what are processed at once.
+//! SSE vector of `qubicon_simd` on x86_64, plain array everywhere else
+
+#[cfg(all(target_arch = "x86_64", target_feature = "sse"))]
+pub(crate) use qubicon_simd::F32x4 as Lanes;
+
+#[cfg(not(all(target_arch = "x86_64", target_feature = "sse")))]
+pub(crate) use fallback::Lanes;
+
+#[cfg(not(all(target_arch = "x86_64", target_feature = "sse")))]
+mod fallback {
+    use core::ops::{ Add, Sub, Mul };
+
+    #[derive(Debug, Clone, Copy)]
+    pub(crate) struct Lanes ( [f32; 4] );
+
+    impl Lanes {
+        pub(crate) fn new_fill(value: f32) -> Self {
+            Self ( [value; 4] )
+        }
+
+        fn zip(self, rhs: Self, f: impl Fn(f32, f32) -> f32) -> Self {
+            Self ( core::array::from_fn(| i | f(self.0[i], rhs.0[i])) )
+        }
+    }
+
+    impl From<[f32; 4]> for Lanes {
+        fn from(value: [f32; 4]) -> Self {
+            Self ( value )
+        }
+    }
+
+    impl From<Lanes> for [f32; 4] {
+        fn from(value: Lanes) -> Self {
+            value.0
+        }
+    }
+
+    impl Add for Lanes {
+        type Output = Self;
+
+        fn add(self, rhs: Self) -> Self::Output {
+            self.zip(rhs, | a, b | a + b)
+        }
+    }
+
+    impl Sub for Lanes {
+        type Output = Self;
+
+        fn sub(self, rhs: Self) -> Self::Output {
+            self.zip(rhs, | a, b | a - b)
+        }
+    }
+
+    impl Mul for Lanes {
+        type Output = Self;
+
+        fn mul(self, rhs: Self) -> Self::Output {
+            self.zip(rhs, | a, b | a * b)
+        }
+    }
+}
+
+/// Loads up to 4 samples. Missing lanes are 0
+pub(crate) fn load(samples: &[f32]) -> Lanes {
+    let mut lanes = [0.0; 4];
+    let len = samples.len().min(4);
+
+    lanes[..len].copy_from_slice(&samples[..len]);
+
+    Lanes::from(lanes)
+}
+
+/// Stores as many lanes, as `samples` can take
+pub(crate) fn store(lanes: Lanes, samples: &mut [f32]) {
+    let lanes: [f32; 4] = lanes.into();
+    let len = samples.len().min(4);
+
+    samples[..len].copy_from_slice(&lanes[..len]);
+}
+
+/// Multiplies `samples` by `gains` element wise
+pub(crate) fn multiply(samples: &mut [f32], gains: &[f32]) {
+    for (samples, gains) in samples.chunks_mut(4).zip(gains.chunks(4)) {
+        store(load(samples) * load(gains), samples);
+    }
+}
+
+/// Adds `other` multiplied by `gain` to `samples`
+pub(crate) fn mix(samples: &mut [f32], other: &[f32], gain: f32) {
+    let gain = Lanes::new_fill(gain);
+
+    for (samples, other) in samples.chunks_mut(4).zip(other.chunks(4)) {
+        store(load(samples) + load(other) * gain, samples);
+    }
+}
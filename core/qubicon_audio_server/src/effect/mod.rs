@@ -0,0 +1,173 @@
+//! Effects, what process interleaved f32 frames in place.
+//! They are inserted into [`EffectChain`]s of [`crate::mixer::Mixer`] buses
+
+pub use biquad::{ Biquad, FilterKind, ParametricEq, EqBand };
+pub use delay::Delay;
+pub use reverb::Reverb;
+pub use dynamics::{ Compressor, Limiter, NoiseGate };
+
+pub(crate) mod lanes;
+mod biquad;
+mod delay;
+mod reverb;
+mod dynamics;
+
+/// Processor of interleaved f32 frames. Effects are created for fixed sample rate and channel count
+pub trait Effect: Send {
+    /// Processes frames in place. Length of `samples` is always a multiple of channel count
+    fn process(&mut self, samples: &mut [f32]);
+
+    /// Clears internal state, like filter history or reverb tail
+    fn reset(&mut self);
+
+    /// Delay in frames, what effect adds to the signal
+    fn latency(&self) -> usize {
+        0
+    }
+}
+
+impl Effect for Box<dyn Effect> {
+    fn process(&mut self, samples: &mut [f32]) {
+        self.as_mut().process(samples)
+    }
+
+    fn reset(&mut self) {
+        self.as_mut().reset()
+    }
+
+    fn latency(&self) -> usize {
+        self.as_ref().latency()
+    }
+}
+
+/// Effects, what are applied one after another
+#[derive(Default)]
+pub struct EffectChain {
+    effects: Vec<Box<dyn Effect>>,
+    bypassed: bool
+}
+
+impl EffectChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Adds effect to the end of chain. Returns its index
+    pub fn push(&mut self, effect: impl Effect + 'static) -> usize {
+        self.effects.push(Box::new(effect));
+        self.effects.len() - 1
+    }
+
+    /// Panics, if `index` is greater than length of chain
+    pub fn insert(&mut self, index: usize, effect: impl Effect + 'static) {
+        self.effects.insert(index, Box::new(effect));
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Box<dyn Effect>> {
+        (index < self.effects.len()).then(|| self.effects.remove(index))
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Box<dyn Effect>> {
+        self.effects.get_mut(index)
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+
+    pub fn is_bypassed(&self) -> bool {
+        self.bypassed
+    }
+
+    /// Bypassed chain leaves signal as is. State of effects is kept
+    pub fn set_bypassed(&mut self, bypassed: bool) {
+        self.bypassed = bypassed;
+    }
+}
+
+impl Effect for EffectChain {
+    fn process(&mut self, samples: &mut [f32]) {
+        if self.bypassed {
+            return;
+        }
+
+        for effect in self.effects.iter_mut() {
+            effect.process(samples);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.effects.iter_mut().for_each(| e | e.reset());
+    }
+
+    fn latency(&self) -> usize {
+        self.effects.iter().map(| e | e.latency()).sum()
+    }
+}
+
+pub(crate) fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+pub(crate) fn gain_to_db(gain: f32) -> f32 {
+    // -inf would poison envelopes
+    20.0 * gain.max(1e-9).log10()
+}
+
+/// Coefficient of one pole smoother, what reaches 1 - 1/e of the step in `time`
+pub(crate) fn time_coefficient(time: core::time::Duration, rate: u32) -> f32 {
+    let frames = time.as_secs_f32() * rate as f32;
+
+    match frames > 0.0 {
+        true => (-1.0 / frames).exp(),
+        false => 0.0
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{ Effect, EffectChain, Delay };
+
+    /// Impulse response of effect, first channel only
+    pub(crate) fn impulse_response(effect: &mut impl Effect, channels: usize, len: usize) -> Vec<f32> {
+        let mut samples = vec![0.0; len * channels];
+
+        samples[..channels].fill(1.0);
+        effect.process(&mut samples);
+
+        samples.iter().step_by(channels).copied().collect()
+    }
+
+    pub(crate) fn assert_response(response: &[f32], reference: &[f32]) {
+        for (i, (a, b)) in response.iter().zip(reference).enumerate() {
+            assert!((a - b).abs() < 1e-5, "sample {i}: {a} != {b}\n{response:?}");
+        }
+    }
+
+    #[test]
+    fn chain() {
+        let mut chain = EffectChain::new();
+
+        // two echoes of one frame make one of two frames
+        chain.push(Delay::new(1, 1, core::time::Duration::from_secs(1), 0.0, 1.0));
+        chain.push(Delay::new(1, 1, core::time::Duration::from_secs(1), 0.0, 1.0));
+
+        assert_response(&impulse_response(&mut chain, 1, 4), &[0.0, 0.0, 1.0, 0.0]);
+
+        chain.set_bypassed(true);
+        assert_response(&impulse_response(&mut chain, 1, 4), &[1.0, 0.0, 0.0, 0.0]);
+
+        chain.set_bypassed(false);
+        chain.reset();
+        chain.remove(0);
+        assert_response(&impulse_response(&mut chain, 1, 4), &[0.0, 1.0, 0.0, 0.0]);
+    }
+}
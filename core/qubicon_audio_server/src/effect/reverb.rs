@@ -0,0 +1,280 @@
+use super::{ Effect, lanes::Lanes };
+
+// Freeverb tuning. Lengths are in frames at 44.1 kHz
+const COMBS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASSES: [usize; 4] = [556, 441, 341, 225];
+// added to lengths of every next channel, so channels are decorrelated
+const SPREAD: usize = 23;
+const TUNING_RATE: f32 = 44100.0;
+
+const INPUT_GAIN: f32 = 0.015;
+const WET_SCALE: f32 = 3.0;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+
+// four combs of one channel, processed in lanes
+struct CombGroup {
+    buffers: [Vec<f32>; 4],
+    positions: [usize; 4],
+    // lowpass in feedback path
+    filter: Lanes
+}
+
+impl CombGroup {
+    fn new(lengths: [usize; 4]) -> Self {
+        Self {
+            buffers: lengths.map(| l | vec![0.0; l]),
+            positions: [0; 4],
+            filter: Lanes::new_fill(0.0)
+        }
+    }
+
+    fn process(&mut self, input: Lanes, feedback: Lanes, damp: Lanes, undamp: Lanes) -> Lanes {
+        let output = Lanes::from(core::array::from_fn(| i | self.buffers[i][self.positions[i]]));
+
+        self.filter = output * undamp + self.filter * damp;
+
+        let stored: [f32; 4] = (input + self.filter * feedback).into();
+
+        for ((buffer, position), stored) in self.buffers.iter_mut().zip(self.positions.iter_mut()).zip(stored) {
+            buffer[*position] = stored;
+            *position = (*position + 1) % buffer.len();
+        }
+
+        output
+    }
+
+    fn reset(&mut self) {
+        self.buffers.iter_mut().for_each(| b | b.fill(0.0));
+        self.filter = Lanes::new_fill(0.0);
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    position: usize
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.position];
+
+        self.buffer[self.position] = input + delayed * ALLPASS_FEEDBACK;
+        self.position = (self.position + 1) % self.buffer.len();
+
+        delayed - input
+    }
+}
+
+struct Tank {
+    combs: [CombGroup; 2],
+    allpasses: Vec<Allpass>
+}
+
+/// Algorithmic reverb after Freeverb. Every channel has its own set of
+/// lowpass-feedback combs and allpasses
+pub struct Reverb {
+    channels: usize,
+    tanks: Vec<Tank>,
+    // wet output of every channel for current frame
+    wet_frame: Vec<f32>,
+
+    room_size: f32,
+    damping: f32,
+    wet: f32,
+    dry: f32,
+    width: f32
+}
+
+impl Reverb {
+    pub fn new(rate: u32, channels: usize) -> Self {
+        if channels == 0 {
+            panic!("reverb should have at least one channel");
+        }
+
+        let scale = | length: usize, channel: usize | {
+            (((length + channel * SPREAD) as f32 * rate as f32 / TUNING_RATE).round() as usize).max(1)
+        };
+
+        let tanks = (0..channels)
+            .map(| c | Tank {
+                combs: [
+                    CombGroup::new(core::array::from_fn(| i | scale(COMBS[i], c))),
+                    CombGroup::new(core::array::from_fn(| i | scale(COMBS[i + 4], c)))
+                ],
+                allpasses: ALLPASSES.iter()
+                    .map(| &l | Allpass { buffer: vec![0.0; scale(l, c)], position: 0 })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            channels,
+            tanks,
+            wet_frame: vec![0.0; channels],
+
+            room_size: 0.5,
+            damping: 0.5,
+            wet: 1.0 / WET_SCALE,
+            dry: 1.0,
+            width: 1.0
+        }
+    }
+
+    /// From 0 to 1. Bigger rooms have longer tails
+    pub fn set_room_size(&mut self, room_size: f32) {
+        self.room_size = room_size.clamp(0.0, 1.0);
+    }
+
+    /// From 0 to 1. Damped rooms lose high frequencies faster
+    pub fn set_damping(&mut self, damping: f32) {
+        self.damping = damping.clamp(0.0, 1.0);
+    }
+
+    /// Linear gain of reverberated signal
+    pub fn set_wet(&mut self, wet: f32) {
+        self.wet = wet.max(0.0);
+    }
+
+    /// Linear gain of original signal
+    pub fn set_dry(&mut self, dry: f32) {
+        self.dry = dry.max(0.0);
+    }
+
+    /// From 0 to 1. 0 mixes wet signal of channel pairs into mono
+    pub fn set_width(&mut self, width: f32) {
+        self.width = width.clamp(0.0, 1.0);
+    }
+}
+
+impl Effect for Reverb {
+    fn process(&mut self, samples: &mut [f32]) {
+        let feedback = Lanes::new_fill(self.room_size * 0.28 + 0.7);
+        let damp = self.damping * 0.4;
+        let (damp, undamp) = (Lanes::new_fill(damp), Lanes::new_fill(1.0 - damp));
+
+        let wet = self.wet * WET_SCALE;
+        // own channel and its stereo partner
+        let wet_own = wet * (self.width * 0.5 + 0.5);
+        let wet_partner = wet * (1.0 - self.width) * 0.5;
+
+        for frame in samples.chunks_exact_mut(self.channels) {
+            for (c, tank) in self.tanks.iter_mut().enumerate() {
+                let input = Lanes::new_fill(frame[c] * INPUT_GAIN);
+
+                let combs = tank.combs[0].process(input, feedback, damp, undamp)
+                    + tank.combs[1].process(input, feedback, damp, undamp);
+                let combs: [f32; 4] = combs.into();
+
+                self.wet_frame[c] = tank.allpasses.iter_mut()
+                    .fold(combs.iter().sum(), | s, allpass | allpass.process(s));
+            }
+
+            for (c, sample) in frame.iter_mut().enumerate() {
+                let partner = match c ^ 1 < self.channels {
+                    true => c ^ 1,
+                    false => c
+                };
+
+                *sample = *sample * self.dry + self.wet_frame[c] * wet_own + self.wet_frame[partner] * wet_partner;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        for tank in self.tanks.iter_mut() {
+            tank.combs.iter_mut().for_each(| c | c.reset());
+            tank.allpasses.iter_mut().for_each(| a | a.buffer.fill(0.0));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::effect::{ Effect, tests::{ impulse_response, assert_response } };
+    use super::{ Reverb, COMBS, INPUT_GAIN, WET_SCALE };
+
+    fn energy(samples: &[f32]) -> f32 {
+        samples.iter().map(| s | s * s).sum()
+    }
+
+    #[test]
+    fn impulse_response_at_tuning_rate() {
+        let mut reverb = Reverb::new(44100, 2);
+
+        reverb.set_wet(1.0);
+        reverb.set_dry(0.5);
+
+        let response = impulse_response(&mut reverb, 2, 44100 * 2);
+
+        assert_eq!(response[0], 0.5);
+        // nothing comes out of combs before the shortest one
+        assert!(response[1..COMBS[0]].iter().all(| &s | s == 0.0));
+        // first reflection passes four allpasses, what flip sign twice
+        assert!((response[COMBS[0]] - INPUT_GAIN * WET_SCALE).abs() < 1e-7);
+
+        // tail decays
+        let early = energy(&response[4410..22050]);
+        let late = energy(&response[66150..]);
+
+        assert!(early > late * 10.0, "{early} {late}");
+        assert!(response.iter().all(| s | s.is_finite()));
+    }
+
+    #[test]
+    fn reference_impulse_response() {
+        // every delay line is a hundred times shorter than at tuning rate
+        let mut reverb = Reverb::new(441, 1);
+
+        let reference = [
+            1.000000, 0.000000, 0.000000, 0.000000, 0.000000, 0.000000, 0.000000, 0.000000,
+            0.000000, 0.000000, 0.000000, 0.015000, 0.015000, 0.000000, 0.000000, -0.037500,
+            -0.022500, -0.063750, -0.063750, 0.009375, -0.009375, 0.128438, 0.086017, 0.084985,
+            0.075872, -0.018202, 0.028115, -0.076901, -0.065199, -0.024275, -0.025489, -0.028564,
+            -0.029221, -0.025642, -0.033058, -0.027581, -0.013295, 0.018331, 0.036503, -0.008417,
+            0.028190, -0.022346, 0.047125, 0.031188, -0.028145, -0.065022, -0.018242, 0.023126,
+            -0.030598, 0.015599, -0.007725, -0.013132, -0.009840, 0.041297, -0.002510, 0.033440,
+            0.016128, 0.030283, 0.017508, 0.004872, -0.012873, -0.031861, -0.018195, 0.054032
+        ];
+
+        assert_response(&impulse_response(&mut reverb, 1, reference.len()), &reference);
+    }
+
+    #[test]
+    fn room_size_and_reset() {
+        let tail = | room_size: f32 | {
+            let mut reverb = Reverb::new(48000, 1);
+
+            reverb.set_room_size(room_size);
+            reverb.set_dry(0.0);
+
+            energy(&impulse_response(&mut reverb, 1, 48000)[24000..])
+        };
+
+        assert!(tail(0.9) > tail(0.2));
+
+        let mut reverb = Reverb::new(48000, 2);
+        let mut samples = vec![1.0; 2000];
+
+        reverb.process(&mut samples);
+        reverb.reset();
+        reverb.set_dry(0.0);
+
+        let response = impulse_response(&mut reverb, 2, 1000);
+
+        assert!(response.iter().all(| &s | s == 0.0));
+    }
+
+    #[test]
+    fn channels_are_decorrelated() {
+        let mut reverb = Reverb::new(48000, 2);
+        let mut samples = vec![0.0; 2 * 48000];
+
+        reverb.set_dry(0.0);
+        samples[..2].fill(1.0);
+        reverb.process(&mut samples);
+
+        let difference: f32 = samples.chunks_exact(2).map(| f | (f[0] - f[1]).abs()).sum();
+
+        assert!(difference > 1.0);
+    }
+}
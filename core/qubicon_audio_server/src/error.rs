@@ -1,24 +1,219 @@
+use core::{ ffi::CStr, fmt::{ self, Display } };
 use libpulse_sys::*;
 use thiserror::Error;
 
-// TODO: Change this shit. These errors dont tell any information at all
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+/// Backend neutral category of an error. Use it to react on errors without looking at PulseAudio codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    ConnectionRefused,
+    ConnectionTerminated,
+    Timeout,
+    AccessDenied,
+    InvalidArgument,
+    NotFound,
+    AlreadyExists,
+    NotSupported,
+    /// Object is not in state, what allows the call. E.g. stream is still connecting
+    BadState,
+    /// Requested data is not available yet. E.g. timing info before first update
+    NoData,
+    Busy,
+    Other
+}
+
+impl From<pa_error_code_t> for ErrorKind {
+    fn from(value: pa_error_code_t) -> Self {
+        use pa_error_code_t::*;
+
+        match value {
+            ConnectionRefused | InvalidServer => Self::ConnectionRefused,
+            ConnectionTerminated | Killed | Forked => Self::ConnectionTerminated,
+            Timeout => Self::Timeout,
+            Access | AuthKey => Self::AccessDenied,
+            Invalid | TooLarge => Self::InvalidArgument,
+            NoEntity => Self::NotFound,
+            Exist => Self::AlreadyExists,
+            NotSupported | NotImplemented | NoExtension | Obsolete | Version => Self::NotSupported,
+            BadState => Self::BadState,
+            NoData => Self::NoData,
+            Busy => Self::Busy,
+            _ => Self::Other
+        }
+    }
+}
+
+/// Error code, reported by PulseAudio. Displayed as its `pa_strerror` message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PulseError(pa_error_code_t);
+
+impl PulseError {
+    pub fn new(code: pa_error_code_t) -> Self {
+        Self(code)
+    }
+
+    pub fn code(&self) -> pa_error_code_t {
+        self.0
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.0.into()
+    }
+
+    pub fn message(&self) -> &'static str {
+        unsafe {
+            let message = pa_strerror(self.0 as i32);
+
+            match message.is_null() {
+                true => "unknown error",
+                // strings are static in libpulse
+                false => CStr::from_ptr(message).to_str().unwrap_or("unknown error")
+            }
+        }
+    }
+}
+
+impl Display for PulseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:?})", self.message(), self.0)
+    }
+}
+
+impl std::error::Error for PulseError {}
+
+impl From<pa_error_code_t> for PulseError {
+    fn from(value: pa_error_code_t) -> Self {
+        Self::new(value)
+    }
+}
+
+/// What stream was doing, when error happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StreamOperation {
+    Connect,
+    Write,
+    Read,
+    SetVolume,
+    SetMute,
+    Cork,
+    Flush,
+    Drain,
+    Trigger,
+    QueryTiming
+}
+
+impl Display for StreamOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(
+            match self {
+                Self::Connect => "connect",
+                Self::Write => "write",
+                Self::Read => "read",
+                Self::SetVolume => "set volume",
+                Self::SetMute => "set mute",
+                Self::Cork => "cork",
+                Self::Flush => "flush",
+                Self::Drain => "drain",
+                Self::Trigger => "trigger",
+                Self::QueryTiming => "query timing"
+            }
+        )
+    }
+}
+
+/// Message of PulseAudio error is not included, it is given by [`std::error::Error::source`]
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
 pub enum Error {
-    #[error("context connection failed. {pa_error:?}")]
-    ContextConnectionFailed { pa_error: pa_error_code_t },
+    #[error("context connection failed")]
+    ContextConnectionFailed { source: PulseError },
 
     #[error("context is in bad state. {ctx_state:?}")]
     ContextBadState { ctx_state: pa_context_state_t },
 
-    #[error("context update failed. {pa_error:?}")]
-    ContextUpdateFailed { pa_error: pa_error_code_t },
-    
-    #[error("stream state is not ready, but {stream_state:?}")]
-    StreamIsNotReady { stream_state: pa_stream_state_t },
+    #[error("context update failed")]
+    ContextUpdateFailed { source: PulseError },
+
+    #[error("stream \"{stream}\" is not ready, but {stream_state:?}")]
+    StreamIsNotReady { stream: String, stream_state: pa_stream_state_t },
+
+    #[error("stream \"{stream}\" failed to {operation}")]
+    StreamOperationFailed { stream: String, operation: StreamOperation, source: PulseError },
+
+    #[error("proplist edit error")]
+    ProplistEditError { source: PulseError },
+
+    #[error("server operation failed")]
+    OperationFailed { source: PulseError }
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::ContextBadState { .. } => ErrorKind::ConnectionTerminated,
+            Self::StreamIsNotReady { .. } => ErrorKind::BadState,
+
+            Self::ContextConnectionFailed { source } |
+            Self::ContextUpdateFailed { source } |
+            Self::StreamOperationFailed { source, .. } |
+            Self::ProplistEditError { source } |
+            Self::OperationFailed { source } => source.kind()
+        }
+    }
+
+    /// Name of the stream, what caused this error
+    pub fn stream_name(&self) -> Option<&str> {
+        match self {
+            Self::StreamIsNotReady { stream, .. } |
+            Self::StreamOperationFailed { stream, .. } => Some(stream),
+            _ => None
+        }
+    }
+
+    /// Code, reported by PulseAudio, if there is one
+    pub fn pulse_error(&self) -> Option<PulseError> {
+        match self {
+            Self::ContextConnectionFailed { source } |
+            Self::ContextUpdateFailed { source } |
+            Self::StreamOperationFailed { source, .. } |
+            Self::ProplistEditError { source } |
+            Self::OperationFailed { source } => Some(*source),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libpulse_sys::*;
+    use super::{ Error, ErrorKind, PulseError, StreamOperation };
+
+    #[test]
+    fn error_kind() {
+        let error = Error::StreamOperationFailed {
+            stream: "music".into(),
+            operation: StreamOperation::Drain,
+            source: PulseError::new(pa_error_code_t::Timeout)
+        };
+
+        assert_eq!(error.kind(), ErrorKind::Timeout);
+        assert_eq!(error.stream_name(), Some("music"));
+        assert_eq!(error.pulse_error().map(| e | e.code()), Some(pa_error_code_t::Timeout));
+
+        let error = Error::ContextConnectionFailed { source: pa_error_code_t::ConnectionRefused.into() };
+
+        assert_eq!(error.kind(), ErrorKind::ConnectionRefused);
+        assert_eq!(error.stream_name(), None);
+
+        // message of the source is printed only once in error chain
+        assert_eq!(error.to_string(), "context connection failed");
+        assert!(std::error::Error::source(&error).is_some());
+
+        let error = Error::StreamIsNotReady { stream: "voice".into(), stream_state: pa_stream_state_t::Creating };
 
-    #[error("stream write error. {pa_error:?}")]
-    StreamWriteError { pa_error: pa_error_code_t },
+        assert_eq!(error.kind(), ErrorKind::BadState);
+        assert_eq!(error.pulse_error(), None);
 
-    #[error("proplist edit error. {pa_error:?}")]
-    ProplistEditError { pa_error: pa_error_code_t }
-}
\ No newline at end of file
+        assert_eq!(ErrorKind::from(pa_error_code_t::Access), ErrorKind::AccessDenied);
+        assert_eq!(ErrorKind::from(pa_error_code_t::NoEntity), ErrorKind::NotFound);
+        assert_eq!(ErrorKind::from(pa_error_code_t::Protocol), ErrorKind::Other);
+    }
+}
@@ -1,32 +1,90 @@
-use std::{ffi::CString, mem::MaybeUninit, pin::Pin};
+use std::pin::Pin;
 
 mod raw;
+mod builder;
 pub mod error;
+pub mod asset;
+pub mod backend;
+pub mod capture;
+pub mod convert;
+pub mod effect;
+pub mod meter;
+pub mod mixer;
+pub mod ring_buffer;
+pub mod sample;
+pub mod spatial;
 
-use raw::{Format, PlaybackStream, PulseContext};
-
-pub use error::Error;
-pub use raw::{ Proplist, UpdateMode, ChannelMap, ChannelPosition, StreamFlags, BufferAttributes };
+pub use error::{ Error, ErrorKind, PulseError, StreamOperation };
+pub use builder::AudioServerBuilder;
+pub use backend::{ Backend, PulseBackend, NullBackend };
+pub use ring_buffer::{ RingProducer, RingConsumer };
+pub use sample::Sample;
+pub use raw::{ properties, Proplist, ProplistKeys, MediaRole, UpdateMode, ChannelMap, ChannelPosition, StreamFlags, BufferAttributes, MainloopMode };
+pub use raw::{ Format, PlaybackStream, RecordStream, StreamRead, StreamWrite, ReadResult, TimingInfo };
+pub use raw::Operation;
+pub use raw::{ SampleFormat, SampleSpec, DeviceKind, DeviceInfo, ServerInfo, DeviceEvent };
 pub type Result<T> = core::result::Result<T, Error>;
 
-pub struct AudioServer {
-    data: Pin<Box<PulseContext>>
+pub struct AudioServer<B: Backend = PulseBackend> {
+    backend: B
 }
 
 impl AudioServer {
-    // TODO: Add app name
+    /// Connects with name of current executable. Use [`Self::builder`] to set application identity
     pub fn init() -> Result<Self> {
-        // assume_init on Box is currently night only
-        #[allow(invalid_value, clippy::uninit_assumed_init)]
-        let mut data = Box::pin(
-            unsafe {
-                MaybeUninit::<PulseContext>::uninit().assume_init()
-            }
-        );
+        Self::init_with_mainloop(MainloopMode::Polled)
+    }
+
+    pub fn builder(app_name: &str) -> AudioServerBuilder {
+        AudioServerBuilder::new(app_name)
+    }
+
+    /// With [`MainloopMode::Threaded`] [`Self::update`] is not required,
+    /// and stream callbacks are called from PulseAudio thread
+    pub fn init_with_mainloop(mode: MainloopMode) -> Result<Self> {
+        Ok ( Self { backend: PulseBackend::init(mode)? } )
+    }
+
+    /// False, while connection is lost and server waits for PulseAudio to come back
+    pub fn is_connected(&self) -> bool {
+        self.backend.is_connected()
+    }
 
-        unsafe { data.as_mut().init(&CString::new("test").unwrap()) }?;
+    /// All sinks, what playback streams can be connected to
+    /// Must not be called from device callback, see [`PulseBackend::subscribe_devices`]
+    pub fn sinks(&self) -> Result<Vec<DeviceInfo>> {
+        self.backend.sinks()
+    }
+
+    /// All sources, including monitors of sinks
+    /// Must not be called from device callback, see [`PulseBackend::subscribe_devices`]
+    pub fn sources(&self) -> Result<Vec<DeviceInfo>> {
+        self.backend.sources()
+    }
 
-        Ok ( Self { data } )
+    /// Contains names of default sink and source
+    /// Must not be called from device callback, see [`PulseBackend::subscribe_devices`]
+    pub fn server_info(&self) -> Result<ServerInfo> {
+        self.backend.server_info()
+    }
+
+    /// See [`PulseBackend::subscribe_devices`]
+    pub fn subscribe_devices(&mut self, callback: impl FnMut(DeviceEvent) + Send + 'static) -> Result<()> {
+        self.backend.subscribe_devices(callback)
+    }
+
+    pub fn unsubscribe_devices(&mut self) -> Result<()> {
+        self.backend.unsubscribe_devices()
+    }
+}
+
+impl<B: Backend> AudioServer<B> {
+    pub fn with_backend(backend: B) -> Self {
+        Self { backend }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
     }
 
     pub fn create_stream<F: Format>(
@@ -37,22 +95,88 @@ impl AudioServer {
         flags: StreamFlags,
         properties: Option<&Proplist>,
         buffer_attributes: Option<&BufferAttributes>
-    ) -> Result<Pin<Box<PlaybackStream<F>>>> {
-        raw::with_c_string(name, | name | {
-            self.data.create_new_playback_stream(name, rate, channel_map, flags, properties, buffer_attributes)
-        })
+    ) -> Result<Pin<Box<B::PlaybackStream<F>>>> {
+        self.backend.create_playback_stream(name, None, rate, channel_map, flags, properties, buffer_attributes)
     }
 
+    /// Same as [`Self::create_stream`], but stream is connected to sink with [`DeviceInfo::name`]
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_stream_on_device<F: Format>(
+        &self,
+        device: &str,
+        name: &str,
+        rate: u32,
+        channel_map: &ChannelMap,
+        flags: StreamFlags,
+        properties: Option<&Proplist>,
+        buffer_attributes: Option<&BufferAttributes>
+    ) -> Result<Pin<Box<B::PlaybackStream<F>>>> {
+        self.backend.create_playback_stream(name, Some(device), rate, channel_map, flags, properties, buffer_attributes)
+    }
+
+    pub fn create_record_stream<F: Format>(
+        &self,
+        name: &str,
+        rate: u32,
+        channel_map: &ChannelMap,
+        flags: StreamFlags,
+        properties: Option<&Proplist>,
+        buffer_attributes: Option<&BufferAttributes>
+    ) -> Result<Pin<Box<B::RecordStream<F>>>> {
+        self.backend.create_record_stream(name, None, rate, channel_map, flags, properties, buffer_attributes)
+    }
+
+    /// Same as [`Self::create_record_stream`], but stream is connected to source with [`DeviceInfo::name`]
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_record_stream_on_device<F: Format>(
+        &self,
+        device: &str,
+        name: &str,
+        rate: u32,
+        channel_map: &ChannelMap,
+        flags: StreamFlags,
+        properties: Option<&Proplist>,
+        buffer_attributes: Option<&BufferAttributes>
+    ) -> Result<Pin<Box<B::RecordStream<F>>>> {
+        self.backend.create_record_stream(name, Some(device), rate, channel_map, flags, properties, buffer_attributes)
+    }
+
+    /// Record stream with [`StreamFlags::PEAK_DETECT`]. PulseAudio sends `rate` peaks per second instead of audio,
+    /// so it is cheap enough to keep open for level indicators. Read it with [`meter::Meter::read_stream`].
+    /// RMS of such stream is RMS of peaks, so it is only approximate.
+    ///
+    /// `device` is name of source. Use monitor source of sink to meter playback, None for default source
+    pub fn create_peak_stream(
+        &self,
+        name: &str,
+        device: Option<&str>,
+        rate: u32,
+        channel_map: &ChannelMap
+    ) -> Result<Pin<Box<B::RecordStream<f32>>>> {
+        let mut flags = StreamFlags::PEAK_DETECT | StreamFlags::ADJUST_LATENCY;
+
+        if device.is_some() {
+            flags |= StreamFlags::DONT_MOVE;
+        }
+
+        // every peak is delivered as soon as it is ready
+        let frame_size = (size_of::<f32>() * channel_map.len()) as u32;
+        let attributes = BufferAttributes::new(u32::MAX, u32::MAX, u32::MAX, u32::MAX, frame_size);
+
+        self.backend.create_record_stream(name, device, rate, channel_map, flags, None, Some(&attributes))
+    }
+
+    /// Processes pending events. Does nothing in threaded mode
     pub fn update(&self) -> Result<()> {
-        self.data.update()
+        self.backend.update()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::ChannelMap;
-    use super::AudioServer;
-    use super::raw::StreamWrite;
+    use super::{ AudioServer, Error, MainloopMode, StreamFlags, BufferAttributes };
+    use super::{ StreamWrite, StreamRead, ReadResult };
 
     #[test]
     fn init_test() {
@@ -60,6 +184,12 @@ mod tests {
             .expect("failed to init audio server");
     }
 
+    #[test]
+    fn threaded_init_test() {
+        AudioServer::init_with_mainloop(MainloopMode::Threaded)
+            .expect("failed to init audio server");
+    }
+
     #[test]
     fn stream_creation_test() {
         let server = AudioServer::init()
@@ -76,7 +206,7 @@ mod tests {
 
         let mut total_x = 0usize;
 
-        loop {
+        while total_x < 44100 {
             server.update().unwrap();
 
             if let Ok(ammount) = stream.as_ref().available_len() {
@@ -96,4 +226,224 @@ mod tests {
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn record_stream_test() {
+        let server = AudioServer::init()
+            .expect("failed to init audio server");
+        let mut stream = server.create_record_stream::<i16>(
+            "test",
+            44100,
+            &ChannelMap::stereo(),
+            Default::default(),
+            None,
+            None
+        )
+            .expect("failed to create stream");
+
+        let mut buf = [0i16; 4096];
+        let mut total_read = 0usize;
+
+        while total_read < 44100 * 2 {
+            server.update().unwrap();
+
+            match stream.as_mut().read(&mut buf) {
+                Ok(ReadResult::Data(ammount)) => {
+                    assert_eq!(ammount % 2, 0, "read should return whole frames");
+
+                    total_read += ammount;
+                },
+                Ok(ReadResult::Hole(ammount)) => total_read += ammount,
+                // stream is not ready yet
+                Err(Error::StreamIsNotReady { .. }) => continue,
+                Err(e) => panic!("failed to read from stream. {e}")
+            }
+        }
+    }
+
+    #[test]
+    fn buffered_stream_test() {
+        let server = AudioServer::init()
+            .expect("failed to init audio server");
+        // requests are never bigger than the ring buffer, 2048 of 4096 samples
+        let attributes = BufferAttributes::new(u32::MAX, 2048 * 4, u32::MAX, u32::MAX, u32::MAX);
+        let mut stream = server.create_stream::<f32>(
+            "test",
+            44100,
+            &ChannelMap::mono(),
+            Default::default(),
+            None,
+            Some(&attributes)
+        )
+            .expect("failed to create stream");
+
+        let mut producer = stream.as_mut().start_buffered(4096)
+            .expect("failed to start buffered mode");
+        let mut total_x = 0usize;
+
+        let wave = | range: core::ops::Range<usize> | range
+            .map(| x | (x as f32 / 44100.0 * 100.0).sin() * 10.0)
+            .collect::<Vec<f32>>();
+
+        // buffer is full before the first request
+        total_x += producer.push(&wave(0..producer.free_len()));
+
+        while total_x < 44100 {
+            server.update().unwrap();
+
+            total_x += producer.push(&wave(total_x..total_x + producer.free_len()));
+        }
+
+        assert_eq!(stream.underflow_count(), 0);
+        assert_eq!(stream.overflow_count(), 0);
+    }
+
+    #[test]
+    fn threaded_buffered_stream_test() {
+        let server = AudioServer::init_with_mainloop(MainloopMode::Threaded)
+            .expect("failed to init audio server");
+        let mut stream = server.create_stream::<f32>(
+            "test",
+            44100,
+            &ChannelMap::mono(),
+            Default::default(),
+            None,
+            None
+        )
+            .expect("failed to create stream");
+
+        let mut producer = stream.as_mut().start_buffered(4096)
+            .expect("failed to start buffered mode");
+        let mut total_x = 0usize;
+
+        // no update calls, samples are consumed from PulseAudio thread
+        while total_x < 44100 {
+            let buf: Vec<f32> = (total_x..(total_x + producer.free_len()))
+                .map(| x | (x as f32 / 44100.0 * 100.0).sin() * 10.0)
+                .collect();
+
+            total_x += producer.push(&buf);
+
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn devices_test() {
+        let server = AudioServer::init()
+            .expect("failed to init audio server");
+        let sinks = server.sinks()
+            .expect("failed to list sinks");
+        let sources = server.sources()
+            .expect("failed to list sources");
+        let info = server.server_info()
+            .expect("failed to get server info");
+
+        if let Some(default_sink) = info.default_sink {
+            assert!(sinks.iter().any(| s | s.name == default_sink), "default sink is not listed");
+
+            let stream = server.create_stream_on_device::<f32>(
+                &default_sink,
+                "test",
+                44100,
+                &ChannelMap::stereo(),
+                Default::default(),
+                None,
+                None
+            );
+
+            assert!(stream.is_ok(), "failed to create stream on default sink");
+        }
+        if let Some(default_source) = info.default_source {
+            assert!(sources.iter().any(| s | s.name == default_source), "default source is not listed");
+        }
+    }
+
+    #[test]
+    fn stream_controls_test() {
+        let server = AudioServer::init_with_mainloop(MainloopMode::Threaded)
+            .expect("failed to init audio server");
+        let mut stream = server.create_stream::<f32>(
+            "test",
+            44100,
+            &ChannelMap::stereo(),
+            Default::default(),
+            None,
+            None
+        )
+            .expect("failed to create stream");
+
+        while let Err(Error::StreamIsNotReady { .. }) = stream.as_ref().available_len() {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+
+        stream.set_volume(&[0.5, 0.25]).unwrap().wait().expect("failed to set volume");
+        stream.set_muted(true).unwrap().wait().expect("failed to mute");
+        stream.set_muted(false).unwrap().wait().expect("failed to unmute");
+
+        stream.pause().unwrap().wait().expect("failed to pause");
+        assert!(stream.is_paused());
+
+        stream.resume().unwrap().wait().expect("failed to resume");
+        assert!(!stream.is_paused());
+
+        let ammount = stream.as_ref().available_len().unwrap().min(4410 * 2);
+
+        stream.as_mut().write(&vec![0.0; ammount]).unwrap();
+        stream.trigger().unwrap().wait().expect("failed to trigger");
+        stream.drain().unwrap().wait().expect("failed to drain");
+        stream.flush().unwrap().wait().expect("failed to flush");
+    }
+
+    #[test]
+    fn timing_test() {
+        use std::sync::{ Arc, atomic::{ AtomicBool, Ordering } };
+
+        let server = AudioServer::init_with_mainloop(MainloopMode::Threaded)
+            .expect("failed to init audio server");
+        let mut stream = server.create_stream::<f32>(
+            "test",
+            44100,
+            &ChannelMap::mono(),
+            StreamFlags::INTERPOLATE_TIMING | StreamFlags::AUTO_TIMING_UPDATE,
+            None,
+            None
+        )
+            .expect("failed to create stream");
+
+        let updated = Arc::new(AtomicBool::new(false));
+        let updated_in_callback = updated.clone();
+
+        stream.as_mut().set_timing_callback(move | _ | updated_in_callback.store(true, Ordering::Relaxed))
+            .expect("failed to set timing callback");
+
+        while let Err(Error::StreamIsNotReady { .. }) = stream.as_ref().available_len() {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+
+        let ammount = stream.as_ref().available_len().unwrap();
+
+        stream.as_mut().write(&vec![0.0; ammount]).unwrap();
+        stream.update_timing().unwrap().wait().expect("failed to update timing");
+
+        assert!(updated.load(Ordering::Relaxed), "timing callback was not called");
+        assert!(stream.timing_info().is_some());
+        stream.latency().expect("failed to get latency");
+
+        let position = stream.playback_position().expect("failed to get position");
+
+        assert!(position < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn builder_test() {
+        let server = AudioServer::builder("qubicon test")
+            .icon_name("applications-games")
+            .role("game")
+            .mainloop(MainloopMode::Threaded)
+            .build()
+            .expect("failed to init audio server");
+
+        assert!(server.is_connected());
+    }
+}
@@ -0,0 +1,290 @@
+//! Peak and RMS metering. [`Meter`] can be inserted into a mixer bus as an effect,
+//! or fed from a record stream, e.g. one made by [`crate::AudioServer::create_peak_stream`]
+
+use std::{ pin::Pin, sync::{ Arc, Mutex }, time::Duration };
+
+use arrayvec::ArrayVec;
+use libpulse_sys::PA_CHANNELS_MAX;
+
+use crate::{ Result, StreamRead, ReadResult };
+use crate::effect::{ Effect, gain_to_db };
+
+// window is split into this ammount of blocks. Levels move by one block at a time
+const BLOCKS: usize = 8;
+
+/// Level of one channel over meter window. Both are linear, 1 is full scale
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ChannelLevel {
+    pub peak: f32,
+    pub rms: f32
+}
+
+impl ChannelLevel {
+    pub fn peak_db(&self) -> f32 {
+        gain_to_db(self.peak)
+    }
+
+    pub fn rms_db(&self) -> f32 {
+        gain_to_db(self.rms)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Levels {
+    pub channels: ArrayVec<ChannelLevel, {PA_CHANNELS_MAX as usize}>
+}
+
+impl Levels {
+    /// Loudest peak of all channels
+    pub fn peak(&self) -> f32 {
+        self.channels.iter().fold(0.0, | p, c | p.max(c.peak))
+    }
+
+    /// Highest RMS of all channels
+    pub fn rms(&self) -> f32 {
+        self.channels.iter().fold(0.0, | r, c | r.max(c.rms))
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Block {
+    peak: f32,
+    square_sum: f32
+}
+
+/// Measures peak and RMS of every channel over a sliding window
+pub struct Meter {
+    channels: usize,
+    block_len: usize,
+
+    // BLOCKS finished blocks of every channel, block-major
+    blocks: Vec<Block>,
+    block: usize,
+    filled_blocks: usize,
+    // block, what is being accumulated
+    current: Vec<Block>,
+    current_len: usize,
+
+    // read buffer for streams
+    scratch: Vec<f32>,
+    shared: Arc<Mutex<Levels>>
+}
+
+impl Meter {
+    /// 300 ms window is close to VU meters, 50 ms reacts fast enough for voice activity indicators
+    pub fn new(rate: u32, channels: usize, window: Duration) -> Self {
+        if channels == 0 || channels > PA_CHANNELS_MAX as usize {
+            panic!("meter should have from 1 to {PA_CHANNELS_MAX} channels");
+        }
+
+        let window_len = (window.as_secs_f64() * rate as f64).round() as usize;
+
+        Self {
+            channels,
+            block_len: (window_len / BLOCKS).max(1),
+
+            blocks: vec![Block::default(); BLOCKS * channels],
+            block: 0,
+            filled_blocks: 0,
+            current: vec![Block::default(); channels],
+            current_len: 0,
+
+            scratch: Vec::new(),
+            shared: Arc::default()
+        }
+    }
+
+    /// Handle, what reads levels from another thread. Levels are updated after every processed buffer
+    pub fn reader(&self) -> MeterReader {
+        MeterReader { shared: self.shared.clone() }
+    }
+
+    /// Measures interleaved frames. Incomplete frame at the end is ignored
+    pub fn process(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (block, &sample) in self.current.iter_mut().zip(frame) {
+                block.peak = block.peak.max(sample.abs());
+                block.square_sum += sample * sample;
+            }
+
+            self.current_len += 1;
+
+            if self.current_len == self.block_len {
+                let start = self.block * self.channels;
+
+                self.blocks[start..start + self.channels].copy_from_slice(&self.current);
+                self.current.fill(Block::default());
+                self.current_len = 0;
+
+                self.block = (self.block + 1) % BLOCKS;
+                self.filled_blocks = (self.filled_blocks + 1).min(BLOCKS);
+            }
+        }
+
+        // audio thread should not wait for readers. They'll get next update
+        if let Ok(mut shared) = self.shared.try_lock() {
+            *shared = self.levels();
+        }
+    }
+
+    /// Reads everything, what is available in record stream, and measures it. Holes are skipped.
+    /// Returns ammount of measured samples
+    pub fn read_stream<S: StreamRead<f32> + ?Sized>(&mut self, mut stream: Pin<&mut S>) -> Result<usize> {
+        let mut total = 0;
+
+        loop {
+            let len = stream.as_ref().available_len()?;
+
+            if len == 0 {
+                return Ok ( total );
+            }
+
+            let mut scratch = core::mem::take(&mut self.scratch);
+            scratch.resize(len, 0.0);
+
+            let result = stream.as_mut().read(&mut scratch);
+
+            if let Ok(ReadResult::Data(ammount)) = result {
+                self.process(&scratch[..ammount]);
+                total += ammount;
+            }
+
+            self.scratch = scratch;
+
+            match result? {
+                ReadResult::Data(0) => return Ok ( total ),
+                _ => continue
+            }
+        }
+    }
+
+    pub fn levels(&self) -> Levels {
+        let len = (self.filled_blocks * self.block_len + self.current_len).max(1);
+
+        let channels = (0..self.channels)
+            .map(| c | {
+                let blocks = self.blocks.iter()
+                    .skip(c)
+                    .step_by(self.channels)
+                    .take(self.filled_blocks)
+                    .chain(core::iter::once(&self.current[c]));
+
+                let (peak, square_sum) = blocks.fold((0.0f32, 0.0), | (p, s), b | (p.max(b.peak), s + b.square_sum));
+
+                ChannelLevel { peak, rms: (square_sum / len as f32).sqrt() }
+            })
+            .collect();
+
+        Levels { channels }
+    }
+
+    pub fn reset(&mut self) {
+        self.blocks.fill(Block::default());
+        self.current.fill(Block::default());
+        self.block = 0;
+        self.filled_blocks = 0;
+        self.current_len = 0;
+
+        *self.shared.lock().unwrap() = Levels::default();
+    }
+}
+
+/// Passes signal through unchanged
+impl Effect for Meter {
+    fn process(&mut self, samples: &mut [f32]) {
+        Meter::process(self, samples);
+    }
+
+    fn reset(&mut self) {
+        Meter::reset(self);
+    }
+}
+
+/// Levels of [`Meter`], what can be read from any thread
+#[derive(Clone)]
+pub struct MeterReader {
+    shared: Arc<Mutex<Levels>>
+}
+
+impl MeterReader {
+    pub fn levels(&self) -> Levels {
+        self.shared.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{ AudioServer, ChannelMap, ChannelPosition, NullBackend };
+    use crate::mixer::{ Mixer, Generator };
+    use super::Meter;
+
+    fn stereo() -> ChannelMap {
+        ChannelMap::from_positions(&[ChannelPosition::FrontLeft, ChannelPosition::FrontRight])
+    }
+
+    #[test]
+    fn peak_and_rms() {
+        let mut meter = Meter::new(1000, 2, Duration::from_millis(800));
+
+        // full scale square on the left, half scale sine on the right
+        let samples: Vec<f32> = (0..800)
+            .flat_map(| i | [if i % 2 == 0 { 1.0 } else { -1.0 }, (i as f32 * 0.1).sin() * 0.5])
+            .collect();
+
+        meter.process(&samples);
+
+        let levels = meter.levels();
+
+        assert_eq!(levels.channels[0].peak, 1.0);
+        assert!((levels.channels[0].rms - 1.0).abs() < 1e-4);
+        assert!((levels.channels[1].peak - 0.5).abs() < 1e-3);
+        assert!((levels.channels[1].rms - 0.5 * core::f32::consts::FRAC_1_SQRT_2).abs() < 1e-2);
+        assert!(levels.channels[0].peak_db().abs() < 1e-4);
+        assert_eq!(levels.peak(), 1.0);
+    }
+
+    #[test]
+    fn window_slides() {
+        let mut meter = Meter::new(100, 1, Duration::from_millis(80));
+
+        meter.process(&[1.0]);
+        assert_eq!(meter.levels().channels[0].peak, 1.0);
+
+        // 8 blocks of one frame later, peak is out of window
+        meter.process(&[0.1; 8]);
+        assert!((meter.levels().channels[0].peak - 0.1).abs() < 1e-6);
+
+        meter.reset();
+        assert_eq!(meter.levels().channels[0].peak, 0.0);
+    }
+
+    #[test]
+    fn meters_bus_and_stream() {
+        let mut mixer = Mixer::new(1000, stereo());
+        let meter = Meter::new(1000, 2, Duration::from_millis(100));
+        let reader = meter.reader();
+
+        mixer.master_effects().push(meter);
+        mixer.play(Generator::new(stereo(), | out | { out.fill(0.25); out.len() }), Default::default());
+
+        let mut out = [0.0; 200];
+        mixer.mix(&mut out);
+
+        // signal passes unchanged
+        assert_eq!(out, [0.25; 200]);
+        assert_eq!(reader.levels().channels[1].rms, 0.25);
+
+        let server = AudioServer::with_backend(NullBackend::new());
+        let mut stream = server.create_peak_stream("peaks", None, 100, &stereo()).unwrap();
+        let mut meter = Meter::new(100, 2, Duration::from_millis(100));
+
+        stream.feed(&[0.5, 0.25, 0.75, 0.125]);
+        server.backend().advance(Duration::from_millis(20));
+
+        assert_eq!(meter.read_stream(stream.as_mut()).unwrap(), 4);
+        assert_eq!(meter.levels().channels[0].peak, 0.75);
+        assert_eq!(meter.levels().channels[1].peak, 0.25);
+    }
+}
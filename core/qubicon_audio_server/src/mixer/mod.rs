@@ -0,0 +1,829 @@
+//! Software mixer. Mixes any number of voices into one interleaved f32 output,
+//! what is usually pushed into [`crate::PlaybackStream`]
+
+use std::time::Duration;
+
+use crate::{ChannelMap, RingProducer};
+use crate::convert::{Resampled, ResamplerQuality};
+use crate::effect::{Effect, EffectChain, lanes};
+use voice::{Voice, Fade};
+
+pub use source::{Source, SampleBuffer, BufferSource, Generator};
+pub use schedule::{StartAt, SequencerClock};
+
+mod voice;
+mod source;
+mod schedule;
+
+const MIN_PITCH: f32 = 0.125;
+const MAX_PITCH: f32 = 8.0;
+const DEFAULT_BPM: f64 = 120.0;
+
+/// Handle to voice, playing in [`Mixer`]. Becomes stale, when voice finishes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VoiceId {
+    index: u32,
+    generation: u32
+}
+
+/// Handle to bus of [`Mixer`]. Buses are never removed, so it is valid for the whole life of mixer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BusId(u32);
+
+/// Initial parameters of a voice
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoiceSettings {
+    /// Linear gain
+    pub gain: f32,
+    /// -1 is full left, 1 is full right
+    pub pan: f32,
+    /// Playback speed, what also changes pitch. 1 is original speed
+    pub pitch: f32,
+    pub looping: bool,
+    pub paused: bool,
+    pub fade_in: Option<Duration>,
+    /// None plays voice straight into master bus
+    pub bus: Option<BusId>
+}
+
+impl Default for VoiceSettings {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            pan: 0.0,
+            pitch: 1.0,
+            looping: false,
+            paused: false,
+            fade_in: None,
+            bus: None
+        }
+    }
+}
+
+struct Slot {
+    generation: u32,
+    voice: Option<Voice>
+}
+
+// voices are mixed into it, processed by its effects and then added to master bus
+struct Bus {
+    effects: EffectChain,
+    buffer: Vec<f32>
+}
+
+pub struct Mixer {
+    rate: u32,
+    channel_map: ChannelMap,
+
+    slots: Vec<Slot>,
+    buses: Vec<Bus>,
+
+    master_gain: f32,
+    last_master_gain: f32,
+    master_effects: EffectChain,
+
+    // frames mixed since creation
+    position: u64,
+    sequencer: SequencerClock,
+
+    // reused between mix calls to avoid allocations
+    scratch: Vec<f32>,
+    // output of fill, reused for the same reason
+    fill_buffer: Vec<f32>
+}
+
+impl Mixer {
+    pub fn new(rate: u32, channel_map: ChannelMap) -> Self {
+        if channel_map.is_empty() {
+            panic!("channel map should contain at least one channel");
+        }
+
+        Self {
+            rate,
+            channel_map,
+
+            slots: Vec::new(),
+            buses: Vec::new(),
+
+            master_gain: 1.0,
+            last_master_gain: 1.0,
+            master_effects: EffectChain::new(),
+
+            position: 0,
+            sequencer: SequencerClock::new(rate, DEFAULT_BPM),
+
+            scratch: Vec::new(),
+            fill_buffer: Vec::new()
+        }
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    pub fn channel_map(&self) -> &ChannelMap {
+        &self.channel_map
+    }
+
+    /// Ammount of frames, what were mixed since creation. Clock of [`StartAt`].
+    /// Mixed frames are heard after latency of the stream, but distance between them stays exact
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Converts beats of [`StartAt::Beat`]. 120 bpm by default
+    pub fn sequencer(&self) -> &SequencerClock {
+        &self.sequencer
+    }
+
+    /// Changes tempo from current position
+    pub fn set_tempo(&mut self, bpm: f64) {
+        self.sequencer.set_bpm(bpm, self.position);
+    }
+
+    pub fn master_gain(&self) -> f32 {
+        self.master_gain
+    }
+
+    /// Linear gain of master bus, applied after all voices are mixed
+    pub fn set_master_gain(&mut self, gain: f32) {
+        self.master_gain = gain.max(0.0);
+    }
+
+    /// Effects of master bus. They are applied after master gain, so limiter should be the last one
+    pub fn master_effects(&mut self) -> &mut EffectChain {
+        &mut self.master_effects
+    }
+
+    /// Adds bus with its own effects. Voices, played into it, are mixed together before effects
+    pub fn add_bus(&mut self) -> BusId {
+        self.buses.push(Bus { effects: EffectChain::new(), buffer: Vec::new() });
+
+        BusId(self.buses.len() as u32 - 1)
+    }
+
+    /// Panics, if bus is from another mixer
+    pub fn bus_effects(&mut self, bus: BusId) -> &mut EffectChain {
+        &mut self.buses[bus.0 as usize].effects
+    }
+
+    /// Moves voice to another bus. None is master bus
+    pub fn set_bus(&mut self, id: VoiceId, bus: Option<BusId>) {
+        if bus.is_some_and(| b | b.0 as usize >= self.buses.len()) {
+            panic!("bus {bus:?} does not exist");
+        }
+
+        if let Some(voice) = self.voice_mut(id) {
+            voice.bus = bus;
+        }
+    }
+
+    /// Ammount of voices, what are not finished yet. Paused voices are counted too
+    pub fn voice_count(&self) -> usize {
+        self.slots.iter().filter(| s | s.voice.is_some()).count()
+    }
+
+    pub fn play(&mut self, source: impl Source + 'static, settings: VoiceSettings) -> VoiceId {
+        self.schedule(source, settings, StartAt::Delay(0))
+    }
+
+    /// Plays voice from exact frame. Voice is counted as playing, while it waits.
+    /// Use [`Self::cancel`] or [`Self::stop`] to remove it
+    pub fn schedule(&mut self, source: impl Source + 'static, settings: VoiceSettings, at: StartAt) -> VoiceId {
+        if settings.bus.is_some_and(| b | b.0 as usize >= self.buses.len()) {
+            panic!("bus {:?} does not exist", settings.bus);
+        }
+
+        let fade = settings.fade_in.map(| d | Fade::new(0.0, 1.0, self.duration_to_frames(d), false));
+        let gain = settings.gain.max(0.0);
+
+        let voice = Voice {
+            source: Box::new(source),
+
+            gain,
+            // no fade from 0 at start, or every voice would click in
+            last_gain: gain,
+            pan: settings.pan.clamp(-1.0, 1.0),
+            looping: settings.looping,
+            paused: settings.paused,
+            fade,
+            bus: settings.bus,
+
+            pitch: settings.pitch.clamp(MIN_PITCH, MAX_PITCH),
+            varispeed: None,
+
+            channel_gains: None,
+
+            routing: Vec::new(),
+            last_routing: Vec::new(),
+            routing_dirty: true,
+
+            start: self.start_frame(at),
+            finished: false
+        };
+
+        let index = match self.slots.iter().position(| s | s.voice.is_none()) {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot { generation: 0, voice: None });
+                self.slots.len() - 1
+            }
+        };
+
+        let slot = &mut self.slots[index];
+        slot.voice = Some(voice);
+
+        VoiceId { index: index as u32, generation: slot.generation }
+    }
+
+    /// Plays source with different sample rate. It is resampled with [`ResamplerQuality::Sinc`]
+    pub fn play_resampled(&mut self, source: impl Source + 'static, rate: u32, settings: VoiceSettings) -> VoiceId {
+        if rate == self.rate {
+            self.play(source, settings)
+        } else {
+            self.play(Resampled::new(source, rate, self.rate, ResamplerQuality::Sinc), settings)
+        }
+    }
+
+    pub fn is_playing(&self, id: VoiceId) -> bool {
+        self.voice(id).is_some_and(| v | !v.paused)
+    }
+
+    /// True, if voice waits for its start frame
+    pub fn is_scheduled(&self, id: VoiceId) -> bool {
+        self.voice(id).is_some_and(| v | v.start > self.position)
+    }
+
+    /// Removes voice, if it has not started yet. Returns false, if it is already playing or finished
+    pub fn cancel(&mut self, id: VoiceId) -> bool {
+        if !self.is_scheduled(id) {
+            return false;
+        }
+
+        self.stop(id);
+
+        true
+    }
+
+    /// Stops voice immediately. Does nothing if voice is already finished
+    pub fn stop(&mut self, id: VoiceId) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.finished = true;
+        }
+        self.collect_finished();
+    }
+
+    pub fn pause(&mut self, id: VoiceId) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.paused = true;
+        }
+    }
+
+    pub fn resume(&mut self, id: VoiceId) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.paused = false;
+        }
+    }
+
+    /// Changes are smoothed over one mixed block
+    pub fn set_gain(&mut self, id: VoiceId, gain: f32) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.gain = gain.max(0.0);
+        }
+    }
+
+    pub fn set_pan(&mut self, id: VoiceId, pan: f32) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.pan = pan.clamp(-1.0, 1.0);
+            voice.routing_dirty = true;
+        }
+    }
+
+    /// Changes playback speed, what also changes pitch. 1 is original speed.
+    /// Clamped to 1/8..8
+    pub fn set_pitch(&mut self, id: VoiceId, pitch: f32) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.pitch = pitch.clamp(MIN_PITCH, MAX_PITCH);
+        }
+    }
+
+    /// Routes voice by gain of every output channel instead of its pan. Source channels are downmixed.
+    /// Used by [`crate::spatial::Spatializer`]. Changes are smoothed over one mixed block
+    pub fn set_channel_gains(&mut self, id: VoiceId, gains: &[f32]) {
+        if gains.len() != self.channel_map.len() {
+            panic!("got {} channel gains, but mixer has {} channels", gains.len(), self.channel_map.len());
+        }
+
+        if let Some(voice) = self.voice_mut(id) {
+            voice.channel_gains = Some(gains.iter().map(| g | g.max(0.0)).collect());
+            voice.routing_dirty = true;
+        }
+    }
+
+    /// Returns voice back to its pan
+    pub fn clear_channel_gains(&mut self, id: VoiceId) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.channel_gains = None;
+            voice.routing_dirty = true;
+        }
+    }
+
+    pub fn set_looping(&mut self, id: VoiceId, looping: bool) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.looping = looping;
+        }
+    }
+
+    /// Fades voice from silence to its gain. Resumes voice, if it was paused
+    pub fn fade_in(&mut self, id: VoiceId, duration: Duration) {
+        let len = self.duration_to_frames(duration);
+
+        if let Some(voice) = self.voice_mut(id) {
+            let from = voice.fade.map(| f | f.value()).unwrap_or(0.0);
+
+            voice.fade = Some(Fade::new(from, 1.0, len, false));
+            voice.paused = false;
+        }
+    }
+
+    /// Fades voice to silence and stops it
+    pub fn fade_out(&mut self, id: VoiceId, duration: Duration) {
+        let len = self.duration_to_frames(duration);
+
+        if let Some(voice) = self.voice_mut(id) {
+            let from = voice.fade.map(| f | f.value()).unwrap_or(1.0);
+
+            voice.fade = Some(Fade::new(from, 0.0, len, true));
+        }
+    }
+
+    /// Mixes all voices into `out`, overwriting its content.
+    /// Length of `out` should be a multiple of channel count
+    pub fn mix(&mut self, out: &mut [f32]) {
+        let channels = self.channel_map.len();
+
+        if !out.len().is_multiple_of(channels) {
+            panic!("output length {} is not a multiple of channel count {channels}", out.len());
+        }
+
+        out.fill(0.0);
+
+        let frames = out.len() / channels;
+
+        for bus in self.buses.iter_mut() {
+            bus.buffer.clear();
+            bus.buffer.resize(out.len(), 0.0);
+        }
+
+        for slot in self.slots.iter_mut() {
+            let Some(voice) = slot.voice.as_mut() else { continue };
+
+            // scheduled voice starts in the middle of the block
+            let offset = voice.start.saturating_sub(self.position);
+
+            if voice.paused || voice.finished || offset >= frames as u64 {
+                continue;
+            }
+
+            let offset = offset as usize;
+            let dst = match voice.bus {
+                Some(bus) => &mut self.buses[bus.0 as usize].buffer[offset * channels..],
+                None => &mut out[offset * channels..]
+            };
+
+            Self::mix_voice(&self.channel_map, voice, &mut self.scratch, dst, frames - offset);
+        }
+
+        // buses run even without voices, so tails of reverbs and delays are heard
+        for bus in self.buses.iter_mut() {
+            bus.effects.process(&mut bus.buffer);
+            lanes::mix(out, &bus.buffer, 1.0);
+        }
+
+        // master bus
+        let step = (self.master_gain - self.last_master_gain) / frames.max(1) as f32;
+
+        for (i, frame) in out.chunks_exact_mut(channels).enumerate() {
+            let gain = self.last_master_gain + step * (i + 1) as f32;
+
+            frame.iter_mut().for_each(| s | *s *= gain);
+        }
+
+        self.last_master_gain = self.master_gain;
+        self.master_effects.process(out);
+
+        self.position += frames as u64;
+        self.collect_finished();
+    }
+
+    /// Mixes as many whole frames as producer can accept and pushes them.
+    /// Returns ammount of pushed samples
+    pub fn fill(&mut self, producer: &mut RingProducer<f32>) -> usize {
+        let channels = self.channel_map.len();
+        let len = producer.free_len() - producer.free_len() % channels;
+
+        if len == 0 {
+            return 0;
+        }
+
+        // taken out, because mix borrows the whole mixer
+        let mut buf = core::mem::take(&mut self.fill_buffer);
+
+        buf.clear();
+        buf.resize(len, 0.0);
+
+        self.mix(&mut buf);
+
+        let pushed = producer.push(&buf);
+
+        self.fill_buffer = buf;
+
+        pushed
+    }
+}
+
+impl Mixer {
+    fn duration_to_frames(&self, duration: Duration) -> usize {
+        (duration.as_secs_f64() * self.rate as f64).round() as usize
+    }
+
+    fn start_frame(&self, at: StartAt) -> u64 {
+        match at {
+            StartAt::Frame(frame) => frame,
+            StartAt::Delay(frames) => self.position + frames,
+            StartAt::Beat(beat) => self.sequencer.frame_at(beat)
+        }
+    }
+
+    fn voice(&self, id: VoiceId) -> Option<&Voice> {
+        self.slots.get(id.index as usize)
+            .filter(| s | s.generation == id.generation)
+            .and_then(| s | s.voice.as_ref())
+    }
+
+    fn voice_mut(&mut self, id: VoiceId) -> Option<&mut Voice> {
+        self.slots.get_mut(id.index as usize)
+            .filter(| s | s.generation == id.generation)
+            .and_then(| s | s.voice.as_mut())
+    }
+
+    fn collect_finished(&mut self) {
+        for slot in self.slots.iter_mut() {
+            if slot.voice.as_ref().is_some_and(| v | v.finished) {
+                slot.voice = None;
+                // makes all old ids stale
+                slot.generation = slot.generation.wrapping_add(1);
+            }
+        }
+    }
+
+    fn mix_voice(output_map: &ChannelMap, voice: &mut Voice, scratch: &mut Vec<f32>, out: &mut [f32], frames: usize) {
+        let out_channels = output_map.len();
+        let src_channels = voice.source.channel_map().len();
+
+        if voice.routing_dirty {
+            voice::routing_matrix(voice.source.channel_map(), output_map, voice.pan, voice.channel_gains.as_deref(), &mut voice.routing);
+            voice.routing_dirty = false;
+
+            // nothing to smooth from for new voices
+            if voice.last_routing.len() != voice.routing.len() {
+                voice.last_routing.clone_from(&voice.routing);
+            }
+        }
+
+        scratch.clear();
+        scratch.resize(frames * src_channels, 0.0);
+
+        let read = match voice.varispeed.is_some() || voice.pitch != 1.0 {
+            true => voice.read_pitched(scratch, src_channels),
+            false => voice.read(scratch)
+        };
+        let read_frames = read / src_channels;
+
+        if read_frames < frames {
+            voice.finished = true;
+        }
+
+        let step = (voice.gain - voice.last_gain) / frames.max(1) as f32;
+        let smooth_routing = voice.last_routing != voice.routing;
+
+        for f in 0..read_frames {
+            let mut gain = voice.last_gain + step * (f + 1) as f32;
+            let t = (f + 1) as f32 / frames as f32;
+
+            if let Some(fade) = voice.fade.as_mut() {
+                gain *= fade.value();
+                fade.advance();
+            }
+
+            let src = &scratch[f * src_channels..(f + 1) * src_channels];
+            let dst = &mut out[f * out_channels..(f + 1) * out_channels];
+
+            for (s, &sample) in src.iter().enumerate() {
+                let routing = &voice.routing[s * out_channels..(s + 1) * out_channels];
+                let last_routing = &voice.last_routing[s * out_channels..(s + 1) * out_channels];
+
+                for ((d, &r), &last) in dst.iter_mut().zip(routing).zip(last_routing) {
+                    let r = match smooth_routing {
+                        true => last + (r - last) * t,
+                        false => r
+                    };
+
+                    *d += sample * r * gain;
+                }
+            }
+        }
+
+        voice.last_gain = voice.gain;
+        voice.last_routing.copy_from_slice(&voice.routing);
+
+        if let Some(fade) = voice.fade {
+            if fade.finished() {
+                voice.fade = None;
+
+                if fade.stop {
+                    voice.finished = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{AudioServer, NullBackend, ChannelMap, ChannelPosition};
+    use crate::effect::{Delay, Limiter};
+    use super::{Mixer, SampleBuffer, Generator, VoiceSettings, StartAt};
+
+    fn mono() -> ChannelMap {
+        ChannelMap::from_positions(&[ChannelPosition::Mono])
+    }
+
+    fn stereo() -> ChannelMap {
+        ChannelMap::from_positions(&[ChannelPosition::FrontLeft, ChannelPosition::FrontRight])
+    }
+
+    #[test]
+    fn mixes_voices() {
+        let mut mixer = Mixer::new(100, mono());
+
+        let a = SampleBuffer::new(vec![0.25; 4], mono());
+        let b = SampleBuffer::new(vec![0.5; 2], mono());
+
+        mixer.play(a.source(), Default::default());
+        mixer.play(b.source(), Default::default());
+
+        let mut out = [0.0; 6];
+        mixer.mix(&mut out);
+
+        assert_eq!(out, [0.75, 0.75, 0.25, 0.25, 0.0, 0.0]);
+        assert_eq!(mixer.voice_count(), 0);
+    }
+
+    #[test]
+    fn looping() {
+        let mut mixer = Mixer::new(100, mono());
+        let buffer = SampleBuffer::new(vec![1.0, 2.0, 3.0], mono());
+
+        let id = mixer.play(buffer.source(), VoiceSettings { looping: true, ..Default::default() });
+
+        let mut out = [0.0; 7];
+        mixer.mix(&mut out);
+
+        assert_eq!(out, [1.0, 2.0, 3.0, 1.0, 2.0, 3.0, 1.0]);
+        assert!(mixer.is_playing(id));
+
+        mixer.stop(id);
+
+        assert!(!mixer.is_playing(id));
+        assert_eq!(mixer.voice_count(), 0);
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let mut mixer = Mixer::new(100, mono());
+        let id = mixer.play(Generator::new(mono(), | out | { out.fill(1.0); out.len() }), Default::default());
+
+        let mut out = [0.0; 2];
+
+        mixer.pause(id);
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.0, 0.0]);
+
+        mixer.resume(id);
+        mixer.mix(&mut out);
+        assert_eq!(out, [1.0, 1.0]);
+    }
+
+    #[test]
+    fn constant_power_pan() {
+        let mut mixer = Mixer::new(100, stereo());
+        let buffer = SampleBuffer::new(vec![1.0; 2], mono());
+
+        mixer.play(buffer.source(), Default::default());
+
+        let mut out = [0.0; 4];
+        mixer.mix(&mut out);
+
+        let center = core::f32::consts::FRAC_1_SQRT_2;
+
+        for s in out {
+            assert!((s - center).abs() < 1e-6);
+        }
+
+        let id = mixer.play(buffer.source(), VoiceSettings { pan: 1.0, ..Default::default() });
+
+        mixer.mix(&mut out);
+        mixer.stop(id);
+        assert_eq!(out[0], 0.0);
+        assert!((out[1] - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stereo_source_balance() {
+        let mut mixer = Mixer::new(100, stereo());
+        let buffer = SampleBuffer::new(vec![1.0, 1.0], stereo());
+
+        mixer.play(buffer.source(), VoiceSettings { pan: -0.5, ..Default::default() });
+
+        let mut out = [0.0; 2];
+        mixer.mix(&mut out);
+
+        assert_eq!(out, [1.0, 0.5]);
+    }
+
+    #[test]
+    fn fades() {
+        let mut mixer = Mixer::new(4, mono());
+        let id = mixer.play(
+            Generator::new(mono(), | out | { out.fill(1.0); out.len() }),
+            VoiceSettings { fade_in: Some(Duration::from_secs(1)), ..Default::default() }
+        );
+
+        let mut out = [0.0; 4];
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.0, 0.25, 0.5, 0.75]);
+
+        mixer.mix(&mut out);
+        assert_eq!(out, [1.0; 4]);
+
+        mixer.fade_out(id, Duration::from_millis(500));
+        mixer.mix(&mut out);
+
+        assert_eq!(out, [1.0, 0.5, 0.0, 0.0]);
+        assert!(!mixer.is_playing(id));
+    }
+
+    #[test]
+    fn gain_is_smoothed() {
+        let mut mixer = Mixer::new(100, mono());
+        let id = mixer.play(Generator::new(mono(), | out | { out.fill(1.0); out.len() }), Default::default());
+
+        mixer.set_gain(id, 0.0);
+
+        let mut out = [0.0; 4];
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.75, 0.5, 0.25, 0.0]);
+
+        mixer.set_master_gain(0.5);
+        mixer.set_gain(id, 1.0);
+        mixer.mix(&mut out);
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.5; 4]);
+    }
+
+    #[test]
+    fn pitch() {
+        let mut mixer = Mixer::new(100, mono());
+        let buffer = SampleBuffer::new(vec![0.0, 1.0, 2.0, 3.0, 4.0], mono());
+
+        let id = mixer.play(buffer.source(), VoiceSettings { pitch: 0.5, ..Default::default() });
+
+        let mut out = [0.0; 4];
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.0, 0.5, 1.0, 1.5]);
+
+        mixer.set_pitch(id, 2.0);
+        mixer.mix(&mut out);
+        assert_eq!(out[..2], [2.0, 4.0]);
+        assert_eq!(mixer.voice_count(), 0);
+    }
+
+    #[test]
+    fn channel_gains() {
+        let mut mixer = Mixer::new(100, stereo());
+        let id = mixer.play(Generator::new(stereo(), | out | { out.fill(1.0); out.len() }), Default::default());
+
+        let mut out = [0.0; 4];
+        mixer.mix(&mut out);
+
+        mixer.set_channel_gains(id, &[0.0, 0.5]);
+        mixer.mix(&mut out);
+        // smoothed from balanced routing
+        assert_eq!(out, [0.5, 0.75, 0.0, 0.5]);
+
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.0, 0.5, 0.0, 0.5]);
+
+        mixer.clear_channel_gains(id);
+        mixer.mix(&mut out);
+        mixer.mix(&mut out);
+        assert_eq!(out, [1.0; 4]);
+    }
+
+    #[test]
+    fn bus_effects() {
+        let mut mixer = Mixer::new(100, mono());
+        let bus = mixer.add_bus();
+
+        let mut limiter = Limiter::new(100, 1, -6.0);
+        limiter.set_lookahead(Duration::ZERO);
+
+        mixer.bus_effects(bus).push(Delay::new(100, 1, Duration::from_millis(20), 0.0, 1.0));
+        mixer.master_effects().push(limiter);
+
+        let impulse = SampleBuffer::new(vec![1.0], mono());
+
+        mixer.play(impulse.source(), VoiceSettings { bus: Some(bus), ..Default::default() });
+        mixer.play(impulse.source(), VoiceSettings { gain: 0.25, ..Default::default() });
+
+        let mut out = [0.0; 4];
+        mixer.mix(&mut out);
+
+        // delay tail comes after voice is finished
+        assert_eq!(out[0], 0.25);
+        assert!((out[2] - 0.5).abs() < 1e-2);
+        assert_eq!(mixer.voice_count(), 0);
+    }
+
+    #[test]
+    fn scheduled_voices() {
+        let mut mixer = Mixer::new(100, mono());
+        let click = SampleBuffer::new(vec![1.0, 0.5], mono());
+
+        let a = mixer.schedule(click.source(), Default::default(), StartAt::Frame(3));
+        let b = mixer.schedule(click.source(), Default::default(), StartAt::Delay(5));
+        let c = mixer.schedule(click.source(), Default::default(), StartAt::Frame(6));
+
+        assert!(mixer.is_scheduled(a) && mixer.is_playing(a));
+        assert!(mixer.cancel(c));
+        assert!(!mixer.cancel(c));
+
+        let mut out = [0.0; 4];
+        mixer.mix(&mut out);
+
+        // starts in the middle of the block and continues in the next one
+        assert_eq!(out, [0.0, 0.0, 0.0, 1.0]);
+        assert!(!mixer.is_scheduled(a));
+        assert!(!mixer.cancel(a));
+
+        mixer.mix(&mut out);
+        assert_eq!(out, [0.5, 1.0, 0.5, 0.0]);
+        assert_eq!(mixer.position(), 8);
+        assert!(!mixer.is_playing(b));
+
+        // frame, what is already mixed, plays at once
+        mixer.schedule(click.source(), Default::default(), StartAt::Frame(0));
+        mixer.mix(&mut out);
+        assert_eq!(out, [1.0, 0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn beats_on_offline_stream() {
+        let rate = 1000;
+        let server = AudioServer::with_backend(NullBackend::new());
+        let mut stream = server.create_stream::<f32>("sequencer", rate, &mono(), Default::default(), None, None).unwrap();
+        let mut producer = stream.as_mut().start_buffered(64);
+
+        let mut mixer = Mixer::new(rate, mono());
+        let click = SampleBuffer::new(vec![1.0], mono());
+
+        // 600 bpm is 100 frames per beat
+        mixer.set_tempo(600.0);
+
+        for beat in 0..4 {
+            mixer.schedule(click.source(), Default::default(), StartAt::Beat(beat as f64));
+        }
+
+        // off-beat, what is cancelled
+        let off_beat = mixer.schedule(click.source(), Default::default(), StartAt::Beat(2.5));
+
+        // blocks of 16 frames don't line up with beats
+        for _ in 0..30 {
+            mixer.fill(&mut producer);
+            server.backend().advance(Duration::from_millis(16));
+
+            if mixer.position() > 200 {
+                mixer.cancel(off_beat);
+            }
+        }
+
+        let output = stream.output();
+        let clicks: Vec<usize> = output.iter().enumerate().filter(| (_, &s) | s != 0.0).map(| (i, _) | i).collect();
+
+        assert_eq!(clicks, [0, 100, 200, 300]);
+        assert_eq!(stream.underflow_count(), 0);
+    }
+}
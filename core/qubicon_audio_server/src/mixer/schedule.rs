@@ -0,0 +1,116 @@
+/// When scheduled voice starts. Times are in frames of [`super::Mixer::position`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StartAt {
+    /// Absolute frame. Frames, what are already mixed, mean "as soon as possible"
+    Frame(u64),
+    /// Frames after current position
+    Delay(u64),
+    /// Beat of mixer [`SequencerClock`]. It is converted to frame, when voice is scheduled,
+    /// so later tempo changes don't move it
+    Beat(f64)
+}
+
+/// Converts musical time to frames. Beat 0 is at frame 0.
+/// Tempo change does not move the beat, what is playing at the moment of change
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SequencerClock {
+    rate: u32,
+    bpm: f64,
+
+    // frame and beat, where current tempo started
+    origin_frame: u64,
+    origin_beat: f64
+}
+
+impl SequencerClock {
+    pub fn new(rate: u32, bpm: f64) -> Self {
+        Self {
+            rate,
+            bpm: Self::clamp_bpm(bpm),
+
+            origin_frame: 0,
+            origin_beat: 0.0
+        }
+    }
+
+    pub fn bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    /// Changes tempo starting from `frame`, usually current mixer position.
+    /// Only one tempo is kept, so beats before `frame` are counted with new tempo too
+    pub fn set_bpm(&mut self, bpm: f64, frame: u64) {
+        self.origin_beat = self.beat_at(frame);
+        self.origin_frame = frame;
+        self.bpm = Self::clamp_bpm(bpm);
+    }
+
+    /// Ammount of frames in one beat at current tempo
+    pub fn frames_per_beat(&self) -> f64 {
+        self.rate as f64 * 60.0 / self.bpm
+    }
+
+    /// Frame, where beat starts. Rounded to the nearest frame
+    pub fn frame_at(&self, beat: f64) -> u64 {
+        let frame = self.origin_frame as f64 + (beat - self.origin_beat) * self.frames_per_beat();
+
+        frame.round().max(0.0) as u64
+    }
+
+    pub fn beat_at(&self, frame: u64) -> f64 {
+        self.origin_beat + (frame as f64 - self.origin_frame as f64) / self.frames_per_beat()
+    }
+
+    /// First beat at or after `frame`, what is a multiple of `division`.
+    /// E.g. division of 4 quantizes to bars of 4/4, 0.5 to eighth notes. Division should be positive and finite
+    pub fn next_beat(&self, frame: u64, division: f64) -> f64 {
+        if !division.is_finite() || division <= 0.0 {
+            panic!("beat division should be positive and finite, got {division}");
+        }
+
+        let beat = self.beat_at(frame);
+        let next = (beat / division).ceil() * division;
+
+        // rounding of frame_at can put beat a bit before frame
+        match self.frame_at(next) < frame {
+            true => next + division,
+            false => next
+        }
+    }
+
+    fn clamp_bpm(bpm: f64) -> f64 {
+        bpm.max(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SequencerClock;
+
+    #[test]
+    fn beats_and_frames() {
+        // 120 bpm at 48 kHz is 24000 frames per beat
+        let mut clock = SequencerClock::new(48000, 120.0);
+
+        assert_eq!(clock.frame_at(1.0), 24000);
+        assert_eq!(clock.frame_at(0.25), 6000);
+        assert_eq!(clock.beat_at(36000), 1.5);
+
+        assert_eq!(clock.next_beat(0, 1.0), 0.0);
+        assert_eq!(clock.next_beat(1, 1.0), 1.0);
+        assert_eq!(clock.next_beat(24001, 4.0), 4.0);
+
+        // twice as fast since beat 2
+        clock.set_bpm(240.0, 48000);
+
+        assert_eq!(clock.frame_at(2.0), 48000);
+        assert_eq!(clock.frame_at(3.0), 60000);
+        assert_eq!(clock.beat_at(60000), 3.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_division() {
+        SequencerClock::new(48000, 120.0).next_beat(0, 0.0);
+    }
+}
@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use crate::ChannelMap;
+
+/// Anything, what can produce interleaved f32 frames for [`super::Mixer`].
+/// Sample rate is assumed to be equal to mixer rate, see [`crate::convert::Resampled`] otherwise
+pub trait Source: Send {
+    fn channel_map(&self) -> &ChannelMap;
+
+    /// Fills `out` with interleaved frames. Length of `out` is always a multiple of channel count.
+    /// Returns ammount of written samples. Returning less than requested means end of source
+    fn read(&mut self, out: &mut [f32]) -> usize;
+
+    /// Moves source to its beginning. Returns false, if source can't do this, so it can't be looped
+    fn rewind(&mut self) -> bool {
+        false
+    }
+}
+
+impl Source for Box<dyn Source> {
+    fn channel_map(&self) -> &ChannelMap {
+        self.as_ref().channel_map()
+    }
+
+    fn read(&mut self, out: &mut [f32]) -> usize {
+        self.as_mut().read(out)
+    }
+
+    fn rewind(&mut self) -> bool {
+        self.as_mut().rewind()
+    }
+}
+
+
+
+/// Immutable interleaved samples in memory. Cheap to clone, so one buffer can be played by many voices
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleBuffer {
+    samples: Arc<[f32]>,
+    channel_map: ChannelMap
+}
+
+impl SampleBuffer {
+    pub fn new(samples: impl Into<Arc<[f32]>>, channel_map: ChannelMap) -> Self {
+        let samples = samples.into();
+
+        if channel_map.is_empty() {
+            panic!("channel map should contain at least one channel");
+        }
+        if !samples.len().is_multiple_of(channel_map.len()) {
+            panic!("sample count {} is not a multiple of channel count {}", samples.len(), channel_map.len());
+        }
+
+        Self { samples, channel_map }
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn channel_map(&self) -> &ChannelMap {
+        &self.channel_map
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.samples.len() / self.channel_map.len()
+    }
+
+    /// Creates source, what plays this buffer from the beginning
+    pub fn source(&self) -> BufferSource {
+        BufferSource { buffer: self.clone(), position: 0 }
+    }
+}
+
+/// Plays [`SampleBuffer`]. Supports rewinding
+#[derive(Debug, Clone)]
+pub struct BufferSource {
+    buffer: SampleBuffer,
+    // in samples
+    position: usize
+}
+
+impl Source for BufferSource {
+    fn channel_map(&self) -> &ChannelMap {
+        self.buffer.channel_map()
+    }
+
+    fn read(&mut self, out: &mut [f32]) -> usize {
+        let remaining = &self.buffer.samples()[self.position..];
+        let ammount = remaining.len().min(out.len());
+
+        out[..ammount].copy_from_slice(&remaining[..ammount]);
+        self.position += ammount;
+
+        ammount
+    }
+
+    fn rewind(&mut self) -> bool {
+        self.position = 0;
+
+        true
+    }
+}
+
+
+
+/// Streaming source, what calls closure to produce samples. For synthesizers, decoders and similar
+pub struct Generator<G: FnMut(&mut [f32]) -> usize + Send> {
+    channel_map: ChannelMap,
+    generator: G
+}
+
+impl<G: FnMut(&mut [f32]) -> usize + Send> Generator<G> {
+    /// Closure has the same contract as [`Source::read`]
+    pub fn new(channel_map: ChannelMap, generator: G) -> Self {
+        Self { channel_map, generator }
+    }
+}
+
+impl<G: FnMut(&mut [f32]) -> usize + Send> Source for Generator<G> {
+    fn channel_map(&self) -> &ChannelMap {
+        &self.channel_map
+    }
+
+    fn read(&mut self, out: &mut [f32]) -> usize {
+        (self.generator)(out)
+    }
+}
@@ -0,0 +1,252 @@
+use arrayvec::ArrayVec;
+use libpulse_sys::PA_CHANNELS_MAX;
+
+use crate::{ChannelMap, ChannelPosition};
+use crate::convert::{horizontal_position, remix_matrix};
+use super::{Source, BusId};
+
+fn is_front(position: ChannelPosition) -> bool {
+    use libpulse_sys::pa_channel_position_t::*;
+
+    matches!(position, Mono | FrontLeft | FrontRight | FrontCenter | FrontLeftOfCenter | FrontRightOfCenter)
+}
+
+/// Constant power pan of one channel placed at `x` between nearest pair of speakers.
+/// Front speakers are preferred, if there are any
+fn pan_gains(output: &ChannelMap, x: f32, gains: &mut [f32]) {
+    let mut speakers: Vec<(usize, f32)> = output.iter()
+        .enumerate()
+        .filter(| (_, &p) | is_front(p))
+        .filter_map(| (i, &p) | horizontal_position(p).map(| x | (i, x)))
+        .collect();
+
+    if speakers.is_empty() {
+        speakers = output.iter()
+            .enumerate()
+            .filter_map(| (i, &p) | horizontal_position(p).map(| x | (i, x)))
+            .collect();
+    }
+
+    speakers.sort_by(| a, b | a.1.total_cmp(&b.1));
+
+    let (Some(&first), Some(&last)) = (speakers.first(), speakers.last()) else { return };
+
+    if x <= first.1 {
+        gains[first.0] += 1.0;
+        return;
+    }
+    if x >= last.1 {
+        gains[last.0] += 1.0;
+        return;
+    }
+
+    for pair in speakers.windows(2) {
+        let ((a, xa), (b, xb)) = (pair[0], pair[1]);
+
+        if x >= xa && x <= xb && xb > xa {
+            let t = (x - xa) / (xb - xa) * core::f32::consts::FRAC_PI_2;
+
+            gains[a] += t.cos();
+            gains[b] += t.sin();
+
+            return;
+        }
+    }
+}
+
+/// Builds routing matrix from source channels to output channels. Matrix is stored source-major.
+/// Multichannel sources are remixed with standard coefficients and balance applied,
+/// mono sources are panned between output speakers.
+/// If `channel_gains` are set, source is downmixed and sent to outputs with these gains instead
+pub(crate) fn routing_matrix(source: &ChannelMap, output: &ChannelMap, pan: f32, channel_gains: Option<&[f32]>, matrix: &mut Vec<f32>) {
+    let pan = pan.clamp(-1.0, 1.0);
+
+    matrix.clear();
+
+    if let Some(gains) = channel_gains {
+        let downmix = 1.0 / source.len() as f32;
+
+        for _ in 0..source.len() {
+            matrix.extend(gains.iter().map(| g | g * downmix));
+        }
+
+        return;
+    }
+
+    if source.len() > 1 {
+        matrix.extend(remix_matrix(source, output));
+
+        for gains in matrix.chunks_exact_mut(output.len()) {
+            for (gain, &position) in gains.iter_mut().zip(output.iter()) {
+                *gain *= match horizontal_position(position) {
+                    Some(x) if x < 0.0 => (1.0 - pan).min(1.0),
+                    Some(x) if x > 0.0 => (1.0 + pan).min(1.0),
+                    _ => 1.0
+                };
+            }
+        }
+
+        return;
+    }
+
+    matrix.resize(output.len(), 0.0);
+
+    // LFE and AUX sources have nowhere to go
+    if let Some(x) = horizontal_position(source[0]) {
+        pan_gains(output, (x + pan).clamp(-1.0, 1.0), matrix);
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Fade {
+    from: f32,
+    to: f32,
+    // in frames
+    len: usize,
+    position: usize,
+    // stop voice when fade ends
+    pub(crate) stop: bool
+}
+
+impl Fade {
+    pub(crate) fn new(from: f32, to: f32, len: usize, stop: bool) -> Self {
+        Self { from, to, len: len.max(1), position: 0, stop }
+    }
+
+    pub(crate) fn value(&self) -> f32 {
+        let t = self.position as f32 / self.len as f32;
+
+        self.from + (self.to - self.from) * t
+    }
+
+    pub(crate) fn advance(&mut self) {
+        self.position = (self.position + 1).min(self.len);
+    }
+
+    pub(crate) fn finished(&self) -> bool {
+        self.position >= self.len
+    }
+}
+
+pub(crate) struct Voice {
+    pub(crate) source: Box<dyn Source>,
+
+    pub(crate) gain: f32,
+    // gain, what was applied at the end of previous block. Used for smoothing
+    pub(crate) last_gain: f32,
+    pub(crate) pan: f32,
+    pub(crate) looping: bool,
+    pub(crate) paused: bool,
+    pub(crate) fade: Option<Fade>,
+    pub(crate) bus: Option<BusId>,
+
+    // playback speed. Voice is read through `varispeed`, once it is not 1
+    pub(crate) pitch: f32,
+    pub(crate) varispeed: Option<Varispeed>,
+
+    // set by spatializer, replaces pan
+    pub(crate) channel_gains: Option<ArrayVec<f32, {PA_CHANNELS_MAX as usize}>>,
+
+    // source-major routing matrix, rebuilt if pan or channel gains change
+    pub(crate) routing: Vec<f32>,
+    // matrix, what was applied at the end of previous block. Used for smoothing
+    pub(crate) last_routing: Vec<f32>,
+    pub(crate) routing_dirty: bool,
+
+    // frame of mixer clock, where voice starts
+    pub(crate) start: u64,
+    pub(crate) finished: bool
+}
+
+impl Voice {
+    /// Reads `out.len()` samples, rewinding source if voice is looping.
+    /// Returns ammount of read samples
+    pub(crate) fn read(&mut self, out: &mut [f32]) -> usize {
+        let mut total = 0;
+        let mut rewound = false;
+
+        while total < out.len() {
+            let ammount = self.source.read(&mut out[total..]);
+
+            total += ammount;
+
+            if total == out.len() {
+                break;
+            }
+            // nothing after rewind, source is empty. Rewinding again would spin forever
+            if ammount == 0 && rewound {
+                break;
+            }
+            if !(self.looping && self.source.rewind()) {
+                break;
+            }
+
+            rewound = true;
+        }
+
+        total
+    }
+
+    /// Same as [`Self::read`], but source is played `pitch` times faster. Frames are linearly interpolated
+    pub(crate) fn read_pitched(&mut self, out: &mut [f32], channels: usize) -> usize {
+        // taken out, so source can be read while it is borrowed
+        let mut varispeed = self.varispeed.take().unwrap_or_default();
+
+        let frames = out.len() / channels;
+        let pitch = self.pitch as f64;
+
+        // last output frame is interpolated between these two
+        let needed = (varispeed.phase + frames.saturating_sub(1) as f64 * pitch) as usize + 2;
+        let mut available = varispeed.pending.len() / channels;
+
+        if needed > available {
+            let start = varispeed.pending.len();
+
+            // frames after end of source stay silent
+            varispeed.pending.resize(needed * channels, 0.0);
+            available += self.read(&mut varispeed.pending[start..]) / channels;
+        }
+
+        let pending = &varispeed.pending;
+        let mut position = varispeed.phase;
+        let mut produced = 0;
+
+        for frame in out.chunks_exact_mut(channels) {
+            let i = position as usize;
+
+            if i >= available {
+                break;
+            }
+
+            let t = (position - i as f64) as f32;
+            let a = &pending[i * channels..(i + 1) * channels];
+            let b = &pending[(i + 1) * channels..(i + 2) * channels];
+
+            for ((s, &a), &b) in frame.iter_mut().zip(a).zip(b) {
+                *s = a + (b - a) * t;
+            }
+
+            produced += 1;
+            position += pitch;
+        }
+
+        let consumed = (position as usize).min(available);
+
+        varispeed.pending.truncate(available * channels);
+        varispeed.pending.drain(..consumed * channels);
+        varispeed.phase = position - consumed as f64;
+
+        self.varispeed = Some(varispeed);
+
+        produced * channels
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Varispeed {
+    // source frames, what are still needed. First one is at integer part of read position
+    pending: Vec<f32>,
+    // fractional part of read position
+    phase: f64
+}
@@ -3,6 +3,8 @@ use std::{ffi::CStr, fmt::Debug, mem::MaybeUninit, ops::{Deref, DerefMut}, str::
 use arrayvec::{ArrayString, ArrayVec};
 use libpulse_sys::*;
 
+use crate::raw::SampleSpec;
+
 pub type ChannelPosition = pa_channel_position_t;
 
 #[derive(Clone, PartialEq, Eq)]
@@ -87,10 +89,64 @@ impl ChannelMap {
         }
     }
 
+    /// FL, FR, LFE
+    pub fn surround_21() -> Self {
+        use pa_channel_position_t::*;
+
+        Self::from_positions(&[FrontLeft, FrontRight, Lfe])
+    }
+
+    /// FL, FR, RL, RR
+    pub fn quad() -> Self {
+        use pa_channel_position_t::*;
+
+        Self::from_positions(&[FrontLeft, FrontRight, RearLeft, RearRight])
+    }
+
+    /// FL, FR, FC, LFE, RL, RR. Same order as in WAV and FLAC files
+    pub fn surround_51() -> Self {
+        use pa_channel_position_t::*;
+
+        Self::from_positions(&[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight])
+    }
+
+    /// 5.1 with SL and SR at the end
+    pub fn surround_71() -> Self {
+        use pa_channel_position_t::*;
+
+        Self::from_positions(&[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight, SideLeft, SideRight])
+    }
+
+    /// 7.1 with four height speakers: TFL, TFR, TRL, TRR
+    pub fn surround_714() -> Self {
+        use pa_channel_position_t::*;
+
+        Self::from_positions(&[
+            FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight, SideLeft, SideRight,
+            TopFrontLeft, TopFrontRight, TopRearLeft, TopRearRight
+        ])
+    }
+
+    /// Same check as `pa_channel_map_valid`. At least one channel and no invalid positions
     pub fn valid(&self) -> bool {
-        let raw = self.into();
+        !self.0.is_empty() && !self.0.contains(&ChannelPosition::Invalid)
+    }
+
+    /// True, if every position of `other` is in this map. Order does not matter
+    pub fn superset_of(&self, other: &ChannelMap) -> bool {
+        other.iter().all(| p | self.contains(p))
+    }
 
-        unsafe { pa_channel_map_valid(&raw) > 0 }
+    /// True, if map is valid and has the same ammount of channels as `spec`
+    pub fn compatible_with(&self, spec: &SampleSpec) -> bool {
+        self.valid() && self.len() == spec.channels as usize
+    }
+
+    /// Input-major gains from channels of this map to channels of `output`,
+    /// so gain from input `i` to output `o` is at `i * output.len() + o`.
+    /// [`crate::convert::Remixer`] and [`crate::mixer::Mixer`] use the same coefficients
+    pub fn remix_matrix(&self, output: &ChannelMap) -> Vec<f32> {
+        crate::convert::remix_matrix(self, output)
     }
 
     pub fn name(&self) -> Option<&CStr> {
@@ -199,6 +255,10 @@ impl From<pa_channel_map> for ChannelMap {
 
 #[cfg(test)]
 mod tests {
+    use core::f32::consts::FRAC_1_SQRT_2;
+    use libpulse_sys::{ pa_channel_position_t::*, pa_sample_format_t };
+
+    use crate::SampleSpec;
     use super::ChannelMap;
 
     #[test]
@@ -211,4 +271,62 @@ mod tests {
         println!("stereo: {stereo:?}");
         println!("auto: {auto:?}");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn standard_layouts() {
+        assert_eq!(&*ChannelMap::surround_21(), &[FrontLeft, FrontRight, Lfe]);
+        assert_eq!(ChannelMap::quad().len(), 4);
+        assert_eq!(ChannelMap::surround_51().len(), 6);
+        assert_eq!(ChannelMap::surround_71().len(), 8);
+        assert_eq!(ChannelMap::surround_714().len(), 12);
+
+        assert!(ChannelMap::surround_714().superset_of(&ChannelMap::surround_71()));
+        assert!(ChannelMap::surround_71().superset_of(&ChannelMap::surround_51()));
+        assert!(ChannelMap::surround_51().superset_of(&ChannelMap::surround_21()));
+        assert!(ChannelMap::surround_51().superset_of(&ChannelMap::quad()));
+        assert!(!ChannelMap::quad().superset_of(&ChannelMap::surround_21()));
+
+        // order does not matter
+        assert!(ChannelMap::from_positions(&[FrontRight, FrontLeft]).superset_of(&ChannelMap::from_positions(&[FrontLeft, FrontRight])));
+    }
+
+    #[test]
+    fn validation() {
+        let spec = SampleSpec { format: pa_sample_format_t::F32le, rate: 48000, channels: 6 };
+
+        assert!(ChannelMap::surround_51().valid());
+        assert!(ChannelMap::surround_51().compatible_with(&spec));
+        assert!(!ChannelMap::surround_71().compatible_with(&spec));
+
+        assert!(!ChannelMap::from_positions(&[]).valid());
+        assert!(!ChannelMap::from_positions(&[FrontLeft, Invalid]).valid());
+    }
+
+    #[test]
+    fn remix_matrix() {
+        let input = ChannelMap::surround_714();
+        let output = ChannelMap::surround_51();
+        let matrix = input.remix_matrix(&output);
+        let gain = | i: usize, o: usize | matrix[i * output.len() + o];
+
+        assert_eq!(matrix.len(), 12 * 6);
+
+        // shared speakers are copied
+        for c in 0..6 {
+            for o in 0..6 {
+                assert_eq!(gain(c, o), if c == o { 1.0 } else { 0.0 });
+            }
+        }
+
+        // sides go to rears, heights to fronts at -3 dB
+        assert_eq!(gain(6, 4), 1.0);
+        assert_eq!(gain(7, 5), 1.0);
+        assert!((gain(8, 0) - FRAC_1_SQRT_2).abs() < 1e-6);
+        assert!((gain(11, 1) - FRAC_1_SQRT_2).abs() < 1e-6);
+
+        // LFE has nowhere to go without LFE speaker
+        let matrix = ChannelMap::surround_21().remix_matrix(&ChannelMap::quad());
+
+        assert!(matrix[8..].iter().all(| &g | g == 0.0));
+    }
+}
@@ -1,7 +1,11 @@
 use core::{ pin::Pin, ffi::CStr };
+use std::ffi::CString;
 use libpulse_sys::*;
 
-use crate::{ Result, Error, raw::{ Format, StreamFlags, BufferAttributes, Proplist, ChannelMap, PlaybackStream } };
+use crate::{ Result, Error, raw::{ Format, StreamFlags, BufferAttributes, Proplist, ChannelMap, PlaybackStream, RecordStream, Mainloop, MainloopMode } };
+use crate::raw::introspect::{ callbacks, DeviceInfo, ServerInfo, DeviceEvent, DeviceEventCallback, ListRequest };
+use crate::raw::operation::context_error;
+use crate::raw::stream::StreamRegistry;
 
 extern "C" fn ctx_state_callback(ctx: *mut pa_context, data: *mut core::ffi::c_void) {
     // its ffi, all code is unsafe
@@ -9,88 +13,334 @@ extern "C" fn ctx_state_callback(ctx: *mut pa_context, data: *mut core::ffi::c_v
         let data = &mut *data.cast::<PulseContext>();
         
         data.ctx_state = pa_context_get_state(ctx);
+
+        match data.ctx_state {
+            // context can't be freed inside of its own callback
+            pa_context_state_t::Failed if data.reconnect => {
+                pa_mainloop_api_once(data.mainloop.api(), Some(ctx_reconnect_callback), (data as *mut PulseContext).cast());
+            },
+            pa_context_state_t::Ready if data.reconnecting => {
+                data.reconnecting = false;
+                data.restore();
+            },
+            _ => {}
+        }
+
+        // wake up init, if it waits in threaded mode
+        data.mainloop.signal();
+    }
+}
+
+extern "C" fn ctx_reconnect_callback(_api: *const pa_mainloop_api, data: *mut core::ffi::c_void) {
+    unsafe {
+        let data = &mut *data.cast::<PulseContext>();
+
+        // streams of failed context are already failed too. They are recreated, when new context is ready
+        pa_context_unref(data.ctx);
+
+        data.reconnecting = true;
+
+        // with NOFAIL context waits for server instead of failing. Error here means, what it can't be created at all
+        if data.connect(PA_CONTEXT_NOFAIL).is_err() {
+            data.ctx_state = pa_context_state_t::Failed;
+        }
+    }
+}
+
+extern "C" fn ctx_subscribe_callback(_ctx: *mut pa_context, event: pa_subscription_event_type_t, index: u32, data: *mut core::ffi::c_void) {
+    unsafe {
+        let data = &mut *data.cast::<PulseContext>();
+
+        if let (Some(callback), Some(event)) = (data.device_callback.as_mut(), DeviceEvent::from_raw(event, index)) {
+            callback(event);
+        }
     }
 }
 
 pub struct PulseContext {
-    mainloop: *mut pa_mainloop,
-    mainloop_api: *const pa_mainloop_api,
+    mainloop: Mainloop,
 
     ctx: *mut pa_context,
     ctx_state: pa_context_state_t,
 
+    // called from subscribe callback
+    device_callback: Option<DeviceEventCallback>,
+
+    // kept to create the same context after reconnection
+    name: CString,
+    properties: Proplist,
+    streams: StreamRegistry,
+
+    reconnect: bool,
+    reconnecting: bool,
+
     _ph: std::marker::PhantomPinned
 }
 
 impl PulseContext {
-    // rewrites all data whats inside without destructor
-    pub unsafe fn init(self: Pin<&mut Self>, name: &CStr) -> Result<()> {
+    // rewrites all data whats inside without destructor.
+    // With `reconnect` failed context is replaced by new one, and streams are recreated on it.
+    // On error resources are not freed here, they are freed by Drop of the context
+    pub unsafe fn init(self: Pin<&mut Self>, name: &CStr, properties: &Proplist, mode: MainloopMode, reconnect: bool) -> Result<()> {
         let this = self.get_unchecked_mut();
 
         // set to some random value
         // this field should not contain some random data, because will be used in initialization process
         this.ctx_state = pa_context_state_t::Unconnected;
+        // fields are uninit, so they should be written without dropping old value
+        core::ptr::write(&mut this.device_callback, None);
+        core::ptr::write(&mut this.name, name.into());
+        core::ptr::write(&mut this.properties, properties.clone());
+        core::ptr::write(&mut this.streams, Default::default());
+        // first connection should fail as is
+        this.reconnect = false;
+        this.reconnecting = false;
 
-        this.mainloop = pa_mainloop_new();
-        this.mainloop_api = pa_mainloop_get_api(this.mainloop);
+        this.mainloop = Mainloop::new(mode);
 
-        this.ctx = pa_context_new(this.mainloop_api, name.as_ptr());
+        this.connect(PA_CONTEXT_NOFLAGS)
+            .map_err(| e | Error::ContextConnectionFailed { source: e.into() })?;
 
-        handle_pa_error!(pa_context_connect(this.ctx, core::ptr::null(), Default::default(), core::ptr::null()))
-            .inspect_err(| _ | { pa_context_unref(this.ctx); pa_mainloop_free(this.mainloop); })
-            .map_err(| e | Error::ContextConnectionFailed { pa_error: e })?;
-        
-        pa_context_set_state_callback(this.ctx, Some(ctx_state_callback), (this as *mut Self).cast());
+        this.mainloop.start()
+            .map_err(| e | Error::ContextConnectionFailed { source: e.into() })?;
 
-        
-        // yes, its busy loop
-        // ctx_state is changed in callback
+        let guard = this.mainloop.lock();
+
+        // ctx_state is changed in callback.
+        // For threaded mainloop callback signals about change, for polled one wait processes events
         #[allow(clippy::while_immutable_condition)]
         while this.ctx_state != pa_context_state_t::Ready {
-            handle_pa_error!(pa_mainloop_iterate(this.mainloop, 1, core::ptr::null_mut()))
-                .inspect_err(| _ | this.destroy_resources())
-                .map_err(| e | Error::ContextConnectionFailed { pa_error: e })?;
-            
             if !pa_context_is_good(this.ctx_state) {
-                this.destroy_resources();
+                // reason, e.g. connection refused, is lost after context is freed
+                let error = match this.ctx_state {
+                    pa_context_state_t::Failed => Error::ContextConnectionFailed { source: context_error(this.ctx).into() },
+                    ctx_state => Error::ContextBadState { ctx_state }
+                };
 
-                return Err(Error::ContextBadState { ctx_state: this.ctx_state })
+                drop(guard);
+
+                return Err(error)
+            }
+
+            if let Err(e) = this.mainloop.wait() {
+                drop(guard);
+
+                return Err(Error::ContextConnectionFailed { source: e.into() });
             }
         }
 
+        this.reconnect = reconnect;
 
         Ok( () )
     }
 
+    // creates new context and starts connection
+    unsafe fn connect(&mut self, flags: pa_context_flags_t) -> core::result::Result<i32, pa_error_code_t> {
+        self.ctx = pa_context_new_with_proplist(self.mainloop.api(), self.name.as_ptr(), self.properties.as_raw());
+
+        if self.ctx.is_null() {
+            return Err(pa_error_code_t::Internal);
+        }
+
+        pa_context_set_state_callback(self.ctx, Some(ctx_state_callback), (self as *mut Self).cast());
+
+        handle_pa_error!(pa_context_connect(self.ctx, core::ptr::null(), flags, core::ptr::null()))
+    }
+
+    // called, when context is ready after reconnection
+    unsafe fn restore(&mut self) {
+        if self.device_callback.is_some() {
+            pa_context_set_subscribe_callback(self.ctx, Some(ctx_subscribe_callback), (self as *mut Self).cast());
+
+            let operation = pa_context_subscribe(
+                self.ctx,
+                PA_SUBSCRIPTION_MASK_SINK | PA_SUBSCRIPTION_MASK_SOURCE | PA_SUBSCRIPTION_MASK_SERVER,
+                None,
+                core::ptr::null_mut()
+            );
+
+            if !operation.is_null() {
+                pa_operation_unref(operation);
+            }
+        }
+
+        for stream in self.streams.lock().unwrap().iter() {
+            stream.reconnect(self.ctx);
+        }
+    }
+
+    /// False, while connection is lost and context waits for server to come back
+    pub fn is_connected(&self) -> bool {
+        let _guard = self.mainloop.lock();
+
+        self.ctx_state == pa_context_state_t::Ready
+    }
+
     pub fn update(&self) -> Result<()> {
         unsafe {
-            handle_pa_error!(pa_mainloop_iterate(self.mainloop, 0, core::ptr::null_mut()))
-                .map(| _ | ())
-                .map_err(| e | Error::ContextUpdateFailed { pa_error: e })
+            self.mainloop.iterate(false)
+                .map_err(| e | Error::ContextUpdateFailed { source: e.into() })
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn create_new_playback_stream<F: Format>(
         &self,
         name: &CStr,
+        device: Option<&CStr>,
         rate: u32,
         channel_map: &ChannelMap,
         flags: StreamFlags,
         properties: Option<&Proplist>,
         buffer_attributes: Option<&BufferAttributes>
     ) -> Result<Pin<Box<PlaybackStream<F>>>> {
-        PlaybackStream::new(self.ctx, name, rate, channel_map, flags, properties, buffer_attributes)
+        let _guard = self.mainloop.lock();
+
+        PlaybackStream::new(self.checked_ctx()?, self.mainloop, &self.streams, name, device, rate, channel_map, flags, properties, buffer_attributes)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_new_record_stream<F: Format>(
+        &self,
+        name: &CStr,
+        device: Option<&CStr>,
+        rate: u32,
+        channel_map: &ChannelMap,
+        flags: StreamFlags,
+        properties: Option<&Proplist>,
+        buffer_attributes: Option<&BufferAttributes>
+    ) -> Result<Pin<Box<RecordStream<F>>>> {
+        let _guard = self.mainloop.lock();
+
+        RecordStream::new(self.checked_ctx()?, self.mainloop, &self.streams, name, device, rate, channel_map, flags, properties, buffer_attributes)
     }
 }
 
 impl PulseContext {
-    // after this structure will be unusable
+    // context is null, if it could not be created again after reconnection. Lock should be held
+    fn checked_ctx(&self) -> Result<*mut pa_context> {
+        match self.ctx.is_null() {
+            true => Err(Error::ContextBadState { ctx_state: pa_context_state_t::Failed }),
+            false => Ok(self.ctx)
+        }
+    }
+
+    /// Blocks until operation is finished. Operation callback should signal mainloop. Lock should be held
+    unsafe fn wait_operation(&self, operation: *mut pa_operation) -> Result<()> {
+        if operation.is_null() {
+            return Err(self.operation_error());
+        }
+
+        while pa_operation_get_state(operation) == pa_operation_state_t::Running {
+            if let Err(e) = self.mainloop.wait() {
+                pa_operation_cancel(operation);
+                pa_operation_unref(operation);
+
+                return Err(Error::OperationFailed { source: e.into() });
+            }
+        }
+
+        let state = pa_operation_get_state(operation);
+
+        pa_operation_unref(operation);
+
+        // cancelled, e.g. context died in the middle, so result is not complete
+        match state {
+            pa_operation_state_t::Done => Ok(()),
+            _ => Err(self.operation_error())
+        }
+    }
+
+    unsafe fn operation_error(&self) -> Error {
+        let source = match context_error(self.ctx) {
+            pa_error_code_t::Ok => pa_error_code_t::Killed,
+            e => e
+        };
+
+        Error::OperationFailed { source: source.into() }
+    }
+
+    pub fn sinks(&self) -> Result<Vec<DeviceInfo>> {
+        let _guard = self.mainloop.lock();
+        let mut request = ListRequest { mainloop: self.mainloop, result: Vec::new() };
+
+        unsafe {
+            let operation = pa_context_get_sink_info_list(self.checked_ctx()?, Some(callbacks::sink_info_callback), (&mut request as *mut ListRequest<_>).cast());
+
+            self.wait_operation(operation)?;
+        }
+
+        Ok ( request.result )
+    }
+
+    pub fn sources(&self) -> Result<Vec<DeviceInfo>> {
+        let _guard = self.mainloop.lock();
+        let mut request = ListRequest { mainloop: self.mainloop, result: Vec::new() };
+
+        unsafe {
+            let operation = pa_context_get_source_info_list(self.checked_ctx()?, Some(callbacks::source_info_callback), (&mut request as *mut ListRequest<_>).cast());
+
+            self.wait_operation(operation)?;
+        }
+
+        Ok ( request.result )
+    }
+
+    pub fn server_info(&self) -> Result<ServerInfo> {
+        let _guard = self.mainloop.lock();
+        let mut request = ListRequest { mainloop: self.mainloop, result: Vec::new() };
+
+        unsafe {
+            let operation = pa_context_get_server_info(self.checked_ctx()?, Some(callbacks::server_info_callback), (&mut request as *mut ListRequest<_>).cast());
+
+            self.wait_operation(operation)?;
+        }
+
+        request.result.pop().ok_or(Error::OperationFailed { source: pa_error_code_t::NoEntity.into() })
+    }
+
+    /// Replaces device event callback. None unsubscribes
+    pub fn set_device_callback(self: Pin<&mut Self>, callback: Option<DeviceEventCallback>) -> Result<()> {
+        let _guard = self.mainloop.lock();
+
+        unsafe {
+            let this = self.get_unchecked_mut();
+            let ctx = this.checked_ctx()?;
+            let mask = match callback {
+                Some(_) => PA_SUBSCRIPTION_MASK_SINK | PA_SUBSCRIPTION_MASK_SOURCE | PA_SUBSCRIPTION_MASK_SERVER,
+                None => PA_SUBSCRIPTION_MASK_NULL
+            };
+
+            this.device_callback = callback;
+
+            pa_context_set_subscribe_callback(ctx, Some(ctx_subscribe_callback), (this as *mut Self).cast());
+
+            let mut request = ListRequest { mainloop: this.mainloop, result: Vec::new() };
+            let operation = pa_context_subscribe(ctx, mask, Some(callbacks::success_callback), (&mut request as *mut ListRequest<bool>).cast());
+
+            this.wait_operation(operation)?;
+
+            match request.result.pop() {
+                Some(true) => Ok(()),
+                _ => Err(this.operation_error())
+            }
+        }
+    }
+}
+
+impl PulseContext {
+    // after this structure will be unusable. Safe to call on context what failed in init
     unsafe fn destroy_resources(&mut self) {
         unsafe {
-            pa_context_disconnect(self.ctx);
-            pa_context_unref(self.ctx);
+            // event thread should not touch context while it is destroyed
+            self.mainloop.stop();
+
+            if !self.ctx.is_null() {
+                pa_context_disconnect(self.ctx);
+                pa_context_unref(self.ctx);
+            }
 
-            pa_mainloop_free(self.mainloop);
+            self.mainloop.free();
         }
     }
 }
@@ -0,0 +1,214 @@
+use core::ffi::{c_char, c_void, CStr};
+use libpulse_sys::*;
+
+use crate::raw::{ChannelMap, Proplist, Mainloop};
+
+pub type SampleFormat = pa_sample_format_t;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleSpec {
+    pub format: SampleFormat,
+    pub rate: u32,
+    pub channels: u8
+}
+
+impl From<pa_sample_spec> for SampleSpec {
+    fn from(value: pa_sample_spec) -> Self {
+        Self {
+            format: value.format,
+            rate: value.rate,
+            channels: value.channels
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceKind {
+    Sink,
+    Source
+}
+
+/// Sink or source, what streams can be connected to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub kind: DeviceKind,
+    pub index: u32,
+    /// Used to select device for stream
+    pub name: String,
+    /// Human readable name
+    pub description: String,
+    pub sample_spec: SampleSpec,
+    pub channel_map: ChannelMap,
+    pub properties: Proplist,
+    /// For sources, what record output of a sink
+    pub monitor_of_sink: Option<u32>
+}
+
+impl DeviceInfo {
+    pub fn is_monitor(&self) -> bool {
+        self.monitor_of_sink.is_some()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    pub server_name: String,
+    pub server_version: String,
+    pub default_sink: Option<String>,
+    pub default_source: Option<String>,
+    pub sample_spec: SampleSpec,
+    pub channel_map: ChannelMap
+}
+
+/// Hot-plug notification. Indices match [`DeviceInfo::index`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceEvent {
+    Added { kind: DeviceKind, index: u32 },
+    Changed { kind: DeviceKind, index: u32 },
+    Removed { kind: DeviceKind, index: u32 },
+    /// Server settings changed. Usually it means, what default sink or source was changed
+    ServerChanged
+}
+
+impl DeviceEvent {
+    pub(crate) fn from_raw(event: pa_subscription_event_type_t, index: u32) -> Option<Self> {
+        let kind = match event & PA_SUBSCRIPTION_EVENT_FACILITY_MASK {
+            PA_SUBSCRIPTION_EVENT_SINK => DeviceKind::Sink,
+            PA_SUBSCRIPTION_EVENT_SOURCE => DeviceKind::Source,
+            PA_SUBSCRIPTION_EVENT_SERVER => return Some(Self::ServerChanged),
+            _ => return None
+        };
+
+        match event & PA_SUBSCRIPTION_EVENT_TYPE_MASK {
+            PA_SUBSCRIPTION_EVENT_NEW => Some(Self::Added { kind, index }),
+            PA_SUBSCRIPTION_EVENT_CHANGE => Some(Self::Changed { kind, index }),
+            PA_SUBSCRIPTION_EVENT_REMOVE => Some(Self::Removed { kind, index }),
+            _ => None
+        }
+    }
+}
+
+pub(crate) type DeviceEventCallback = Box<dyn FnMut(DeviceEvent) + Send>;
+
+unsafe fn string(ptr: *const c_char) -> Option<String> {
+    (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_string_lossy().into_owned())
+}
+
+/// Userdata of list callbacks. Lives on stack of the thread, what waits for operation
+pub(crate) struct ListRequest<T> {
+    pub(crate) mainloop: Mainloop,
+    pub(crate) result: Vec<T>
+}
+
+pub(crate) mod callbacks {
+    use super::*;
+
+    pub extern "C" fn sink_info_callback(_ctx: *mut pa_context, info: *const pa_sink_info, eol: i32, data: *mut c_void) {
+        unsafe {
+            let request = &mut *data.cast::<ListRequest<DeviceInfo>>();
+
+            if eol != 0 || info.is_null() {
+                request.mainloop.signal();
+
+                return;
+            }
+
+            let info = &*info;
+
+            request.result.push(
+                DeviceInfo {
+                    kind: DeviceKind::Sink,
+                    index: info.index,
+                    name: string(info.name).unwrap_or_default(),
+                    description: string(info.description).unwrap_or_default(),
+                    sample_spec: info.sample_spec.into(),
+                    channel_map: info.channel_map.into(),
+                    properties: Proplist::copy_from_raw(info.proplist),
+                    monitor_of_sink: None
+                }
+            );
+        }
+    }
+
+    pub extern "C" fn source_info_callback(_ctx: *mut pa_context, info: *const pa_source_info, eol: i32, data: *mut c_void) {
+        unsafe {
+            let request = &mut *data.cast::<ListRequest<DeviceInfo>>();
+
+            if eol != 0 || info.is_null() {
+                request.mainloop.signal();
+
+                return;
+            }
+
+            let info = &*info;
+
+            request.result.push(
+                DeviceInfo {
+                    kind: DeviceKind::Source,
+                    index: info.index,
+                    name: string(info.name).unwrap_or_default(),
+                    description: string(info.description).unwrap_or_default(),
+                    sample_spec: info.sample_spec.into(),
+                    channel_map: info.channel_map.into(),
+                    properties: Proplist::copy_from_raw(info.proplist),
+                    monitor_of_sink: (info.monitor_of_sink != PA_INVALID_INDEX).then_some(info.monitor_of_sink)
+                }
+            );
+        }
+    }
+
+    pub extern "C" fn server_info_callback(_ctx: *mut pa_context, info: *const pa_server_info, data: *mut c_void) {
+        unsafe {
+            let request = &mut *data.cast::<ListRequest<ServerInfo>>();
+
+            if !info.is_null() {
+                let info = &*info;
+
+                request.result.push(
+                    ServerInfo {
+                        server_name: string(info.server_name).unwrap_or_default(),
+                        server_version: string(info.server_version).unwrap_or_default(),
+                        default_sink: string(info.default_sink_name),
+                        default_source: string(info.default_source_name),
+                        sample_spec: info.sample_spec.into(),
+                        channel_map: info.channel_map.into()
+                    }
+                );
+            }
+
+            request.mainloop.signal();
+        }
+    }
+
+    pub extern "C" fn success_callback(_ctx: *mut pa_context, success: i32, data: *mut c_void) {
+        unsafe {
+            let request = &mut *data.cast::<ListRequest<bool>>();
+
+            request.result.push(success != 0);
+            request.mainloop.signal();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libpulse_sys::*;
+    use super::{DeviceEvent, DeviceKind};
+
+    #[test]
+    fn device_events() {
+        assert_eq!(
+            DeviceEvent::from_raw(PA_SUBSCRIPTION_EVENT_SINK | PA_SUBSCRIPTION_EVENT_NEW, 3),
+            Some(DeviceEvent::Added { kind: DeviceKind::Sink, index: 3 })
+        );
+        assert_eq!(
+            DeviceEvent::from_raw(PA_SUBSCRIPTION_EVENT_SOURCE | PA_SUBSCRIPTION_EVENT_REMOVE, 1),
+            Some(DeviceEvent::Removed { kind: DeviceKind::Source, index: 1 })
+        );
+        assert_eq!(
+            DeviceEvent::from_raw(PA_SUBSCRIPTION_EVENT_SERVER | PA_SUBSCRIPTION_EVENT_CHANGE, 0),
+            Some(DeviceEvent::ServerChanged)
+        );
+        assert_eq!(DeviceEvent::from_raw(PA_SUBSCRIPTION_EVENT_SINK_INPUT | PA_SUBSCRIPTION_EVENT_NEW, 0), None);
+    }
+}
@@ -0,0 +1,105 @@
+use libpulse_sys::*;
+
+/// How PulseAudio events are processed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MainloopMode {
+    /// Events are processed only inside of `AudioServer::update`
+    #[default]
+    Polled,
+    /// Events are processed in separate thread, owned by PulseAudio.
+    /// Audio keeps running even if user thread stalls
+    Threaded
+}
+
+// Copy, because streams should be able to lock mainloop on their own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mainloop {
+    Polled(*mut pa_mainloop),
+    Threaded(*mut pa_threaded_mainloop)
+}
+
+impl Mainloop {
+    pub unsafe fn new(mode: MainloopMode) -> Self {
+        match mode {
+            MainloopMode::Polled => Self::Polled(pa_mainloop_new()),
+            MainloopMode::Threaded => Self::Threaded(pa_threaded_mainloop_new())
+        }
+    }
+
+    pub unsafe fn api(&self) -> *const pa_mainloop_api {
+        match *self {
+            Self::Polled(m) => pa_mainloop_get_api(m),
+            Self::Threaded(m) => pa_threaded_mainloop_get_api(m)
+        }
+    }
+
+    /// Starts event thread. Does nothing for polled mainloop
+    pub unsafe fn start(&self) -> Result<(), pa_error_code_t> {
+        match *self {
+            Self::Polled(_) => Ok(()),
+            Self::Threaded(m) => handle_pa_error!(pa_threaded_mainloop_start(m)).map(| _ | ())
+        }
+    }
+
+    /// Processes pending events. Does nothing for threaded mainloop
+    pub unsafe fn iterate(&self, block: bool) -> Result<(), pa_error_code_t> {
+        match *self {
+            Self::Polled(m) => handle_pa_error!(pa_mainloop_iterate(m, block as i32, core::ptr::null_mut())).map(| _ | ()),
+            Self::Threaded(_) => Ok(())
+        }
+    }
+
+    /// Blocks event thread until guard is dropped.
+    /// Any call on context or streams in threaded mode should be done under this lock
+    pub fn lock(&self) -> MainloopGuard {
+        match *self {
+            // lock is recursive, but there is no need to take it from callbacks
+            Self::Threaded(m) if unsafe { pa_threaded_mainloop_in_thread(m) } == 0 => unsafe {
+                pa_threaded_mainloop_lock(m);
+
+                MainloopGuard(Some(m))
+            },
+            _ => MainloopGuard(None)
+        }
+    }
+
+    /// Waits for [`Self::signal`] from callback. Lock should be held.
+    /// For polled mainloop it just blocks until some event is processed
+    pub unsafe fn wait(&self) -> Result<(), pa_error_code_t> {
+        match *self {
+            Self::Polled(_) => self.iterate(true),
+            Self::Threaded(m) => { pa_threaded_mainloop_wait(m); Ok(()) }
+        }
+    }
+
+    /// Wakes up thread, what is blocked in [`Self::wait`]. Called from callbacks
+    pub unsafe fn signal(&self) {
+        if let Self::Threaded(m) = *self {
+            pa_threaded_mainloop_signal(m, 0);
+        }
+    }
+
+    /// Stops event thread. Should be called before freeing objects, owned by mainloop
+    pub unsafe fn stop(&self) {
+        if let Self::Threaded(m) = *self {
+            pa_threaded_mainloop_stop(m);
+        }
+    }
+
+    pub unsafe fn free(self) {
+        match self {
+            Self::Polled(m) => pa_mainloop_free(m),
+            Self::Threaded(m) => pa_threaded_mainloop_free(m)
+        }
+    }
+}
+
+pub struct MainloopGuard(Option<*mut pa_threaded_mainloop>);
+
+impl Drop for MainloopGuard {
+    fn drop(&mut self) {
+        if let Some(m) = self.0 {
+            unsafe { pa_threaded_mainloop_unlock(m) }
+        }
+    }
+}
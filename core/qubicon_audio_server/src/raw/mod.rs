@@ -1,7 +1,10 @@
-pub use stream::{Format, StreamFlags, BufferAttributes, StreamRead, StreamWrite, PlaybackStream};
-pub use proplist::{properties, Proplist, UpdateMode};
+pub use stream::{Format, StreamFlags, BufferAttributes, StreamRead, StreamWrite, ReadResult, TimingInfo, PlaybackStream, RecordStream};
+pub use proplist::{properties, Proplist, ProplistKeys, MediaRole, UpdateMode};
 pub use channel_map::{ChannelMap, ChannelPosition};
 pub use context::PulseContext;
+pub use mainloop::{Mainloop, MainloopGuard, MainloopMode};
+pub use operation::Operation;
+pub use introspect::{SampleFormat, SampleSpec, DeviceKind, DeviceInfo, ServerInfo, DeviceEvent};
 
 // unsafe macro. should not be used on non-pulse functions.
 macro_rules! handle_pa_error {
@@ -33,6 +36,9 @@ pub(crate) fn with_c_string<R>(str: &str, op: impl FnOnce(&core::ffi::CStr) -> R
 } 
 
 pub mod stream;
+pub mod mainloop;
 pub mod context;
 pub mod proplist;
-pub mod channel_map;
\ No newline at end of file
+pub mod channel_map;
+pub mod introspect;
+pub mod operation;
\ No newline at end of file
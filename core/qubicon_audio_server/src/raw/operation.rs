@@ -0,0 +1,189 @@
+use core::{ ffi::c_void, future::Future, pin::Pin, task::{ Context, Poll, Waker } };
+use std::sync::{ Arc, Mutex };
+use libpulse_sys::*;
+
+use crate::{ Error, Result, raw::Mainloop };
+
+/// Last error of context, what caused operation to fail
+pub(crate) unsafe fn context_error(ctx: *const pa_context) -> pa_error_code_t {
+    use num_traits::cast::FromPrimitive;
+
+    pa_error_code_t::from_i32(pa_context_errno(ctx)).unwrap_or(pa_error_code_t::Unknown)
+}
+
+#[derive(Default)]
+struct State {
+    // None while operation is running
+    result: Option<core::result::Result<(), pa_error_code_t>>,
+    waker: Option<Waker>
+}
+
+// shared between operation handle and success callback
+struct Shared {
+    mainloop: Mainloop,
+    state: Mutex<State>
+}
+
+// callback runs in mainloop thread in threaded mode. Mainloop is only signaled from there
+unsafe impl Send for Shared {}
+unsafe impl Sync for Shared {}
+
+impl Shared {
+    // called from success callback
+    unsafe fn complete(data: *mut c_void, success: i32, ctx: *const pa_context) {
+        let shared = &*data.cast::<Self>();
+        let result = match success != 0 {
+            true => Ok(()),
+            false => Err(context_error(ctx))
+        };
+
+        shared.finish(result);
+    }
+
+    // sets result only once and wakes everyone, who waits for it
+    fn finish(&self, result: core::result::Result<(), pa_error_code_t>) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+
+            state.result.get_or_insert(result);
+            state.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+
+        unsafe { self.mainloop.signal() }
+    }
+}
+
+// Called once, when operation is done (after success callback) or cancelled (success callback never runs).
+// Takes back reference, what was given as userdata to both callbacks
+extern "C" fn operation_state_callback(op: *mut pa_operation, data: *mut c_void) {
+    unsafe {
+        match pa_operation_get_state(op) {
+            pa_operation_state_t::Running => {},
+            // cancelled by user, or by server, e.g. stream was disconnected
+            _ => Arc::from_raw(data.cast::<Shared>()).finish(Err(pa_error_code_t::Killed))
+        }
+    }
+}
+
+pub(crate) mod callbacks {
+    use libpulse_sys::*;
+    use super::*;
+
+    pub extern "C" fn stream_success_callback(stream: *mut pa_stream, success: i32, data: *mut c_void) {
+        unsafe { Shared::complete(data, success, pa_stream_get_context(stream)) }
+    }
+
+    pub extern "C" fn context_success_callback(ctx: *mut pa_context, success: i32, data: *mut c_void) {
+        unsafe { Shared::complete(data, success, ctx) }
+    }
+}
+
+/// Server operation in progress. Dropping it does not cancel the operation.
+///
+/// Can be checked with [`Self::is_done`], blocked on with [`Self::wait`] or awaited.
+/// With [`crate::MainloopMode::Polled`] it progresses only inside of `update` calls or [`Self::wait`]
+pub struct Operation {
+    op: *mut pa_operation,
+    shared: Arc<Shared>,
+
+    error: Box<dyn Fn(pa_error_code_t) -> Error>
+}
+
+impl Operation {
+    /// `start` gets userdata for one of [`callbacks`] and should return started operation.
+    /// Mainloop lock should be held
+    pub(crate) unsafe fn start(
+        ctx: *const pa_context,
+        mainloop: Mainloop,
+        error: impl Fn(pa_error_code_t) -> Error + 'static,
+        start: impl FnOnce(*mut c_void) -> *mut pa_operation
+    ) -> Result<Self> {
+        let shared = Arc::new(
+            Shared {
+                mainloop,
+                state: Mutex::default()
+            }
+        );
+
+        // reference for callbacks
+        let data = Arc::into_raw(shared.clone());
+        let op = start(data.cast_mut().cast());
+
+        if op.is_null() {
+            drop(Arc::from_raw(data));
+
+            return Err(error(context_error(ctx)));
+        }
+
+        pa_operation_set_state_callback(op, Some(operation_state_callback), data.cast_mut().cast());
+
+        Ok ( Self { op, shared, error: Box::new(error) } )
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.shared.state.lock().unwrap().result.is_some()
+    }
+
+    /// Blocks until server finishes operation
+    pub fn wait(self) -> Result<()> {
+        let _guard = self.shared.mainloop.lock();
+
+        loop {
+            if let Some(result) = self.take_result() {
+                return result;
+            }
+
+            unsafe {
+                self.shared.mainloop.wait()
+                    .map_err(| e | Error::ContextUpdateFailed { source: e.into() })?;
+            }
+        }
+    }
+
+    /// Callback will not be called, but server may still have done part of the work
+    pub fn cancel(self) {
+        let _guard = self.shared.mainloop.lock();
+
+        unsafe {
+            // state callback releases its reference
+            if !self.is_done() {
+                pa_operation_cancel(self.op);
+            }
+        }
+    }
+
+    // lock should be held
+    fn take_result(&self) -> Option<Result<()>> {
+        self.shared.state.lock().unwrap().result
+            .map(| r | r.map_err(&self.error))
+    }
+}
+
+impl Future for Operation {
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let _guard = self.shared.mainloop.lock();
+
+        if let Some(result) = self.take_result() {
+            return Poll::Ready(result);
+        }
+
+        self.shared.state.lock().unwrap().waker = Some(cx.waker().clone());
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Operation {
+    fn drop(&mut self) {
+        let _guard = self.shared.mainloop.lock();
+
+        // reference of callbacks is released by state callback, even if operation ends after this
+        unsafe { pa_operation_unref(self.op) }
+    }
+}
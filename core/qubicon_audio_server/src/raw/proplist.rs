@@ -1,4 +1,4 @@
-use std::ffi::CStr;
+use std::{ ffi::{ c_void, CStr }, fmt::{ self, Display }, str::FromStr };
 use libpulse_sys::*;
 
 use crate::{Result, Error};
@@ -8,6 +8,7 @@ pub mod properties {
     #[allow(unused_imports)]
     pub use libpulse_sys::{
         PA_PROP_MEDIA_NAME as MEDIA_NAME,
+        PA_PROP_MEDIA_TITLE as MEDIA_TITLE,
         PA_PROP_MEDIA_ARTIST as MEDIA_ARTIST,
         PA_PROP_MEDIA_COPYRIGHT as MEDIA_COPYRIGHT,
         PA_PROP_MEDIA_SOFTWARE as MEDIA_SOFTWARE,
@@ -50,7 +51,8 @@ pub mod properties {
         PA_PROP_APPLICATION_PROCESS_ID as APPLICATION_PROCESS_ID,
         PA_PROP_APPLICATION_PROCESS_BINARY as APPLICATION_PROCESS_BINARY,
         PA_PROP_APPLICATION_PROCESS_USER as APPLICATION_PROCESS_USER,
-        PA_PROP_APPLICATION_PROCESS_HOST as APPLCIATION_PROCESS_HOST,
+        PA_PROP_APPLICATION_PROCESS_HOST as APPLICATION_PROCESS_HOST,
+        PA_PROP_APPLICATION_PROCESS_MACHINE_ID as APPLICATION_PROCESS_MACHINE_ID,
         PA_PROP_APPLICATION_PROCESS_MACHINE_ID as APPLICATION_MACHINE_ID,
         PA_PROP_APPLICATION_PROCESS_SESSION_ID as APPLICATION_PROCESS_SESSION_ID,
         PA_PROP_DEVICE_STRING as DEVICE_STRING,
@@ -75,14 +77,73 @@ pub mod properties {
         PA_PROP_DEVICE_INTENDED_ROLES as DEVICE_INTENDED_ROLES,
         PA_PROP_DEVICE_PROFILE_DESCRIPTION as DEVICE_PROFILE_DESCRIPTION,
         PA_PROP_MODULE_AUTHOR as MODULE_AUTHOR,
+        PA_PROP_MODULE_DESCRIPTION as MODULE_DESCRIPTION,
         PA_PROP_MODULE_USAGE as MODULE_USAGE,
         PA_PROP_MODULE_VERSION as MODULE_VERSION,
         PA_PROP_FORMAT_SAMPLE_FORMAT as FORMAT_SAMPLE_FORMAT,
         PA_PROP_FORMAT_RATE as FORMAT_RATE,
         PA_PROP_FORMAT_CHANNELS as FORMAT_CHANNELS,
         PA_PROP_FORMAT_CHANNEL_MAP as FORMAT_CHANNEL_MAP
-        // there should be two more from newer versions of pulseaudio
     };
+
+    // libpulse-sys has these only behind version features
+
+    /// Since PulseAudio 13. "true" disables shared memory transport for the client
+    pub const CONTEXT_FORCE_DISABLE_SHM: &str = "context.force.disable.shm";
+    /// Since PulseAudio 15. Codec of bluetooth device, e.g. "sbc" or "aptx"
+    pub const BLUETOOTH_CODEC: &str = "bluetooth.codec";
+
+    #[deprecated = "misspelled, use APPLICATION_PROCESS_HOST"]
+    pub const APPLCIATION_PROCESS_HOST: &str = APPLICATION_PROCESS_HOST;
+}
+
+/// Value of [`properties::MEDIA_ROLE`]. Policy modules use it to route and duck streams
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MediaRole {
+    Video,
+    Music,
+    Game,
+    /// Short sounds, e.g. notifications and UI clicks
+    Event,
+    /// Voice chat
+    Phone,
+    Animation,
+    Production,
+    /// Accessibility, e.g. screen readers
+    A11y,
+    Test
+}
+
+impl MediaRole {
+    pub const ALL: [Self; 9] = [
+        Self::Video, Self::Music, Self::Game, Self::Event, Self::Phone,
+        Self::Animation, Self::Production, Self::A11y, Self::Test
+    ];
+
+    /// Name, what PulseAudio uses
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Video => "video",
+            Self::Music => "music",
+            Self::Game => "game",
+            Self::Event => "event",
+            Self::Phone => "phone",
+            Self::Animation => "animation",
+            Self::Production => "production",
+            Self::A11y => "a11y",
+            Self::Test => "test"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(| r | r.name() == name)
+    }
+}
+
+impl Display for MediaRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
 
@@ -95,6 +156,15 @@ impl Proplist {
         self.0
     }
 
+    /// Copies proplist, what is owned by PulseAudio
+    pub(crate) unsafe fn copy_from_raw(proplist: *const pa_proplist) -> Self {
+        if proplist.is_null() {
+            Self::new()
+        } else {
+            Self ( pa_proplist_copy(proplist) )
+        }
+    }
+
 
     pub fn new() -> Self {
         unsafe { Self ( pa_proplist_new() ) }
@@ -148,6 +218,26 @@ impl Proplist {
         })
     }
 
+    /// Keys in no particular order
+    pub fn keys(&self) -> ProplistKeys<'_> {
+        ProplistKeys { proplist: self, state: core::ptr::null_mut() }
+    }
+
+    /// Parses format of [`Display`], e.g. `media.role = "game" application.process.id = "42"`.
+    /// Binary values are written as `hex:0a0b`. None, if string is malformed
+    pub fn from_string(string: &str) -> Option<Self> {
+        let proplist = super::with_c_string(string, | string | unsafe { pa_proplist_from_string(string.as_ptr()) });
+
+        (!proplist.is_null()).then(|| Self ( proplist ))
+    }
+
+    fn get_parsed<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get_string(key)?.parse().ok()
+    }
+
+    fn set_display(&mut self, key: &str, value: impl Display) -> Result<()> {
+        self.set_string(key, &value.to_string())
+    }
 
 
 
@@ -160,7 +250,7 @@ impl Proplist {
             super::with_c_string(key, | key | {
                 handle_pa_error!(pa_proplist_set(self.0, key.as_ptr(), value.as_ptr().cast(), value.len()))
                     .map(| _ | ())
-                    .map_err(| e | Error::ProplistEditError { pa_error: e })
+                    .map_err(| e | Error::ProplistEditError { source: e.into() })
             })
         }
     }
@@ -172,7 +262,7 @@ impl Proplist {
                 super::with_c_string(value, | value | {
                     handle_pa_error!(pa_proplist_sets(self.0, key.as_ptr(), value.as_ptr()))
                         .map(| _ | ())
-                        .map_err(| e | Error::ProplistEditError { pa_error: e })
+                        .map_err(| e | Error::ProplistEditError { source: e.into() })
                 })
             })
         }
@@ -183,7 +273,7 @@ impl Proplist {
             super::with_c_string(key, | key | {
                 handle_pa_error!(pa_proplist_unset(self.0, key.as_ptr()))
                     .map(| _ | ())
-                    .map_err(| e | Error::ProplistEditError { pa_error: e })
+                    .map_err(| e | Error::ProplistEditError { source: e.into() })
             })
         }
     }
@@ -194,6 +284,80 @@ impl Proplist {
     }
 }
 
+// typed access to well known properties. Getters return None for missing and malformed values
+impl Proplist {
+    pub fn media_name(&self) -> Option<&str> {
+        self.get_string(properties::MEDIA_NAME)
+    }
+
+    pub fn set_media_name(&mut self, name: &str) -> Result<()> {
+        self.set_string(properties::MEDIA_NAME, name)
+    }
+
+    /// None also for roles, what are not in [`MediaRole`]. Use [`Self::get_string`] to read them
+    pub fn media_role(&self) -> Option<MediaRole> {
+        MediaRole::from_name(self.get_string(properties::MEDIA_ROLE)?)
+    }
+
+    pub fn set_media_role(&mut self, role: MediaRole) -> Result<()> {
+        self.set_string(properties::MEDIA_ROLE, role.name())
+    }
+
+    pub fn application_name(&self) -> Option<&str> {
+        self.get_string(properties::APPLICATION_NAME)
+    }
+
+    pub fn set_application_name(&mut self, name: &str) -> Result<()> {
+        self.set_string(properties::APPLICATION_NAME, name)
+    }
+
+    pub fn process_id(&self) -> Option<u32> {
+        self.get_parsed(properties::APPLICATION_PROCESS_ID)
+    }
+
+    pub fn set_process_id(&mut self, pid: u32) -> Result<()> {
+        self.set_display(properties::APPLICATION_PROCESS_ID, pid)
+    }
+
+    /// Window system specific id of window, what plays the stream. XID on X11
+    pub fn window_id(&self) -> Option<u64> {
+        self.get_parsed(properties::WINDOW_ID)
+    }
+
+    pub fn set_window_id(&mut self, id: u64) -> Result<()> {
+        self.set_display(properties::WINDOW_ID, id)
+    }
+
+    pub fn x11_window_id(&self) -> Option<u64> {
+        self.get_parsed(properties::WINDOW_X11_XID)
+    }
+
+    pub fn set_x11_window_id(&mut self, xid: u64) -> Result<()> {
+        self.set_display(properties::WINDOW_X11_XID, xid)
+    }
+}
+
+/// Iterator over keys of [`Proplist`]
+pub struct ProplistKeys<'a> {
+    proplist: &'a Proplist,
+    state: *mut c_void
+}
+
+impl<'a> Iterator for ProplistKeys<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = unsafe { pa_proplist_iterate(self.proplist.0, &mut self.state) };
+
+        if key.is_null() {
+            return None;
+        }
+
+        // libpulse accepts only ASCII keys
+        Some( unsafe { core::str::from_utf8_unchecked(CStr::from_ptr(key).to_bytes()) } )
+    }
+}
+
 impl Default for Proplist {
     fn default() -> Self {
         Self::new()
@@ -218,6 +382,28 @@ impl Clone for Proplist {
     }
 }
 
+impl std::fmt::Debug for Proplist {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Proplist")
+            .field("size", &self.size())
+            .finish()
+    }
+}
+
+/// One line of `key = "value"` pairs, what can be parsed back with [`Proplist::from_string`]
+impl Display for Proplist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        unsafe {
+            let string = super::with_c_string(" ", | separator | pa_proplist_to_string_sep(self.0, separator.as_ptr()));
+            let result = f.write_str(&CStr::from_ptr(string).to_string_lossy());
+
+            pa_xfree(string.cast());
+
+            result
+        }
+    }
+}
+
 impl Drop for Proplist {
     fn drop(&mut self) {
         unsafe { pa_proplist_free(self.0); }
@@ -225,10 +411,112 @@ impl Drop for Proplist {
 }
 
 
+/// Map of keys to values. Text values are strings, binary ones are bytes
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use core::fmt;
+    use serde::{ Serialize, Serializer, Deserialize, Deserializer, ser::SerializeMap, de::{ self, Visitor, MapAccess, SeqAccess } };
+
+    use super::Proplist;
+
+    struct Bytes<'a> (&'a [u8]);
+
+    impl Serialize for Bytes<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    impl Serialize for Proplist {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(self.size() as usize))?;
+
+            for key in self.keys() {
+                match self.get_string(key) {
+                    Some(text) => map.serialize_entry(key, text)?,
+                    None => map.serialize_entry(key, &Bytes(self.get(key).unwrap_or_default()))?
+                }
+            }
+
+            map.end()
+        }
+    }
+
+    enum Value {
+        Text(String),
+        Bytes(Vec<u8>)
+    }
+
+    struct ValueVisitor;
+
+    impl<'de> Visitor<'de> for ValueVisitor {
+        type Value = Value;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("string or bytes")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+            Ok ( Value::Text(v.into()) )
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Value, E> {
+            Ok ( Value::Bytes(v.into()) )
+        }
+
+        // formats without bytes, like JSON, write them as arrays
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+
+            Ok ( Value::Bytes(bytes) )
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Value {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(ValueVisitor)
+        }
+    }
+
+    struct ProplistVisitor;
+
+    impl<'de> Visitor<'de> for ProplistVisitor {
+        type Value = Proplist;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("map of properties")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Proplist, A::Error> {
+            let mut proplist = Proplist::new();
+
+            while let Some((key, value)) = map.next_entry::<String, Value>()? {
+                match value {
+                    Value::Text(text) => proplist.set_string(&key, &text),
+                    Value::Bytes(bytes) => proplist.set(&key, &bytes)
+                }.map_err(de::Error::custom)?;
+            }
+
+            Ok ( proplist )
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Proplist {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_map(ProplistVisitor)
+        }
+    }
+}
+
+
 
 #[cfg(test)]
 mod tests {
-    use super::Proplist;
+    use super::{ Proplist, MediaRole, properties };
 
     #[test]
     fn is_empty() {
@@ -271,4 +559,88 @@ mod tests {
             &[1, 2, 3, 4, 5, 6, 7, 8]
         )
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn typed_entries() {
+        let mut proplist = Proplist::new();
+
+        proplist.set_media_role(MediaRole::Game).unwrap();
+        proplist.set_process_id(4242).unwrap();
+        proplist.set_x11_window_id(0x3c00007).unwrap();
+
+        assert_eq!(proplist.get_string(properties::MEDIA_ROLE), Some("game"));
+        assert_eq!(proplist.media_role(), Some(MediaRole::Game));
+        assert_eq!(proplist.process_id(), Some(4242));
+        assert_eq!(proplist.x11_window_id(), Some(0x3c00007));
+        assert_eq!(proplist.window_id(), None);
+
+        // unknown role and malformed number
+        proplist.set_string(properties::MEDIA_ROLE, "dance").unwrap();
+        proplist.set_string(properties::APPLICATION_PROCESS_ID, "pid").unwrap();
+
+        assert_eq!(proplist.media_role(), None);
+        assert_eq!(proplist.process_id(), None);
+    }
+
+    #[test]
+    fn keys() {
+        let mut proplist = Proplist::new();
+
+        proplist.set_media_name("theme").unwrap();
+        proplist.set("some_raw_value", &[1, 2]).unwrap();
+
+        let mut keys: Vec<&str> = proplist.keys().collect();
+        keys.sort();
+
+        assert_eq!(keys, [properties::MEDIA_NAME, "some_raw_value"]);
+        assert_eq!(Proplist::new().keys().count(), 0);
+    }
+
+    #[test]
+    fn string_round_trip() {
+        let mut proplist = Proplist::new();
+
+        proplist.set_media_name("main theme").unwrap();
+        proplist.set_media_role(MediaRole::Music).unwrap();
+        proplist.set("some_raw_value", &[1, 2, 0xff]).unwrap();
+
+        let string = proplist.to_string();
+
+        assert!(string.contains("media.name = \"main theme\""), "{string}");
+        assert_eq!(Proplist::from_string(&string), Some(proplist));
+
+        assert_eq!(Proplist::from_string("media.name"), None);
+        assert_eq!(Proplist::from_string(""), Some(Proplist::new()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        use serde_test::{ Token, assert_tokens, assert_de_tokens };
+
+        let mut proplist = Proplist::new();
+        proplist.set_media_role(MediaRole::Event).unwrap();
+
+        assert_tokens(&proplist, &[
+            Token::Map { len: Some(1) },
+            Token::Str("media.role"), Token::Str("event"),
+            Token::MapEnd
+        ]);
+
+        let mut proplist = Proplist::new();
+        proplist.set("some_raw_value", &[1, 2]).unwrap();
+
+        assert_tokens(&proplist, &[
+            Token::Map { len: Some(1) },
+            Token::Str("some_raw_value"), Token::Bytes(&[1, 2]),
+            Token::MapEnd
+        ]);
+
+        // as it comes from JSON
+        assert_de_tokens(&proplist, &[
+            Token::Map { len: Some(1) },
+            Token::Str("some_raw_value"), Token::Seq { len: Some(2) }, Token::U8(1), Token::U8(2), Token::SeqEnd,
+            Token::MapEnd
+        ]);
+    }
+}
@@ -1,81 +1,76 @@
-use std::{ ffi::CStr, marker::PhantomData, pin::Pin, ops::Deref};
+use std::{ ffi::{ CStr, CString }, marker::PhantomData, pin::Pin, ops::Deref, sync::{ Arc, Mutex, atomic::{AtomicU64, Ordering} }, time::{ Duration, SystemTime } };
 use bitflags::bitflags;
 use libpulse_sys::*;
 
-use crate::{Error, Result, raw::{ ChannelMap, Proplist }};
+use crate::{Error, Result, error::StreamOperation, sample::Sample, raw::{ ChannelMap, Proplist, Mainloop, MainloopGuard, Operation, DeviceKind, operation::{ self, context_error } }, ring_buffer::{ ring_buffer, RingProducer, RingConsumer }};
+
+// pa_context_set_sink_input_volume or pa_context_set_source_output_volume
+type VolumeSetter = unsafe extern "C" fn(*mut pa_context, u32, *const pa_cvolume, pa_context_success_cb_t, *mut core::ffi::c_void) -> *mut pa_operation;
+// pa_context_set_sink_input_mute or pa_context_set_source_output_mute
+type MuteSetter = unsafe extern "C" fn(*mut pa_context, u32, i32, pa_context_success_cb_t, *mut core::ffi::c_void) -> *mut pa_operation;
 
 mod callbacks {
     use libpulse_sys::*;
     use super::*;
 
-    // pub extern "C" fn stream_write_callback<F: Format>(stream: *mut pa_stream, writable_len: usize, data: *mut core::ffi::c_void) {
-    //     unsafe {
-    //         let data = &mut *data.cast::<PlaybackStream<F>>();
-    //         let (first_chunk, second_chunk) = data.buf.as_slices();
-
-    //         let first_len = core::mem::size_of_val(first_chunk);
-    //         let second_len = core::mem::size_of_val(second_chunk);
-
-    //         let mut total_written = 0;
-
-    //         {
-    //             let write_ammount = first_len.min(writable_len);
-
-    //             total_written += write_ammount;
-
-    //             let _ = pa_stream_write(
-    //                 stream,
-    //                 first_chunk.as_ptr().cast(),
-    //                 write_ammount,
-    //                 None,
-    //                 0,
-    //                 pa_seek_mode_t::Relative
-    //             );
-    //         }
-            
-    //         if writable_len > first_len {
-    //             let writable_len = writable_len - first_len;
-    //             let write_ammount = second_len.min(writable_len);
-
-    //             total_written += write_ammount;
-                
-    //             let _ = pa_stream_write(
-    //                 stream,
-    //                 second_chunk.as_ptr().cast(),
-    //                 write_ammount,
-    //                 None,
-    //                 0,
-    //                 pa_seek_mode_t::Relative
-    //             );
-    //         }
-
-    //         let _ = data.buf.drain(0..(total_written / core::mem::size_of::<F>()));
-    //     }
-    // }
-
-    // pub extern "C" fn stream_underflow_callback(stream: *mut pa_stream, data: *mut core::ffi::c_void) {
-    //     unsafe {
-    //         let data = &mut *data.cast::<BaseStream>();
-    //     }
-    // }
+    pub extern "C" fn stream_write_callback<F: Format>(_stream: *mut pa_stream, writable_len: usize, data: *mut core::ffi::c_void) {
+        unsafe {
+            let data = &mut *data.cast::<PlaybackStream<F>>();
+
+            data.write_from_buffer(writable_len, true);
+        }
+    }
+
+    pub extern "C" fn stream_underflow_callback<F: Format>(_stream: *mut pa_stream, data: *mut core::ffi::c_void) {
+        unsafe {
+            let data = &*data.cast::<PlaybackStream<F>>();
+
+            data.underflows.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub extern "C" fn stream_latency_update_callback(stream: *mut pa_stream, data: *mut core::ffi::c_void) {
+        unsafe {
+            let data = &mut *data.cast::<BaseStream>();
+            let info = pa_stream_get_timing_info(stream);
+
+            if let (Some(callback), false) = (data.timing_callback.as_mut(), info.is_null()) {
+                callback(TimingInfo::from(&*info));
+            }
+        }
+    }
+
+    pub extern "C" fn stream_overflow_callback<F: Format>(_stream: *mut pa_stream, data: *mut core::ffi::c_void) {
+        unsafe {
+            let data = &*data.cast::<PlaybackStream<F>>();
+
+            data.overflows.fetch_add(1, Ordering::Relaxed);
+        }
+    }
 }
 
-pub trait Format: format_trait_sealed::FormatSealed {}
+/// Sample type, what can be sent to PulseAudio as is. See [`crate::sample`] for formats with explicit byte order
+pub trait Format: format_trait_sealed::FormatSealed + Sample + Copy + Send + 'static {}
 
-impl<T: format_trait_sealed::FormatSealed> Format for T {}
+impl<T: format_trait_sealed::FormatSealed + Sample + Copy + Send + 'static> Format for T {}
 
 mod format_trait_sealed {
     use libpulse_sys::*;
+    use crate::sample::*;
     
-    pub trait FormatSealed {
+    pub trait FormatSealed: Sized {
         const FORMAT: pa_sample_format_t;
+        const SILENCE: Self;
     }
 
     impl FormatSealed for u8 {
         const FORMAT: pa_sample_format_t = pa_sample_format_t::U8;
+        const SILENCE: Self = 0x80;
     }
 
     impl FormatSealed for i16 {
+        const SILENCE: Self = 0;
+
         #[cfg(target_endian = "little")]
         const FORMAT: pa_sample_format_t = pa_sample_format_t::S16le;
     
@@ -84,6 +79,8 @@ mod format_trait_sealed {
     }
 
     impl FormatSealed for i32 {
+        const SILENCE: Self = 0;
+
         #[cfg(target_endian = "little")]
         const FORMAT: pa_sample_format_t = pa_sample_format_t::S32le;
     
@@ -92,13 +89,40 @@ mod format_trait_sealed {
     }
 
     impl FormatSealed for f32 {
+        const SILENCE: Self = 0.0;
+
         #[cfg(target_endian = "little")]
         const FORMAT: pa_sample_format_t = pa_sample_format_t::F32le;
     
         #[cfg(target_endian = "big")]
         const FORMAT: pa_sample_format_t = pa_sample_format_t::F32be;
     }
-    // TODO: more formats
+
+    macro_rules! sealed_format {
+        ($($ty:ty => $format:ident, $silence:expr;)*) => {
+            $(
+                impl FormatSealed for $ty {
+                    const FORMAT: pa_sample_format_t = pa_sample_format_t::$format;
+                    const SILENCE: Self = $silence;
+                }
+            )*
+        };
+    }
+
+    sealed_format! {
+        I16Le => S16le, I16Le([0; 2]);
+        I16Be => S16be, I16Be([0; 2]);
+        I24Le => S24le, I24Le([0; 3]);
+        I24Be => S24be, I24Be([0; 3]);
+        I24In32Le => S24_32le, I24In32Le([0; 4]);
+        I24In32Be => S24_32be, I24In32Be([0; 4]);
+        I32Le => S32le, I32Le([0; 4]);
+        I32Be => S32be, I32Be([0; 4]);
+        F32Le => F32le, F32Le([0; 4]);
+        F32Be => F32be, F32Be([0; 4]);
+        ALaw => ALaw, ALaw(0xD5);
+        MuLaw => ULaw, MuLaw(0xFF);
+    }
 }
 
 bitflags! {
@@ -144,6 +168,25 @@ pub struct BufferAttributes {
     frag_size: u32
 }
 
+impl BufferAttributes {
+    /// All values are in bytes. `u32::MAX` means server default
+    pub const fn new(max_length: u32, tlength: u32, pre_buf: u32, min_req: u32, frag_size: u32) -> Self {
+        Self { max_length, tlength, pre_buf, min_req, frag_size }
+    }
+
+    /// Target length of playback buffer in bytes. None for server default
+    pub(crate) fn target_length(&self) -> Option<u32> {
+        (self.tlength != u32::MAX).then_some(self.tlength)
+    }
+}
+
+impl Default for BufferAttributes {
+    fn default() -> Self {
+        Self::new(u32::MAX, u32::MAX, u32::MAX, u32::MAX, u32::MAX)
+    }
+}
+
+#[allow(clippy::from_over_into)]
 impl Into<pa_buffer_attr> for BufferAttributes {
     fn into(self) -> pa_buffer_attr {
         pa_buffer_attr {
@@ -165,80 +208,445 @@ pub trait StreamWrite<F> {
 
 pub trait StreamRead<F> {
     fn available_len(self: Pin<&Self>) -> Result<usize>;
-    fn read(self: Pin<&mut Self>, data: &mut [F]) -> Result<usize>;
+    fn read(self: Pin<&mut Self>, data: &mut [F]) -> Result<ReadResult>;
+}
+
+/// Outcome of [`StreamRead::read`]. Lengths are in samples, not frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReadResult {
+    /// This ammount of samples was copied into the buffer. Always a multiple of channel count
+    Data(usize),
+    /// Server has no data for this ammount of samples (e.g. after overflow).
+    /// Nothing was written into the buffer
+    Hole(usize)
+}
+
+/// Snapshot of stream timing, what server sent last time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimingInfo {
+    /// When this info was current
+    pub timestamp: SystemTime,
+    /// Time, what it takes for sink to play sample after it left sink buffer
+    pub sink_latency: Duration,
+    /// Time, what it takes for sample recorded by source to be available for stream
+    pub source_latency: Duration,
+    /// Time, what it takes for data to travel between client and server
+    pub transport_latency: Duration,
+    pub playing: bool,
+    /// Byte position of write pointer in server buffer. None, if it is not known after flush or seek
+    pub write_index: Option<i64>,
+    /// Byte position of read pointer in server buffer
+    pub read_index: Option<i64>,
+    /// Bytes played since last underrun
+    pub since_underrun: i64
+}
+
+impl From<&pa_timing_info> for TimingInfo {
+    fn from(value: &pa_timing_info) -> Self {
+        let timestamp = Duration::from_secs(value.timestamp.tv_sec as u64) + Duration::from_micros(value.timestamp.tv_usec as u64);
+
+        Self {
+            timestamp: SystemTime::UNIX_EPOCH + timestamp,
+            sink_latency: Duration::from_micros(value.sink_usec),
+            source_latency: Duration::from_micros(value.source_usec),
+            transport_latency: Duration::from_micros(value.transport_usec),
+            playing: value.playing != 0,
+            write_index: (value.write_index_corrupt == 0).then_some(value.write_index),
+            read_index: (value.read_index_corrupt == 0).then_some(value.read_index),
+            since_underrun: value.since_underrun
+        }
+    }
+}
+
+type TimingCallback = Box<dyn FnMut(TimingInfo) + Send>;
+
+/// Live streams of a context. They are recreated, when context reconnects
+pub(crate) type StreamRegistry = Arc<Mutex<Vec<RegisteredStream>>>;
+
+pub(crate) struct RegisteredStream {
+    base: *const BaseStream,
+    // PlaybackStream or RecordStream, what owns base
+    stream: *mut core::ffi::c_void,
+    reconnect: unsafe fn(*mut core::ffi::c_void, *mut pa_context)
+}
+
+// only touched under mainloop lock
+unsafe impl Send for RegisteredStream {}
+
+impl RegisteredStream {
+    /// Creates stream again on new context. Mainloop lock should be held
+    pub(crate) unsafe fn reconnect(&self, ctx: *mut pa_context) {
+        (self.reconnect)(self.stream, ctx)
+    }
 }
 
 
 
 pub struct BaseStream {
     stream: *mut pa_stream,
+    mainloop: Mainloop,
+
+    name: String,
 
     rate: u32,
     channel_map: ChannelMap,
 
-    paused: bool,
+    // kept to recreate stream after reconnection
+    format: pa_sample_format_t,
+    device: Option<CString>,
+    flags: StreamFlags,
+    properties: Option<Proplist>,
+    buffer_attributes: Option<BufferAttributes>,
+    registry: Option<StreamRegistry>,
+
+    // called from latency update callback
+    timing_callback: Option<TimingCallback>,
+
     _ph: std::marker::PhantomPinned
 }
 
 impl BaseStream {
-    /// # Safety
-    /// Ideally, value should be pinned to be safely usable in callbacks
-    pub unsafe fn new_unpinned(
-        ctx: *mut pa_context,
+    /// Server side of stream is not created until [`Self::create_raw`]
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new_unpinned(
+        mainloop: Mainloop,
         name: &CStr,
+        device: Option<&CStr>,
         format: pa_sample_format_t,
         rate: u32,
         channel_map: &ChannelMap,
-        properties: Option<&Proplist>
+        flags: StreamFlags,
+        properties: Option<&Proplist>,
+        buffer_attributes: Option<&BufferAttributes>
     ) -> Self {
-        let sample_spec = pa_sample_spec {
-            format,
+        Self {
+            stream: core::ptr::null_mut(),
+            mainloop,
+
+            name: name.to_string_lossy().into_owned(),
 
             rate,
-            channels: channel_map.len() as u8
-        };
+            channel_map: channel_map.clone(),
+
+            format,
+            device: device.map(CString::from),
+            flags,
+            properties: properties.cloned(),
+            buffer_attributes: buffer_attributes.copied(),
+            registry: None,
+
+            timing_callback: None,
+
+            _ph: Default::default()
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn state(&self) -> pa_stream_state_t {
+        // stream is replaced by mainloop thread on reconnect
+        let _guard = self.lock();
+
+        if self.stream.is_null() {
+            return pa_stream_state_t::Failed;
+        }
+
+        unsafe { pa_stream_get_state(self.stream) }
+    }
+
+    /// Name of sink or source, what stream is connected to. None, if stream is not connected yet
+    pub fn device_name(&self) -> Option<String> {
+        let _guard = self.lock();
+
+        if self.stream.is_null() {
+            return None;
+        }
 
         unsafe {
-            let raw_channel_map = channel_map.into();
-            let stream = pa_stream_new_with_proplist(
-                ctx,
-                name.as_ptr(),
-                &sample_spec,
-                &raw_channel_map,
-                properties.map(| pl | pl.as_raw()).unwrap_or(core::ptr::null_mut())
-            );
+            let name = pa_stream_get_device_name(self.stream);
+
+            (!name.is_null()).then(|| CStr::from_ptr(name).to_string_lossy().into_owned())
+        }
+    }
 
-            Self {
-                stream,
+    /// Corks stream. Buffered samples are kept and played after [`Self::resume`]
+    pub fn pause(&self) -> Result<Operation> {
+        self.cork(true)
+    }
 
-                rate,
-                channel_map: channel_map.clone(),
+    pub fn resume(&self) -> Result<Operation> {
+        self.cork(false)
+    }
 
-                paused: false,
+    /// Also true for streams created with [`StreamFlags::START_CORKED`], until they are resumed
+    pub fn is_paused(&self) -> bool {
+        let _guard = self.lock();
 
-                _ph: Default::default()
-            }
+        if self.stream.is_null() {
+            return false;
         }
+
+        unsafe { pa_stream_is_corked(self.stream) == 1 }
     }
 
-    pub fn state(&self) -> pa_stream_state_t {
-        unsafe { pa_stream_get_state(self.stream) }
+    /// Total delay of the stream. For playback it is time before written sample is heard,
+    /// for record it is time since sample was recorded. Negative record latency is returned as zero.
+    ///
+    /// Fails with `NoData`, if server has not sent timing info yet
+    pub fn latency(&self) -> Result<Duration> {
+        let _guard = self.lock();
+
+        self._is_ready()?;
+
+        unsafe {
+            let mut usec = 0;
+            let mut negative = 0;
+
+            handle_pa_error!(pa_stream_get_latency(self.stream, &mut usec, &mut negative))
+                .map_err(| e | self.error(StreamOperation::QueryTiming, e))?;
+
+            Ok ( if negative != 0 { Duration::ZERO } else { Duration::from_micros(usec) } )
+        }
+    }
+
+    /// Last timing info, what server sent. None, if there was no update yet
+    pub fn timing_info(&self) -> Option<TimingInfo> {
+        let _guard = self.lock();
+
+        if self.stream.is_null() {
+            return None;
+        }
+
+        unsafe {
+            let info = pa_stream_get_timing_info(self.stream);
+
+            (!info.is_null()).then(|| TimingInfo::from(&*info))
+        }
+    }
+
+    /// Requests fresh timing info. Not needed with [`StreamFlags::AUTO_TIMING_UPDATE`]
+    pub fn update_timing(&self) -> Result<Operation> {
+        self.stream_operation(
+            StreamOperation::QueryTiming,
+            | stream, cb, data | unsafe { pa_stream_update_timing_info(stream, cb, data) }
+        )
+    }
+
+    /// Drops all buffered samples, including ones in server buffer
+    pub fn flush(&self) -> Result<Operation> {
+        self.stream_operation(
+            StreamOperation::Flush,
+            | stream, cb, data | unsafe { pa_stream_flush(stream, cb, data) }
+        )
+    }
+
+    /// Locks mainloop, if it is threaded. Every call on stream should be done under this lock
+    pub(crate) fn lock(&self) -> MainloopGuard {
+        self.mainloop.lock()
     }
 }
 
 impl BaseStream {
+    // stream is null after failed reconnect. Lock should be held
+    fn _is_created(&self) -> Result<()> {
+        match self.stream.is_null() {
+            true => Err(Error::StreamIsNotReady { stream: self.name.clone(), stream_state: pa_stream_state_t::Failed }),
+            false => Ok(())
+        }
+    }
+
+    // Lock should be held, stream is replaced on reconnect
     fn _is_ready(&self) -> Result<()> {
         let state = self.state();
 
         match state == pa_stream_state_t::Ready {
             true => Ok(()),
-            false => Err(Error::StreamIsNotReady { stream_state: state })
+            false => Err(Error::StreamIsNotReady { stream: self.name.clone(), stream_state: state })
+        }
+    }
+
+    // self should be pinned. Mainloop lock should be held
+    unsafe fn create_raw(&mut self, ctx: *mut pa_context) -> Result<()> {
+        let sample_spec = pa_sample_spec {
+            format: self.format,
+
+            rate: self.rate,
+            channels: self.channel_map.len() as u8
+        };
+        let raw_channel_map = (&self.channel_map).into();
+
+        self.stream = super::with_c_string(&self.name, | name | {
+            pa_stream_new_with_proplist(
+                ctx,
+                name.as_ptr(),
+                &sample_spec,
+                &raw_channel_map,
+                self.properties.as_ref().map(| pl | pl.as_raw()).unwrap_or(core::ptr::null_mut())
+            )
+        });
+
+        if self.stream.is_null() {
+            return Err(self.error(StreamOperation::Connect, context_error(ctx)));
+        }
+
+        if self.timing_callback.is_some() {
+            pa_stream_set_latency_update_callback(self.stream, Some(callbacks::stream_latency_update_callback), (self as *mut Self).cast());
+        }
+
+        Ok ( () )
+    }
+
+    // frees stream of failed context. Pause state is kept for the new one
+    unsafe fn release_raw(&mut self) {
+        if self.stream.is_null() {
+            return;
+        }
+
+        self.flags.set(StreamFlags::START_CORKED, pa_stream_is_corked(self.stream) == 1);
+
+        pa_stream_unref(self.stream);
+        self.stream = core::ptr::null_mut();
+    }
+
+    // stream is connected to sink or source
+    unsafe fn connect_raw(&self, kind: DeviceKind) -> Result<()> {
+        let device = self.device.as_ref().map(| d | d.as_ptr()).unwrap_or(core::ptr::null());
+        let raw_buffer_attributes: Option<pa_buffer_attr> = self.buffer_attributes.map(| at | at.into());
+        let raw_buffer_attributes = raw_buffer_attributes.as_ref().map(| at | at as *const _).unwrap_or(core::ptr::null());
+
+        let result = match kind {
+            DeviceKind::Sink => pa_stream_connect_playback(self.stream, device, raw_buffer_attributes, self.flags.into(), core::ptr::null(), core::ptr::null_mut()),
+            DeviceKind::Source => pa_stream_connect_record(self.stream, device, raw_buffer_attributes, self.flags.into())
+        };
+
+        handle_pa_error!(result)
+            .map(| _ | ())
+            .map_err(| e | self.error(StreamOperation::Connect, e))
+    }
+
+    // self and stream should be pinned
+    unsafe fn register(&mut self, registry: &StreamRegistry, stream: *mut core::ffi::c_void, reconnect: unsafe fn(*mut core::ffi::c_void, *mut pa_context)) {
+        registry.lock().unwrap().push(RegisteredStream { base: self, stream, reconnect });
+
+        self.registry = Some(registry.clone());
+    }
+
+    fn error(&self, operation: StreamOperation, pa_error: pa_error_code_t) -> Error {
+        Error::StreamOperationFailed { stream: self.name.clone(), operation, source: pa_error.into() }
+    }
+
+    // same as error, but for operation, what fails later
+    fn operation_error(&self, operation: StreamOperation) -> impl Fn(pa_error_code_t) -> Error + 'static {
+        let stream = self.name.clone();
+
+        move | pa_error | Error::StreamOperationFailed { stream: stream.clone(), operation, source: pa_error.into() }
+    }
+
+    fn cork(&self, paused: bool) -> Result<Operation> {
+        self.stream_operation(
+            StreamOperation::Cork,
+            | stream, cb, data | unsafe { pa_stream_cork(stream, paused as i32, cb, data) }
+        )
+    }
+
+    fn stream_operation(
+        &self,
+        operation: StreamOperation,
+        start: impl FnOnce(*mut pa_stream, pa_stream_success_cb_t, *mut core::ffi::c_void) -> *mut pa_operation
+    ) -> Result<Operation> {
+        let _guard = self.lock();
+
+        self._is_ready()?;
+
+        unsafe {
+            Operation::start(
+                pa_stream_get_context(self.stream),
+                self.mainloop,
+                self.operation_error(operation),
+                | data | start(self.stream, Some(operation::callbacks::stream_success_callback), data)
+            )
+        }
+    }
+
+    // self should be pinned. Removing callback never fails
+    unsafe fn set_timing_callback_raw(&mut self, callback: Option<TimingCallback>) -> Result<()> {
+        let _guard = self.lock();
+
+        if callback.is_some() {
+            self._is_created()?;
+        }
+
+        let raw_callback: pa_stream_notify_cb_t = callback.as_ref().map(| _ | callbacks::stream_latency_update_callback as _);
+
+        self.timing_callback = callback;
+
+        if !self.stream.is_null() {
+            pa_stream_set_latency_update_callback(self.stream, raw_callback, (self as *mut Self).cast());
+        }
+
+        Ok ( () )
+    }
+
+    fn set_volume_with(&self, volumes: &[f32], setter: VolumeSetter) -> Result<Operation> {
+        if volumes.len() != self.channel_map.len() {
+            panic!("volume count {} does not match channel count {}", volumes.len(), self.channel_map.len());
+        }
+
+        let _guard = self.lock();
+
+        self._is_ready()?;
+
+        unsafe {
+            let mut volume: pa_cvolume = core::mem::zeroed();
+
+            volume.channels = volumes.len() as u8;
+            volume.values.iter_mut()
+                .zip(volumes)
+                .for_each(| (v, &linear) | *v = pa_sw_volume_from_linear(linear.max(0.0) as f64));
+
+            let ctx = pa_stream_get_context(self.stream);
+
+            Operation::start(
+                ctx,
+                self.mainloop,
+                self.operation_error(StreamOperation::SetVolume),
+                | data | setter(ctx, pa_stream_get_index(self.stream), &volume, Some(operation::callbacks::context_success_callback), data)
+            )
+        }
+    }
+
+    fn set_muted_with(&self, muted: bool, setter: MuteSetter) -> Result<Operation> {
+        let _guard = self.lock();
+
+        self._is_ready()?;
+
+        unsafe {
+            let ctx = pa_stream_get_context(self.stream);
+
+            Operation::start(
+                ctx,
+                self.mainloop,
+                self.operation_error(StreamOperation::SetMute),
+                | data | setter(ctx, pa_stream_get_index(self.stream), muted as i32, Some(operation::callbacks::context_success_callback), data)
+            )
         }
     }
 }
 
 impl Drop for BaseStream {
     fn drop(&mut self) {
+        let _guard = self.lock();
+
+        if let Some(registry) = self.registry.as_ref() {
+            registry.lock().unwrap().retain(| s | !core::ptr::eq(s.base, self));
+        }
+
+        if self.stream.is_null() {
+            return;
+        }
+
         unsafe {
             pa_stream_disconnect(self.stream);
             pa_stream_unref(self.stream);
@@ -250,46 +658,225 @@ impl Drop for BaseStream {
 
 pub struct PlaybackStream<F: Format> {
     base: BaseStream,
+
+    // Some, if stream is fed from ring buffer in write callback
+    buffer: Option<RingConsumer<F>>,
+
+    // modified in callbacks
+    underflows: AtomicU64,
+    overflows: AtomicU64,
+
     _ph: PhantomData<F>
 }
 
 impl<F: Format> PlaybackStream<F> {
-    pub fn new(
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
         ctx: *mut pa_context,
+        mainloop: Mainloop,
+        registry: &StreamRegistry,
         name: &CStr,
+        device: Option<&CStr>,
         rate: u32,
         channel_map: &ChannelMap,
         flags: StreamFlags,
         properties: Option<&Proplist>,
         buffer_attributes: Option<&BufferAttributes>
     ) -> Result<Pin<Box<Self>>> {
+        let _guard = mainloop.lock();
+
         unsafe {
-            let base = BaseStream::new_unpinned(ctx, name, F::FORMAT, rate, channel_map, properties);
-            let mut value = Box::pin( Self { base, _ph: Default::default() } );
+            let base = BaseStream::new_unpinned(mainloop, name, device, F::FORMAT, rate, channel_map, flags, properties, buffer_attributes);
+            let mut value = Box::pin(
+                Self {
+                    base,
+
+                    buffer: None,
+
+                    underflows: AtomicU64::new(0),
+                    overflows: AtomicU64::new(0),
+
+                    _ph: Default::default()
+                }
+            );
 
             {
                 let this = value.as_mut().get_unchecked_mut();
 
-                let raw_buffet_attributes = buffer_attributes.map(| &at | at.into());
-
-                handle_pa_error!(
-                    pa_stream_connect_playback(
-                        this.base.stream,
-                        core::ptr::null(),
-                        raw_buffet_attributes.as_ref().map(| at | at as *const _).unwrap_or(core::ptr::null()),
-                        flags.into(),
-                        core::ptr::null(),
-                        core::ptr::null_mut()
-                    )
-                ).unwrap();
-
-                //pa_stream_set_write_callback(this.base.stream, Some(callbacks::stream_write_callback::<F>), (this as *mut Self).cast());
-                //pa_stream_set_underflow_callback(this.base.stream, Some(callbacks::stream_underflow_callback), (this as *mut Self).cast());
+                this.connect(ctx)?;
+                let data = (this as *mut Self).cast();
+
+                this.base.register(registry, data, Self::reconnect);
             }
 
             Ok ( value )
         }
     }
+
+    // self should be pinned. Mainloop lock should be held
+    unsafe fn connect(&mut self, ctx: *mut pa_context) -> Result<()> {
+        self.base.create_raw(ctx)?;
+        self.base.connect_raw(DeviceKind::Sink)?;
+
+        let stream = self.base.stream;
+        let data = (self as *mut Self).cast();
+
+        pa_stream_set_underflow_callback(stream, Some(callbacks::stream_underflow_callback::<F>), data);
+        pa_stream_set_overflow_callback(stream, Some(callbacks::stream_overflow_callback::<F>), data);
+
+        if self.buffer.is_some() {
+            pa_stream_set_write_callback(stream, Some(callbacks::stream_write_callback::<F>), data);
+        }
+
+        Ok ( () )
+    }
+
+    // see RegisteredStream
+    unsafe fn reconnect(this: *mut core::ffi::c_void, ctx: *mut pa_context) {
+        let this = &mut *this.cast::<Self>();
+
+        this.base.release_raw();
+        // on failure stream stays failed, and user gets errors from it
+        let _ = this.connect(ctx);
+    }
+
+    /// Switches stream to pull mode. Server requests are served from ring buffer with `capacity` samples,
+    /// and returned producer can be moved to any thread to push samples without touching mainloop.
+    /// 
+    /// If buffer can't satisfy request, missing part is filled with silence and underflow is counted.
+    /// Latency in this mode is controlled by `tlength` of [`BufferAttributes`].
+    /// Calling this again replaces the buffer. Direct [`StreamWrite::write`] calls bypass the buffer.
+    ///
+    /// Fails, if stream was lost and could not be recreated
+    pub fn start_buffered(self: Pin<&mut Self>, capacity: usize) -> Result<RingProducer<F>> {
+        let (producer, consumer) = ring_buffer(capacity);
+
+        unsafe {
+            let this = self.get_unchecked_mut();
+            // write callback should not run while buffer is replaced
+            let _guard = this.base.lock();
+
+            this.base._is_created()?;
+            this.buffer = Some(consumer);
+
+            pa_stream_set_write_callback(this.base.stream, Some(callbacks::stream_write_callback::<F>), (this as *mut Self).cast());
+
+            // Request, what came before the callback, is not repeated by server, so stream would stall.
+            // New buffer is empty, so it is silence, but it is not an underflow of the buffer
+            match pa_stream_writable_size(this.base.stream) {
+                // not ready yet
+                usize::MAX | 0 => {},
+                writable => this.write_from_buffer(writable, false)
+            }
+        }
+
+        Ok ( producer )
+    }
+
+    /// How many times playback ran out of data.
+    /// Counts both server underflows and requests, what ring buffer could not fully satisfy
+    pub fn underflow_count(&self) -> u64 {
+        self.underflows.load(Ordering::Relaxed)
+    }
+
+    /// How many times server buffer overflowed
+    pub fn overflow_count(&self) -> u64 {
+        self.overflows.load(Ordering::Relaxed)
+    }
+
+    /// Time of the sample, what is being heard right now, since stream start.
+    /// Pauses and underflows stop it. With [`StreamFlags::INTERPOLATE_TIMING`] it is smooth between timing updates.
+    ///
+    /// Fails with `NoData`, if server has not sent timing info yet
+    pub fn playback_position(&self) -> Result<Duration> {
+        let _guard = self.lock();
+
+        self._is_ready()?;
+
+        unsafe {
+            let mut usec = 0;
+
+            handle_pa_error!(pa_stream_get_time(self.base.stream, &mut usec))
+                .map_err(| e | self.base.error(StreamOperation::QueryTiming, e))?;
+
+            Ok ( Duration::from_micros(usec) )
+        }
+    }
+
+    /// Same as [`Self::playback_position`], but in frames
+    pub fn playback_frame(&self) -> Result<u64> {
+        let position = self.playback_position()?;
+
+        Ok ( (position.as_micros() * self.base.rate as u128 / 1_000_000) as u64 )
+    }
+
+    /// Callback is called every time server sends timing info.
+    /// In polled mode it is called inside of update, in threaded mode from PulseAudio thread
+    pub fn set_timing_callback(self: Pin<&mut Self>, callback: impl FnMut(TimingInfo) + Send + 'static) -> Result<()> {
+        unsafe { self.get_unchecked_mut().base.set_timing_callback_raw(Some(Box::new(callback))) }
+    }
+
+    pub fn remove_timing_callback(self: Pin<&mut Self>) {
+        let _ = unsafe { self.get_unchecked_mut().base.set_timing_callback_raw(None) };
+    }
+
+    /// Linear volume of each channel, 1.0 is unchanged. Length should match channel count
+    pub fn set_volume(&self, volumes: &[f32]) -> Result<Operation> {
+        self.base.set_volume_with(volumes, pa_context_set_sink_input_volume)
+    }
+
+    pub fn set_muted(&self, muted: bool) -> Result<Operation> {
+        self.base.set_muted_with(muted, pa_context_set_sink_input_mute)
+    }
+
+    /// Completes, when everything written so far is played. Samples, what are still in ring buffer
+    /// of [`Self::start_buffered`], are not waited for. Stream keeps running after drain
+    pub fn drain(&self) -> Result<Operation> {
+        self.base.stream_operation(
+            StreamOperation::Drain,
+            | stream, cb, data | unsafe { pa_stream_drain(stream, cb, data) }
+        )
+    }
+
+    /// Starts playback without waiting for prebuffer to fill. Useful for sounds, what are shorter than prebuffer
+    pub fn trigger(&self) -> Result<Operation> {
+        self.base.stream_operation(
+            StreamOperation::Trigger,
+            | stream, cb, data | unsafe { pa_stream_trigger(stream, cb, data) }
+        )
+    }
+}
+
+impl<F: Format> PlaybackStream<F> {
+    // called from write callback. Lock should be held
+    unsafe fn write_from_buffer(&mut self, writable_len: usize, count_underflow: bool) {
+        let channels = self.base.channel_map.len();
+        let Some(buffer) = self.buffer.as_mut() else { return };
+
+        let mut len = writable_len;
+        let mut dst = core::ptr::null_mut();
+
+        if handle_pa_error!(pa_stream_begin_write(self.base.stream, &mut dst, &mut len)).is_err() {
+            return;
+        }
+
+        let dst = core::slice::from_raw_parts_mut(dst.cast::<F>(), len / core::mem::size_of::<F>());
+
+        // only whole frames, or channels will be shifted after silence
+        let available = (buffer.len() - buffer.len() % channels).min(dst.len());
+        let ammount = buffer.pop(&mut dst[..available]);
+
+        if ammount < dst.len() {
+            dst[ammount..].fill(F::SILENCE);
+
+            if count_underflow {
+                self.underflows.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let _ = handle_pa_error!(pa_stream_write(self.base.stream, dst.as_ptr().cast(), len, None, 0, pa_seek_mode_t::Relative))
+            .inspect_err(| _ | { pa_stream_cancel_write(self.base.stream); });
+    }
 }
 
 impl<F: Format> Deref for PlaybackStream<F> {
@@ -302,6 +889,8 @@ impl<F: Format> Deref for PlaybackStream<F> {
 
 impl<F: Format> StreamWrite<F> for PlaybackStream<F> {
     fn available_len(self: Pin<&Self>) -> Result<usize> {
+        let _guard = self.lock();
+
         // if not ready, return
         self._is_ready()?;
         
@@ -311,6 +900,8 @@ impl<F: Format> StreamWrite<F> for PlaybackStream<F> {
     }
 
     fn write(self: Pin<&mut Self>, data: &[F]) -> Result<usize> {
+        let _guard = self.lock();
+
         // if not ready, return
         self._is_ready()?;
 
@@ -319,13 +910,13 @@ impl<F: Format> StreamWrite<F> for PlaybackStream<F> {
             let mut dst = core::ptr::null_mut();
 
             handle_pa_error!(pa_stream_begin_write(self.base.stream, &mut dst, &mut len))
-                .map_err(| e | Error::StreamWriteError { pa_error: e })?;
+                .map_err(| e | self.base.error(StreamOperation::Write, e))?;
 
             core::ptr::copy_nonoverlapping(data.as_ptr(), dst.cast(), len / core::mem::size_of::<F>());
 
             handle_pa_error!(pa_stream_write(self.base.stream, dst, len, None, 0, pa_seek_mode_t::Relative))
                 .inspect_err(| _ | { pa_stream_cancel_write(self.base.stream); })
-                .map_err(| e | Error::StreamWriteError { pa_error: e })?;
+                .map_err(| e | self.base.error(StreamOperation::Write, e))?;
 
             Ok( len / core::mem::size_of::<F>() )
         }
@@ -336,43 +927,109 @@ impl<F: Format> StreamWrite<F> for PlaybackStream<F> {
 
 pub struct RecordStream<F: Format> {
     base: BaseStream,
+
+    // fragment returned by pa_stream_peek. Stays valid until pa_stream_drop
+    fragment: *const u8,
+    fragment_len: usize,
+    fragment_offset: usize,
+
     _ph: PhantomData<F>
 }
 
 impl<F: Format> RecordStream<F> {
-    pub fn new(
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
         ctx: *mut pa_context,
+        mainloop: Mainloop,
+        registry: &StreamRegistry,
         name: &CStr,
+        device: Option<&CStr>,
         rate: u32,
         channel_map: &ChannelMap,
         flags: StreamFlags,
         properties: Option<&Proplist>,
         buffer_attributes: Option<&BufferAttributes>
     ) -> Result<Pin<Box<Self>>> {
+        let _guard = mainloop.lock();
+
         unsafe {
-            let base = BaseStream::new_unpinned(ctx, name, F::FORMAT, rate, channel_map, properties);
-            let mut value = Box::pin(Self { base, _ph: Default::default() });
+            let base = BaseStream::new_unpinned(mainloop, name, device, F::FORMAT, rate, channel_map, flags, properties, buffer_attributes);
+            let mut value = Box::pin(
+                Self {
+                    base,
+
+                    fragment: core::ptr::null(),
+                    fragment_len: 0,
+                    fragment_offset: 0,
+
+                    _ph: Default::default()
+                }
+            );
 
             {
                 let this = value.as_mut().get_unchecked_mut();
 
-                let raw_buffer_attributes = buffer_attributes.map(| &at | at.into());
+                this.base.create_raw(ctx)?;
+                this.base.connect_raw(DeviceKind::Source)?;
+                let data = (this as *mut Self).cast();
 
-                handle_pa_error!(
-                    pa_stream_connect_record(
-                        this.stream,
-                        core::ptr::null(),
-                        raw_buffer_attributes.as_ref().map(| at | at as *const _).unwrap_or(core::ptr::null()),
-                        flags.into()
-                    )
-                ).unwrap();
-
-                // there should be code what registers callbacks
+                this.base.register(registry, data, Self::reconnect);
             }
 
             Ok ( value )
         }
     }
+
+    // see RegisteredStream
+    unsafe fn reconnect(this: *mut core::ffi::c_void, ctx: *mut pa_context) {
+        let this = &mut *this.cast::<Self>();
+
+        // fragment was freed together with old stream
+        this.fragment = core::ptr::null();
+        this.fragment_len = 0;
+        this.fragment_offset = 0;
+
+        this.base.release_raw();
+
+        if this.base.create_raw(ctx).is_ok() {
+            let _ = this.base.connect_raw(DeviceKind::Source);
+        }
+    }
+
+    /// See [`PlaybackStream::set_timing_callback`]
+    pub fn set_timing_callback(self: Pin<&mut Self>, callback: impl FnMut(TimingInfo) + Send + 'static) -> Result<()> {
+        unsafe { self.get_unchecked_mut().base.set_timing_callback_raw(Some(Box::new(callback))) }
+    }
+
+    pub fn remove_timing_callback(self: Pin<&mut Self>) {
+        let _ = unsafe { self.get_unchecked_mut().base.set_timing_callback_raw(None) };
+    }
+
+    /// Linear volume of each channel, 1.0 is unchanged. Length should match channel count
+    pub fn set_volume(&self, volumes: &[f32]) -> Result<Operation> {
+        self.base.set_volume_with(volumes, pa_context_set_source_output_volume)
+    }
+
+    pub fn set_muted(&self, muted: bool) -> Result<Operation> {
+        self.base.set_muted_with(muted, pa_context_set_source_output_mute)
+    }
+}
+
+impl<F: Format> RecordStream<F> {
+    fn frame_len(&self) -> usize {
+        self.base.channel_map.len() * core::mem::size_of::<F>()
+    }
+
+    // drops current fragment, so next peek will return new one
+    unsafe fn drop_fragment(&mut self) -> Result<()> {
+        self.fragment = core::ptr::null();
+        self.fragment_len = 0;
+        self.fragment_offset = 0;
+
+        handle_pa_error!(pa_stream_drop(self.base.stream))
+            .map(| _ | ())
+            .map_err(| e | self.base.error(StreamOperation::Read, e))
+    }
 }
 
 impl<F: Format> Deref for RecordStream<F> {
@@ -385,17 +1042,120 @@ impl<F: Format> Deref for RecordStream<F> {
 
 impl<F: Format> StreamRead<F> for RecordStream<F> {
     fn available_len(self: Pin<&Self>) -> Result<usize> {
+        let readable = {
+            let _guard = self.lock();
+
+            // if not ready, return
+            self._is_ready()?;
+
+            unsafe { pa_stream_readable_size(self.base.stream) }
+        };
+        // part of peeked fragment is already consumed, but server still counts it
+        let readable = readable.saturating_sub(self.fragment_offset);
+
+        Ok ( (readable - readable % self.frame_len()) / core::mem::size_of::<F>() )
+    }
+
+    fn read(self: Pin<&mut Self>, data: &mut [F]) -> Result<ReadResult> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let _guard = this.base.lock();
+
         // if not ready, return
-        self._is_ready()?;
+        this.base._is_ready()?;
+
+        let frame_len = this.frame_len();
+        // only whole frames are handed out
+        let capacity = core::mem::size_of_val(data) - core::mem::size_of_val(data) % frame_len;
+        let dst: *mut u8 = data.as_mut_ptr().cast();
+
+        let mut total_read = 0;
 
         unsafe {
-            Ok ( pa_stream_readable_size(self.base.stream) )
+            while total_read < capacity {
+                if this.fragment_len == 0 {
+                    let mut ptr = core::ptr::null();
+                    let mut len = 0;
+
+                    handle_pa_error!(pa_stream_peek(this.base.stream, &mut ptr, &mut len))
+                        .map_err(| e | this.base.error(StreamOperation::Read, e))?;
+
+                    // buffer is empty
+                    if len == 0 {
+                        break;
+                    }
+
+                    // hole in the buffer. Report it only if there is no data before it,
+                    // otherwise it will be peeked again on next call
+                    if ptr.is_null() {
+                        if total_read == 0 {
+                            handle_pa_error!(pa_stream_drop(this.base.stream))
+                                .map_err(| e | this.base.error(StreamOperation::Read, e))?;
+
+                            return Ok ( ReadResult::Hole(len / core::mem::size_of::<F>()) );
+                        }
+
+                        break;
+                    }
+
+                    this.fragment = ptr.cast();
+                    this.fragment_len = len;
+                    this.fragment_offset = 0;
+                }
+
+                let remaining = this.fragment_len - this.fragment_offset;
+                let ammount = (remaining - remaining % frame_len).min(capacity - total_read);
+
+                // fragment tail is smaller than frame, nothing to do with it
+                if ammount == 0 {
+                    this.drop_fragment()?;
+                    continue;
+                }
+
+                // fragment is not guaranteed to be aligned for F, so copy bytes
+                core::ptr::copy_nonoverlapping(
+                    this.fragment.add(this.fragment_offset),
+                    dst.add(total_read),
+                    ammount
+                );
+
+                total_read += ammount;
+                this.fragment_offset += ammount;
+
+                if this.fragment_offset + frame_len > this.fragment_len {
+                    this.drop_fragment()?;
+                }
+            }
         }
+
+        Ok ( ReadResult::Data(total_read / core::mem::size_of::<F>()) )
     }
-    fn read(self: Pin<&mut Self>, data: &mut [F]) -> Result<usize> {
-        // if not ready, return
-        self._is_ready()?;
+}
 
-        todo!()
+#[cfg(test)]
+mod tests {
+    use std::time::{ Duration, SystemTime };
+    use libpulse_sys::*;
+    use super::TimingInfo;
+
+    #[test]
+    fn timing_info() {
+        let mut raw: pa_timing_info = unsafe { core::mem::zeroed() };
+
+        raw.timestamp.tv_sec = 10;
+        raw.timestamp.tv_usec = 500;
+        raw.sink_usec = 20_000;
+        raw.transport_usec = 150;
+        raw.playing = 1;
+        raw.write_index = 4096;
+        raw.read_index_corrupt = 1;
+
+        let info = TimingInfo::from(&raw);
+
+        assert_eq!(info.timestamp, SystemTime::UNIX_EPOCH + Duration::from_micros(10_000_500));
+        assert_eq!(info.sink_latency, Duration::from_millis(20));
+        assert_eq!(info.transport_latency, Duration::from_micros(150));
+        assert!(info.playing);
+        assert_eq!(info.write_index, Some(4096));
+        assert_eq!(info.read_index, None);
     }
-}
\ No newline at end of file
+}
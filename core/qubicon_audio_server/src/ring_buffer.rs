@@ -0,0 +1,208 @@
+//! Lock-free single producer single consumer ring buffer.
+//! Used to hand samples from game thread to stream callbacks without touching mainloop
+
+use std::{cell::UnsafeCell, mem::MaybeUninit, sync::{atomic::{AtomicUsize, Ordering}, Arc}};
+
+struct Shared<T> {
+    buf: Box<[UnsafeCell<MaybeUninit<T>>]>,
+
+    // both indices are never wrapped by capacity, only by usize overflow.
+    // Real position in buffer is index % capacity
+
+    // index of next element to read. Written only by consumer
+    head: AtomicUsize,
+    // index of next element to write. Written only by producer
+    tail: AtomicUsize
+}
+
+// buffer slots are accessed by one side at a time, what is guaranteed by head and tail
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    fn len(&self) -> usize {
+        self.tail.load(Ordering::Acquire)
+            .wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+
+    // returns pointer to slot, what corresponds to index
+    fn slot(&self, idx: usize) -> *mut T {
+        self.buf[idx % self.capacity()].get().cast()
+    }
+}
+
+/// Creates new ring buffer and splits it to writing and reading halves.
+/// Capacity is in elements
+pub fn ring_buffer<T: Copy + Send>(capacity: usize) -> (RingProducer<T>, RingConsumer<T>) {
+    if capacity == 0 {
+        panic!("ring buffer capacity should be non zero");
+    }
+
+    let shared = Arc::new(
+        Shared {
+            buf: (0..capacity).map(| _ | UnsafeCell::new(MaybeUninit::uninit())).collect(),
+
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0)
+        }
+    );
+
+    (
+        RingProducer { shared: Arc::clone(&shared) },
+        RingConsumer { shared }
+    )
+}
+
+/// Writing half of ring buffer
+pub struct RingProducer<T: Copy + Send> {
+    shared: Arc<Shared<T>>
+}
+
+impl<T: Copy + Send> RingProducer<T> {
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+
+    /// Ammount of elements, what are not consumed yet
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Ammount of elements, what can be pushed right now
+    pub fn free_len(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// Pushes as much elements as possible. Returns ammount of pushed elements
+    pub fn push(&mut self, data: &[T]) -> usize {
+        let ammount = data.len().min(self.free_len());
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+
+        for (i, value) in data[..ammount].iter().enumerate() {
+            unsafe { self.shared.slot(tail.wrapping_add(i)).write(*value) }
+        }
+
+        self.shared.tail.store(tail.wrapping_add(ammount), Ordering::Release);
+
+        ammount
+    }
+}
+
+/// Reading half of ring buffer
+pub struct RingConsumer<T: Copy + Send> {
+    shared: Arc<Shared<T>>
+}
+
+impl<T: Copy + Send> RingConsumer<T> {
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+
+    /// Ammount of elements, what can be popped right now
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pops as much elements as fits into `out`. Returns ammount of popped elements
+    pub fn pop(&mut self, out: &mut [T]) -> usize {
+        let ammount = out.len().min(self.len());
+        let head = self.shared.head.load(Ordering::Relaxed);
+
+        for (i, value) in out[..ammount].iter_mut().enumerate() {
+            *value = unsafe { self.shared.slot(head.wrapping_add(i)).read() };
+        }
+
+        self.shared.head.store(head.wrapping_add(ammount), Ordering::Release);
+
+        ammount
+    }
+
+    /// Drops all elements, what are currently in buffer
+    pub fn clear(&mut self) {
+        let tail = self.shared.tail.load(Ordering::Acquire);
+
+        self.shared.head.store(tail, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ring_buffer;
+
+    #[test]
+    fn push_pop() {
+        let (mut producer, mut consumer) = ring_buffer::<u32>(4);
+
+        assert_eq!(producer.push(&[1, 2, 3, 4, 5, 6]), 4);
+        assert_eq!(producer.free_len(), 0);
+
+        let mut out = [0; 3];
+
+        assert_eq!(consumer.pop(&mut out), 3);
+        assert_eq!(out, [1, 2, 3]);
+
+        // wraps around the end
+        assert_eq!(producer.push(&[5, 6, 7]), 3);
+        assert_eq!(consumer.len(), 4);
+
+        let mut out = [0; 8];
+
+        assert_eq!(consumer.pop(&mut out), 4);
+        assert_eq!(&out[..4], &[4, 5, 6, 7]);
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn clear() {
+        let (mut producer, mut consumer) = ring_buffer::<u8>(8);
+
+        producer.push(&[1, 2, 3]);
+        consumer.clear();
+
+        assert!(producer.is_empty());
+        assert_eq!(producer.free_len(), 8);
+    }
+
+    #[test]
+    fn threaded_transfer() {
+        const TOTAL: u32 = 10_000;
+
+        let (mut producer, mut consumer) = ring_buffer::<u32>(64);
+
+        let thread = std::thread::spawn(move || {
+            let mut next = 0;
+
+            while next < TOTAL {
+                let chunk: Vec<u32> = (next..(next + 16).min(TOTAL)).collect();
+
+                next += producer.push(&chunk) as u32;
+            }
+        });
+
+        let mut expected = 0;
+        let mut out = [0; 32];
+
+        while expected < TOTAL {
+            let ammount = consumer.pop(&mut out);
+
+            for &value in &out[..ammount] {
+                assert_eq!(value, expected);
+
+                expected += 1;
+            }
+        }
+
+        thread.join().unwrap();
+    }
+}
@@ -0,0 +1,511 @@
+//! Sample types for every PulseAudio sample format and their conversion to and from f32.
+//! Types with explicit byte order are stored as byte arrays, so they have no alignment
+//! and can be read straight from stream buffers
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+/// Conversion between sample and f32 in [-1, 1]
+pub trait Sample: Copy {
+    /// Conversion is exact for formats up to 24 bits
+    fn to_f32(self) -> f32;
+
+    /// Rounds to nearest value and clamps to range of format
+    fn from_f32(value: f32) -> Self;
+
+    /// Same as [`Self::from_f32`], but adds TPDF dither of 1 LSB before rounding.
+    /// Removes quantization distortion at the cost of a little noise. Float formats ignore dither
+    fn from_f32_dithered(value: f32, dither: &mut Dither) -> Self {
+        let _ = dither;
+
+        Self::from_f32(value)
+    }
+}
+
+/// Converts samples to f32. Slices should have the same length
+pub fn convert_to_f32<S: Sample>(src: &[S], dst: &mut [f32]) {
+    if src.len() != dst.len() {
+        panic!("source has {} samples, but destination has {}", src.len(), dst.len());
+    }
+
+    for (dst, &src) in dst.iter_mut().zip(src) {
+        *dst = src.to_f32();
+    }
+}
+
+/// Converts f32 samples to `S`, optionally with dither. Slices should have the same length
+pub fn convert_from_f32<S: Sample>(src: &[f32], dst: &mut [S], mut dither: Option<&mut Dither>) {
+    if src.len() != dst.len() {
+        panic!("source has {} samples, but destination has {}", src.len(), dst.len());
+    }
+
+    for (dst, &src) in dst.iter_mut().zip(src) {
+        *dst = match dither.as_deref_mut() {
+            Some(dither) => S::from_f32_dithered(src, dither),
+            None => S::from_f32(src)
+        };
+    }
+}
+
+/// Source of triangular noise for [`Sample::from_f32_dithered`]
+pub struct Dither {
+    rng: StdRng
+}
+
+impl Dither {
+    pub fn new() -> Self {
+        Self { rng: StdRng::from_entropy() }
+    }
+
+    /// Dither with reproducible noise
+    pub fn with_seed(seed: u64) -> Self {
+        Self { rng: StdRng::seed_from_u64(seed) }
+    }
+
+    /// Triangular noise in (-lsb, lsb)
+    fn next(&mut self, lsb: f32) -> f32 {
+        (self.rng.gen::<f32>() - self.rng.gen::<f32>()) * lsb
+    }
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// integer with `bits` significant bits from f32
+fn quantize(value: f32, bits: u32) -> i32 {
+    let scale = (1u64 << (bits - 1)) as f64;
+
+    (value as f64 * scale).round().clamp(-scale, scale - 1.0) as i32
+}
+
+fn dequantize(value: i32, bits: u32) -> f32 {
+    (value as f64 / (1u64 << (bits - 1)) as f64) as f32
+}
+
+fn dither_lsb(bits: u32) -> f32 {
+    1.0 / (1u64 << (bits - 1)) as f32
+}
+
+
+
+impl Sample for u8 {
+    fn to_f32(self) -> f32 {
+        dequantize(self as i32 - 0x80, 8)
+    }
+
+    fn from_f32(value: f32) -> Self {
+        (quantize(value, 8) + 0x80) as u8
+    }
+
+    fn from_f32_dithered(value: f32, dither: &mut Dither) -> Self {
+        Self::from_f32(value + dither.next(dither_lsb(8)))
+    }
+}
+
+impl Sample for i16 {
+    fn to_f32(self) -> f32 {
+        dequantize(self as i32, 16)
+    }
+
+    fn from_f32(value: f32) -> Self {
+        quantize(value, 16) as i16
+    }
+
+    fn from_f32_dithered(value: f32, dither: &mut Dither) -> Self {
+        Self::from_f32(value + dither.next(dither_lsb(16)))
+    }
+}
+
+impl Sample for i32 {
+    fn to_f32(self) -> f32 {
+        dequantize(self, 32)
+    }
+
+    fn from_f32(value: f32) -> Self {
+        quantize(value, 32)
+    }
+
+    // f32 has only 24 bits of precision, so dither is applied at this level
+    fn from_f32_dithered(value: f32, dither: &mut Dither) -> Self {
+        Self::from_f32(value + dither.next(dither_lsb(24)))
+    }
+}
+
+impl Sample for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+}
+
+
+
+macro_rules! int_sample {
+    ($(#[$meta:meta])* $name:ident, $bytes:literal, $bits:literal, $to_bytes:ident, $from_bytes:ident) => {
+        $(#[$meta])*
+        #[repr(transparent)]
+        #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct $name(pub [u8; $bytes]);
+
+        impl $name {
+            pub fn new(value: i32) -> Self {
+                Self($to_bytes(value))
+            }
+
+            pub fn get(self) -> i32 {
+                $from_bytes(self.0)
+            }
+        }
+
+        impl Sample for $name {
+            fn to_f32(self) -> f32 {
+                dequantize(self.get(), $bits)
+            }
+
+            fn from_f32(value: f32) -> Self {
+                Self::new(quantize(value, $bits))
+            }
+
+            fn from_f32_dithered(value: f32, dither: &mut Dither) -> Self {
+                Self::from_f32(value + dither.next(dither_lsb($bits.min(24))))
+            }
+        }
+    };
+}
+
+fn i16_to_le(value: i32) -> [u8; 2] { (value as i16).to_le_bytes() }
+fn i16_to_be(value: i32) -> [u8; 2] { (value as i16).to_be_bytes() }
+fn i16_from_le(bytes: [u8; 2]) -> i32 { i16::from_le_bytes(bytes) as i32 }
+fn i16_from_be(bytes: [u8; 2]) -> i32 { i16::from_be_bytes(bytes) as i32 }
+
+fn i24_to_le(value: i32) -> [u8; 3] { let [a, b, c, _] = value.to_le_bytes(); [a, b, c] }
+fn i24_to_be(value: i32) -> [u8; 3] { let [_, a, b, c] = value.to_be_bytes(); [a, b, c] }
+// shifts sign extend 24 bit value
+fn i24_from_le([a, b, c]: [u8; 3]) -> i32 { i32::from_le_bytes([0, a, b, c]) >> 8 }
+fn i24_from_be([a, b, c]: [u8; 3]) -> i32 { i32::from_be_bytes([a, b, c, 0]) >> 8 }
+
+fn i32_to_le(value: i32) -> [u8; 4] { value.to_le_bytes() }
+fn i32_to_be(value: i32) -> [u8; 4] { value.to_be_bytes() }
+fn i32_from_le(bytes: [u8; 4]) -> i32 { i32::from_le_bytes(bytes) }
+fn i32_from_be(bytes: [u8; 4]) -> i32 { i32::from_be_bytes(bytes) }
+
+// 24 bits in the least significant bits of 32 bit word. Upper byte is ignored on read
+fn i24_32_from_le(bytes: [u8; 4]) -> i32 { (i32::from_le_bytes(bytes) << 8) >> 8 }
+fn i24_32_from_be(bytes: [u8; 4]) -> i32 { (i32::from_be_bytes(bytes) << 8) >> 8 }
+
+int_sample!(
+    /// Signed 16 bit, little endian
+    I16Le, 2, 16, i16_to_le, i16_from_le
+);
+int_sample!(
+    /// Signed 16 bit, big endian
+    I16Be, 2, 16, i16_to_be, i16_from_be
+);
+int_sample!(
+    /// Signed 24 bit packed into 3 bytes, little endian
+    I24Le, 3, 24, i24_to_le, i24_from_le
+);
+int_sample!(
+    /// Signed 24 bit packed into 3 bytes, big endian
+    I24Be, 3, 24, i24_to_be, i24_from_be
+);
+int_sample!(
+    /// Signed 24 bit in the lower bits of 32 bit word, little endian
+    I24In32Le, 4, 24, i32_to_le, i24_32_from_le
+);
+int_sample!(
+    /// Signed 24 bit in the lower bits of 32 bit word, big endian
+    I24In32Be, 4, 24, i32_to_be, i24_32_from_be
+);
+int_sample!(
+    /// Signed 32 bit, little endian
+    I32Le, 4, 32, i32_to_le, i32_from_le
+);
+int_sample!(
+    /// Signed 32 bit, big endian
+    I32Be, 4, 32, i32_to_be, i32_from_be
+);
+
+/// IEEE float, little endian
+#[repr(transparent)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F32Le(pub [u8; 4]);
+
+impl F32Le {
+    pub fn new(value: f32) -> Self {
+        Self(value.to_le_bytes())
+    }
+
+    pub fn get(self) -> f32 {
+        f32::from_le_bytes(self.0)
+    }
+}
+
+impl Sample for F32Le {
+    fn to_f32(self) -> f32 {
+        self.get()
+    }
+
+    fn from_f32(value: f32) -> Self {
+        Self::new(value)
+    }
+}
+
+/// IEEE float, big endian
+#[repr(transparent)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F32Be(pub [u8; 4]);
+
+impl F32Be {
+    pub fn new(value: f32) -> Self {
+        Self(value.to_be_bytes())
+    }
+
+    pub fn get(self) -> f32 {
+        f32::from_be_bytes(self.0)
+    }
+}
+
+impl Sample for F32Be {
+    fn to_f32(self) -> f32 {
+        self.get()
+    }
+
+    fn from_f32(value: f32) -> Self {
+        Self::new(value)
+    }
+}
+
+
+
+// G.711 segment ends
+const ALAW_SEGMENTS: [i32; 8] = [0x1F, 0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF];
+const ULAW_SEGMENTS: [i32; 8] = [0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF, 0x1FFF];
+const ULAW_BIAS: i32 = 0x84;
+const ULAW_CLIP: i32 = 8159;
+
+fn segment(value: i32, ends: &[i32; 8]) -> usize {
+    ends.iter().position(| &end | value <= end).unwrap_or(8)
+}
+
+/// G.711 A-law
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ALaw(pub u8);
+
+impl ALaw {
+    pub fn encode(linear: i16) -> Self {
+        // 13 bit
+        let mut value = linear as i32 >> 3;
+        let mask = if value >= 0 {
+            0xD5
+        } else {
+            value = -value - 1;
+            0x55
+        };
+
+        let seg = segment(value, &ALAW_SEGMENTS);
+
+        if seg >= 8 {
+            return Self(0x7F ^ mask);
+        }
+
+        let mantissa = if seg < 2 { (value >> 1) & 0xF } else { (value >> seg) & 0xF };
+
+        Self((((seg as i32) << 4) | mantissa) as u8 ^ mask)
+    }
+
+    pub fn decode(self) -> i16 {
+        let value = self.0 ^ 0x55;
+        let seg = (value & 0x70) >> 4;
+        let mut t = ((value & 0xF) as i32) << 4;
+
+        match seg {
+            0 => t += 8,
+            1 => t += 0x108,
+            _ => t = (t + 0x108) << (seg - 1)
+        }
+
+        if value & 0x80 != 0 { t as i16 } else { -t as i16 }
+    }
+}
+
+impl Default for ALaw {
+    fn default() -> Self {
+        Self(0xD5)
+    }
+}
+
+impl Sample for ALaw {
+    fn to_f32(self) -> f32 {
+        self.decode().to_f32()
+    }
+
+    fn from_f32(value: f32) -> Self {
+        Self::encode(i16::from_f32(value))
+    }
+
+    // dither is applied to 13 bit linear value, what is encoded
+    fn from_f32_dithered(value: f32, dither: &mut Dither) -> Self {
+        Self::from_f32(value + dither.next(dither_lsb(13)))
+    }
+}
+
+/// G.711 μ-law
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MuLaw(pub u8);
+
+impl MuLaw {
+    pub fn encode(linear: i16) -> Self {
+        // 14 bit
+        let mut value = linear as i32 >> 2;
+        let mask = if value < 0 {
+            value = -value;
+            0x7F
+        } else {
+            0xFF
+        };
+
+        value = value.min(ULAW_CLIP) + (ULAW_BIAS >> 2);
+
+        let seg = segment(value, &ULAW_SEGMENTS);
+
+        if seg >= 8 {
+            return Self(0x7F ^ mask);
+        }
+
+        Self((((seg as i32) << 4) | ((value >> (seg + 1)) & 0xF)) as u8 ^ mask)
+    }
+
+    pub fn decode(self) -> i16 {
+        let value = !self.0;
+        let t = ((((value & 0xF) as i32) << 3) + ULAW_BIAS) << ((value & 0x70) >> 4);
+
+        if value & 0x80 != 0 { (ULAW_BIAS - t) as i16 } else { (t - ULAW_BIAS) as i16 }
+    }
+}
+
+impl Default for MuLaw {
+    fn default() -> Self {
+        Self(0xFF)
+    }
+}
+
+impl Sample for MuLaw {
+    fn to_f32(self) -> f32 {
+        self.decode().to_f32()
+    }
+
+    fn from_f32(value: f32) -> Self {
+        Self::encode(i16::from_f32(value))
+    }
+
+    // dither is applied to 14 bit linear value, what is encoded
+    fn from_f32_dithered(value: f32, dither: &mut Dither) -> Self {
+        Self::from_f32(value + dither.next(dither_lsb(14)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: [f32; 7] = [0.0, 0.5, -0.5, 0.25, -1.0, 0.125, -0.0078125];
+
+    fn roundtrip<S: Sample>() {
+        for value in VALUES {
+            assert_eq!(S::from_f32(value).to_f32(), value, "{}", core::any::type_name::<S>());
+        }
+    }
+
+    #[test]
+    fn lossless_roundtrip() {
+        roundtrip::<u8>();
+        roundtrip::<i16>();
+        roundtrip::<i32>();
+        roundtrip::<f32>();
+        roundtrip::<I16Le>();
+        roundtrip::<I16Be>();
+        roundtrip::<I24Le>();
+        roundtrip::<I24Be>();
+        roundtrip::<I24In32Le>();
+        roundtrip::<I24In32Be>();
+        roundtrip::<I32Le>();
+        roundtrip::<I32Be>();
+        roundtrip::<F32Le>();
+        roundtrip::<F32Be>();
+    }
+
+    #[test]
+    fn byte_order() {
+        assert_eq!(I16Le::new(0x1234).0, [0x34, 0x12]);
+        assert_eq!(I16Be::new(0x1234).0, [0x12, 0x34]);
+        assert_eq!(I24Le::new(-2).0, [0xFE, 0xFF, 0xFF]);
+        assert_eq!(I24Be::new(0x123456).0, [0x12, 0x34, 0x56]);
+        assert_eq!(I24Le([0x00, 0x00, 0x80]).get(), -0x800000);
+        assert_eq!(I24In32Le([0x56, 0x34, 0x12, 0x00]).get(), 0x123456);
+        // upper byte is padding
+        assert_eq!(I24In32Be([0x7F, 0xFF, 0xFF, 0xFF]).get(), -1);
+        assert_eq!(F32Be::new(1.0).0, [0x3F, 0x80, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn clamping() {
+        assert_eq!(i16::from_f32(2.0), i16::MAX);
+        assert_eq!(u8::from_f32(-2.0), 0);
+        assert_eq!(I24Le::from_f32(1.0).get(), 0x7FFFFF);
+        assert_eq!(i32::from_f32(-1.5), i32::MIN);
+    }
+
+    #[test]
+    fn companding() {
+        assert_eq!(ALaw::from_f32(0.0), ALaw::default());
+        assert_eq!(MuLaw::from_f32(0.0), MuLaw::default());
+
+        // every code decodes to value, what encodes back to the same code
+        for code in 0..=255 {
+            assert_eq!(ALaw::encode(ALaw(code).decode()), ALaw(code));
+
+            // 0x7F is negative zero, it encodes as positive one
+            if code != 0x7F {
+                assert_eq!(MuLaw::encode(MuLaw(code).decode()), MuLaw(code));
+            }
+        }
+
+        assert!((ALaw::from_f32(0.5).to_f32() - 0.5).abs() < 0.02);
+        assert!((MuLaw::from_f32(-0.5).to_f32() + 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn dithering() {
+        let mut dither = Dither::with_seed(1);
+        let value = 0.3 / 128.0;
+
+        let mut sum = 0.0;
+
+        for _ in 0..10_000 {
+            let sample = u8::from_f32_dithered(value, &mut dither);
+
+            // never more than 1 LSB away
+            assert!((sample as i32 - 0x80).abs() <= 1);
+            sum += sample.to_f32();
+        }
+
+        // average keeps level, what is below 1 LSB
+        assert!((sum / 10_000.0 - value).abs() < 0.1 / 128.0);
+
+        let mut out = [I16Le::default(); 3];
+        convert_from_f32(&[0.5, -0.5, 0.0], &mut out, None);
+
+        let mut back = [0.0; 3];
+        convert_to_f32(&out, &mut back);
+
+        assert_eq!(back, [0.5, -0.5, 0.0]);
+    }
+}
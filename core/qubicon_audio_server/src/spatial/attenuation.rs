@@ -0,0 +1,158 @@
+use num_traits::AsPrimitive;
+use qubicon_measuring_units::si::base_units::Metre;
+
+use super::Vec3;
+
+/// How gain falls with distance. Formulas are the same as clamped distance models of OpenAL
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rolloff {
+    /// `ref / (ref + factor * (d - ref))`. Closest to how sound behaves in real world
+    Inverse,
+    /// `1 - factor * (d - ref) / (max - ref)`. Reaches silence at max distance
+    Linear,
+    /// `(d / ref) ^ -factor`
+    Exponential
+}
+
+/// Distance attenuation of an emitter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Attenuation {
+    pub rolloff: Rolloff,
+    /// Distance, where gain is 1. Closer emitters are not louder
+    pub reference_distance: Metre<f32>,
+    /// Distance, after what gain does not change anymore
+    pub max_distance: Metre<f32>,
+    /// How fast gain falls. 0 disables attenuation
+    pub rolloff_factor: f32
+}
+
+impl Default for Attenuation {
+    fn default() -> Self {
+        Self {
+            rolloff: Rolloff::Inverse,
+            reference_distance: Metre::from(1.0),
+            max_distance: Metre::from(1000.0),
+            rolloff_factor: 1.0
+        }
+    }
+}
+
+impl Attenuation {
+    pub fn gain(&self, distance: Metre<f32>) -> f32 {
+        // zero reference distance would make everything silent
+        let reference = self.reference_distance.as_().max(f32::EPSILON);
+        let max = self.max_distance.as_().max(reference);
+        let distance = distance.as_().clamp(reference, max);
+        let factor = self.rolloff_factor.max(0.0);
+
+        let gain = match self.rolloff {
+            Rolloff::Inverse => reference / (reference + factor * (distance - reference)),
+            Rolloff::Linear => match max > reference {
+                true => 1.0 - factor * (distance - reference) / (max - reference),
+                false => 1.0
+            },
+            Rolloff::Exponential => (distance / reference).powf(-factor)
+        };
+
+        gain.clamp(0.0, 1.0)
+    }
+}
+
+/// Directional emitter. Full gain inside of inner cone, `outer_gain` outside of outer one
+/// and interpolated between them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cone {
+    /// Full angle in radians
+    pub inner_angle: f32,
+    /// Full angle in radians
+    pub outer_angle: f32,
+    pub outer_gain: f32
+}
+
+impl Default for Cone {
+    fn default() -> Self {
+        Self {
+            inner_angle: core::f32::consts::TAU,
+            outer_angle: core::f32::consts::TAU,
+            outer_gain: 0.0
+        }
+    }
+}
+
+impl Cone {
+    /// Gain for listener in `to_listener` direction from emitter, what points to `direction`
+    pub(crate) fn gain(&self, direction: Vec3<f32>, to_listener: Vec3<f32>) -> f32 {
+        let (Some(direction), Some(to_listener)) = (direction.normalized(), to_listener.normalized()) else { return 1.0 };
+
+        let angle = direction.dot(to_listener).clamp(-1.0, 1.0).acos();
+        let inner = self.inner_angle.max(0.0) * 0.5;
+        let outer = (self.outer_angle * 0.5).max(inner);
+
+        if angle <= inner {
+            1.0
+        } else if angle >= outer {
+            self.outer_gain
+        } else {
+            let t = (angle - inner) / (outer - inner);
+
+            1.0 + (self.outer_gain - 1.0) * t
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use qubicon_measuring_units::si::base_units::Metre;
+    use super::{ Attenuation, Cone, Rolloff, Vec3 };
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn rolloff() {
+        let mut attenuation = Attenuation {
+            rolloff: Rolloff::Inverse,
+            reference_distance: Metre::from(2.0),
+            max_distance: Metre::from(10.0),
+            rolloff_factor: 1.0
+        };
+
+        assert_near(attenuation.gain(Metre::from(0.5)), 1.0);
+        assert_near(attenuation.gain(Metre::from(4.0)), 0.5);
+        // clamped at max distance
+        assert_near(attenuation.gain(Metre::from(100.0)), 0.2);
+
+        attenuation.rolloff = Rolloff::Linear;
+
+        assert_near(attenuation.gain(Metre::from(6.0)), 0.5);
+        assert_near(attenuation.gain(Metre::from(10.0)), 0.0);
+
+        attenuation.rolloff = Rolloff::Exponential;
+        attenuation.rolloff_factor = 2.0;
+
+        assert_near(attenuation.gain(Metre::from(4.0)), 0.25);
+
+        attenuation.rolloff_factor = 0.0;
+
+        assert_near(attenuation.gain(Metre::from(8.0)), 1.0);
+    }
+
+    #[test]
+    fn cone() {
+        let cone = Cone {
+            inner_angle: 90f32.to_radians(),
+            outer_angle: 180f32.to_radians(),
+            outer_gain: 0.2
+        };
+        let forward = Vec3::new(0.0, 0.0, -1.0);
+
+        assert_near(cone.gain(forward, Vec3::new(0.3, 0.0, -1.0)), 1.0);
+        assert_near(cone.gain(forward, Vec3::new(0.0, 0.0, 1.0)), 0.2);
+        // halfway between 45 and 90 degrees
+        let angle = 67.5f32.to_radians();
+
+        assert_near(cone.gain(forward, Vec3::new(angle.sin(), 0.0, -angle.cos())), 0.6);
+        assert_near(Cone::default().gain(forward, Vec3::new(0.0, 0.0, 1.0)), 1.0);
+    }
+}
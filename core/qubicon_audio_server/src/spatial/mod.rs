@@ -0,0 +1,413 @@
+//! 3D positional audio. [`Spatializer`] turns emitters in game world into channel gains
+//! and Doppler pitch of [`crate::mixer::Mixer`] voices.
+//!
+//! Coordinates are right handed, like in OpenAL. By default listener looks to -Z, +Y is up and +X is right.
+//! Panning uses only horizontal speakers of output channel map, height speakers are left silent
+
+use core::ops::{ Add, Sub, Mul };
+
+use arrayvec::ArrayVec;
+use libpulse_sys::PA_CHANNELS_MAX;
+use num_traits::AsPrimitive;
+use qubicon_measuring_units::si::{ base_units::{ Metre, Second }, derived_units::MetrePerSecond };
+
+use crate::ChannelMap;
+use crate::mixer::{ Mixer, VoiceId };
+use panning::Panner;
+
+pub use attenuation::{ Rolloff, Attenuation, Cone };
+
+mod attenuation;
+mod panning;
+
+/// Speed of sound in dry air at 20 °C
+pub const SPEED_OF_SOUND: f32 = 343.3;
+
+// sources can't outrun their own sound. Also keeps pitch finite
+const MAX_RELATIVE_SPEED: f32 = 0.9;
+
+/// Vector in world space. Positions are in [`Metre`], velocities in [`MetrePerSecond`]
+/// and directions are plain f32
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Vec3<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T
+}
+
+impl<T> Vec3<T> {
+    pub const fn new(x: T, y: T, z: T) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl<T: AsPrimitive<f32>> Vec3<T> {
+    fn raw(self) -> Vec3<f32> {
+        Vec3::new(self.x.as_(), self.y.as_(), self.z.as_())
+    }
+}
+
+impl Vec3<f32> {
+    fn dot(self, rhs: Self) -> f32 {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+
+    fn cross(self, rhs: Self) -> Self {
+        Self::new(
+            self.y * rhs.z - self.z * rhs.y,
+            self.z * rhs.x - self.x * rhs.z,
+            self.x * rhs.y - self.y * rhs.x
+        )
+    }
+
+    fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    // None for zero vector
+    fn normalized(self) -> Option<Self> {
+        let length = self.length();
+
+        (length > f32::EPSILON).then(|| self * (1.0 / length))
+    }
+}
+
+impl<T: Add<Output = T>> Add for Vec3<T> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl<T: Sub<Output = T>> Sub for Vec3<T> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl<T: Mul<f32, Output = T>> Mul<f32> for Vec3<T> {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Self::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+// displacement over time
+fn velocity(from: Vec3<Metre<f32>>, to: Vec3<Metre<f32>>, elapsed: Second<f32>) -> Vec3<MetrePerSecond<f32>> {
+    if elapsed.as_() <= 0.0 {
+        return Vec3::default();
+    }
+
+    let delta = to - from;
+
+    Vec3::new(delta.x / elapsed, delta.y / elapsed, delta.z / elapsed)
+}
+
+/// Ears of the player
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Listener {
+    pub position: Vec3<Metre<f32>>,
+    pub velocity: Vec3<MetrePerSecond<f32>>,
+    /// Where listener looks. Does not need to be normalized
+    pub forward: Vec3<f32>,
+    pub up: Vec3<f32>
+}
+
+impl Default for Listener {
+    fn default() -> Self {
+        Self {
+            position: Vec3::default(),
+            velocity: Vec3::default(),
+            forward: Vec3::new(0.0, 0.0, -1.0),
+            up: Vec3::new(0.0, 1.0, 0.0)
+        }
+    }
+}
+
+impl Listener {
+    /// Moves listener and sets velocity from distance, passed in `elapsed` time
+    pub fn move_to(&mut self, position: Vec3<Metre<f32>>, elapsed: Second<f32>) {
+        self.velocity = velocity(self.position, position, elapsed);
+        self.position = position;
+    }
+
+    pub fn look_at(&mut self, forward: Vec3<f32>, up: Vec3<f32>) {
+        self.forward = forward;
+        self.up = up;
+    }
+
+    // right, up and forward axes. Falls back to default orientation, if vectors are degenerate
+    fn basis(&self) -> (Vec3<f32>, Vec3<f32>, Vec3<f32>) {
+        let forward = self.forward.normalized().unwrap_or(Vec3::new(0.0, 0.0, -1.0));
+
+        match forward.cross(self.up).normalized() {
+            Some(right) => (right, right.cross(forward), forward),
+            None => (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0))
+        }
+    }
+}
+
+/// Sound source in the world
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Emitter {
+    pub position: Vec3<Metre<f32>>,
+    pub velocity: Vec3<MetrePerSecond<f32>>,
+    /// Where emitter points. Only used with cone
+    pub direction: Vec3<f32>,
+    /// None for emitters, what sound the same in all directions
+    pub cone: Option<Cone>,
+    pub attenuation: Attenuation,
+    /// Linear gain, applied on top of attenuation
+    pub gain: f32,
+    /// Multiplies Doppler shift of this emitter. 0 disables it
+    pub doppler_factor: f32
+}
+
+impl Default for Emitter {
+    fn default() -> Self {
+        Self {
+            position: Vec3::default(),
+            velocity: Vec3::default(),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            cone: None,
+            attenuation: Attenuation::default(),
+            gain: 1.0,
+            doppler_factor: 1.0
+        }
+    }
+}
+
+impl Emitter {
+    /// Moves emitter and sets velocity from distance, passed in `elapsed` time
+    pub fn move_to(&mut self, position: Vec3<Metre<f32>>, elapsed: Second<f32>) {
+        self.velocity = velocity(self.position, position, elapsed);
+        self.position = position;
+    }
+}
+
+/// What listener hears from an emitter
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spatialized {
+    /// Gain of every output channel, attenuation and cone included
+    pub gains: ArrayVec<f32, {PA_CHANNELS_MAX as usize}>,
+    /// Doppler playback speed. 1 is no shift
+    pub pitch: f32,
+    pub distance: Metre<f32>
+}
+
+/// Places emitters around listener for one output channel map
+pub struct Spatializer {
+    channel_map: ChannelMap,
+    panner: Panner,
+
+    listener: Listener,
+
+    speed_of_sound: MetrePerSecond<f32>,
+    doppler_factor: f32
+}
+
+impl Spatializer {
+    pub fn new(channel_map: ChannelMap) -> Self {
+        Self {
+            panner: Panner::new(&channel_map),
+            channel_map,
+
+            listener: Listener::default(),
+
+            speed_of_sound: MetrePerSecond::from(SPEED_OF_SOUND),
+            doppler_factor: 1.0
+        }
+    }
+
+    pub fn channel_map(&self) -> &ChannelMap {
+        &self.channel_map
+    }
+
+    pub fn listener(&self) -> &Listener {
+        &self.listener
+    }
+
+    pub fn listener_mut(&mut self) -> &mut Listener {
+        &mut self.listener
+    }
+
+    pub fn set_listener(&mut self, listener: Listener) {
+        self.listener = listener;
+    }
+
+    pub fn speed_of_sound(&self) -> MetrePerSecond<f32> {
+        self.speed_of_sound
+    }
+
+    /// Lower speed exaggerates Doppler shift. Useful if world is not in real scale
+    pub fn set_speed_of_sound(&mut self, speed: MetrePerSecond<f32>) {
+        self.speed_of_sound = MetrePerSecond::from(speed.as_().max(f32::EPSILON));
+    }
+
+    pub fn doppler_factor(&self) -> f32 {
+        self.doppler_factor
+    }
+
+    /// Multiplies Doppler shift of all emitters. 0 disables it
+    pub fn set_doppler_factor(&mut self, factor: f32) {
+        self.doppler_factor = factor.max(0.0);
+    }
+
+    pub fn spatialize(&self, emitter: &Emitter) -> Spatialized {
+        let to_emitter = emitter.position.raw() - self.listener.position.raw();
+        let distance = to_emitter.length();
+
+        let (right, _, forward) = self.listener.basis();
+        let (x, z) = (to_emitter.dot(right), to_emitter.dot(forward));
+
+        // emitter is inside of listener's head, or right above it
+        let azimuth = (x.hypot(z) > f32::EPSILON).then(|| x.atan2(z).to_degrees());
+
+        let mut gains: ArrayVec<f32, {PA_CHANNELS_MAX as usize}> = self.channel_map.iter().map(| _ | 0.0).collect();
+
+        self.panner.pan(azimuth, &mut gains);
+
+        let cone = emitter.cone.map(| c | c.gain(emitter.direction, to_emitter * -1.0)).unwrap_or(1.0);
+        let gain = emitter.gain.max(0.0) * emitter.attenuation.gain(Metre::from(distance)) * cone;
+
+        gains.iter_mut().for_each(| g | *g *= gain);
+
+        Spatialized {
+            gains,
+            pitch: self.doppler(emitter, to_emitter),
+            distance: Metre::from(distance)
+        }
+    }
+
+    /// Sets channel gains and pitch of mixer voice. Mixer should have the same channel map
+    pub fn apply(&self, mixer: &mut Mixer, id: VoiceId, emitter: &Emitter) {
+        let spatialized = self.spatialize(emitter);
+
+        mixer.set_channel_gains(id, &spatialized.gains);
+        mixer.set_pitch(id, spatialized.pitch);
+    }
+
+    fn doppler(&self, emitter: &Emitter, to_emitter: Vec3<f32>) -> f32 {
+        let factor = self.doppler_factor * emitter.doppler_factor.max(0.0);
+
+        let Some(to_listener) = (to_emitter * -1.0).normalized() else { return 1.0 };
+
+        if factor == 0.0 {
+            return 1.0;
+        }
+
+        let c = self.speed_of_sound.as_();
+        let limit = c * MAX_RELATIVE_SPEED;
+
+        // speeds towards listener
+        let source = (emitter.velocity.raw().dot(to_listener) * factor).clamp(-limit, limit);
+        let listener = (self.listener.velocity.raw().dot(to_listener) * factor).clamp(-limit, limit);
+
+        (c - listener) / (c - source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use qubicon_measuring_units::si::{ base_units::{ Metre, Second }, derived_units::MetrePerSecond };
+
+    use libpulse_sys::pa_channel_position_t::*;
+    use crate::ChannelMap;
+    use crate::mixer::{ Mixer, Generator };
+    use super::{ Spatializer, Emitter, Listener, Vec3, Attenuation, Rolloff };
+
+    fn at(x: f32, y: f32, z: f32) -> Vec3<Metre<f32>> {
+        Vec3::new(Metre::from(x), Metre::from(y), Metre::from(z))
+    }
+
+    #[test]
+    fn panning_follows_listener() {
+        let spatializer = Spatializer::new(ChannelMap::from_positions(&[FrontLeft, FrontRight]));
+        let emitter = Emitter { position: at(5.0, 0.0, 0.0), ..Default::default() };
+
+        let right = spatializer.spatialize(&emitter);
+
+        assert_eq!(right.distance, Metre::from(5.0));
+        assert!(right.gains[0] < 1e-6);
+        assert!((right.gains[1] - 0.2).abs() < 1e-6);
+
+        // turned to the right, so emitter is in front
+        let mut spatializer = spatializer;
+
+        spatializer.listener_mut().look_at(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+
+        let front = spatializer.spatialize(&emitter);
+
+        assert!((front.gains[0] - front.gains[1]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn surround_rear() {
+        let spatializer = Spatializer::new(ChannelMap::from_positions(&[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight]));
+        let emitter = Emitter {
+            position: at(-1.0, 0.0, 1.0),
+            attenuation: Attenuation { rolloff_factor: 0.0, ..Default::default() },
+            ..Default::default()
+        };
+
+        let gains = spatializer.spatialize(&emitter).gains;
+
+        assert!((gains[4] - 1.0).abs() < 1e-5);
+        assert!(gains.iter().enumerate().all(| (i, &g) | i == 4 || g < 1e-5));
+    }
+
+    #[test]
+    fn doppler() {
+        let mut spatializer = Spatializer::new(ChannelMap::from_positions(&[Mono]));
+        let mut emitter = Emitter { position: at(0.0, 0.0, -100.0), ..Default::default() };
+
+        // 34.33 m/s towards listener
+        emitter.move_to(at(0.0, 0.0, -96.567), Second::from(0.1));
+
+        let pitch = spatializer.spatialize(&emitter).pitch;
+
+        assert!((pitch - 1.0 / 0.9).abs() < 1e-3, "{pitch}");
+
+        spatializer.set_doppler_factor(0.0);
+        assert_eq!(spatializer.spatialize(&emitter).pitch, 1.0);
+
+        // listener runs away with the same speed
+        spatializer.set_doppler_factor(1.0);
+        spatializer.set_listener(
+            Listener {
+                velocity: Vec3::new(MetrePerSecond::from(0.0), MetrePerSecond::from(0.0), MetrePerSecond::from(34.33)),
+                ..Default::default()
+            }
+        );
+
+        assert!((spatializer.spatialize(&emitter).pitch - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn applies_to_mixer() {
+        let stereo = ChannelMap::from_positions(&[FrontLeft, FrontRight]);
+        let spatializer = Spatializer::new(stereo.clone());
+        let mut mixer = Mixer::new(100, stereo);
+
+        let id = mixer.play(Generator::new(ChannelMap::from_positions(&[Mono]), | out | { out.fill(1.0); out.len() }), Default::default());
+        let emitter = Emitter {
+            position: at(-2.0, 0.0, 0.0),
+            attenuation: Attenuation { rolloff: Rolloff::Linear, reference_distance: Metre::from(1.0), max_distance: Metre::from(3.0), rolloff_factor: 1.0 },
+            ..Default::default()
+        };
+
+        spatializer.apply(&mut mixer, id, &emitter);
+
+        let mut out = [0.0; 8];
+        mixer.mix(&mut out);
+
+        for frame in out.chunks_exact(2) {
+            assert!((frame[0] - 0.5).abs() < 1e-6);
+            assert!(frame[1].abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use core::f32::consts::FRAC_PI_2;
+
+use crate::{ChannelMap, ChannelPosition};
+
+/// Direction of speaker in degrees, clockwise from front. So 90 is right and -90 is left.
+/// None for speakers out of horizontal ring, like LFE, AUX or height speakers
+pub(crate) fn speaker_azimuth(position: ChannelPosition) -> Option<f32> {
+    use libpulse_sys::pa_channel_position_t::*;
+
+    match position {
+        Mono | FrontCenter => Some(0.0),
+        FrontLeft => Some(-30.0),
+        FrontRight => Some(30.0),
+        FrontLeftOfCenter => Some(-15.0),
+        FrontRightOfCenter => Some(15.0),
+        SideLeft => Some(-90.0),
+        SideRight => Some(90.0),
+        RearLeft => Some(-135.0),
+        RearRight => Some(135.0),
+        RearCenter => Some(180.0),
+        _ => None
+    }
+}
+
+/// Pairwise constant power panning over speakers of the horizontal ring
+pub(crate) struct Panner {
+    // output index and azimuth, sorted by azimuth
+    speakers: Vec<(usize, f32)>,
+    // speakers are all around the listener. Otherwise sources behind are mirrored to front
+    surround: bool
+}
+
+impl Panner {
+    pub(crate) fn new(output: &ChannelMap) -> Self {
+        let mut speakers: Vec<(usize, f32)> = output.iter()
+            .enumerate()
+            .filter_map(| (i, &p) | speaker_azimuth(p).map(| a | (i, a)))
+            .collect();
+
+        speakers.sort_by(| a, b | a.1.total_cmp(&b.1));
+
+        // no gap between neighbour speakers is wider than half of circle
+        let surround = speakers.len() > 2 && (0..speakers.len()).all(| i | {
+            let next = (i + 1) % speakers.len();
+
+            (speakers[next].1 - speakers[i].1).rem_euclid(360.0) <= 180.0
+        });
+
+        Self { speakers, surround }
+    }
+
+    /// Adds gains of source at `azimuth` degrees to `gains`.
+    /// Source without direction is spread over all speakers
+    pub(crate) fn pan(&self, azimuth: Option<f32>, gains: &mut [f32]) {
+        let (Some(&first), Some(&last)) = (self.speakers.first(), self.speakers.last()) else {
+            // there is no speaker with direction, so every channel gets the same
+            let gain = 1.0 / (gains.len() as f32).sqrt();
+
+            gains.iter_mut().for_each(| g | *g += gain);
+            return;
+        };
+
+        let Some(azimuth) = azimuth else {
+            let gain = 1.0 / (self.speakers.len() as f32).sqrt();
+
+            for &(i, _) in &self.speakers {
+                gains[i] += gain;
+            }
+            return;
+        };
+
+        let azimuth = (azimuth + 180.0).rem_euclid(360.0) - 180.0;
+
+        if self.surround {
+            for (k, &(a, az_a)) in self.speakers.iter().enumerate() {
+                let (b, az_b) = self.speakers[(k + 1) % self.speakers.len()];
+                let span = (az_b - az_a).rem_euclid(360.0);
+                let offset = (azimuth - az_a).rem_euclid(360.0);
+
+                if span > 0.0 && offset <= span {
+                    Self::pair(gains, a, b, offset / span);
+                    return;
+                }
+            }
+        }
+
+        // mirror rear half to front, as there are no speakers to place it
+        let azimuth = match azimuth {
+            a if a > 90.0 => 180.0 - a,
+            a if a < -90.0 => -180.0 - a,
+            a => a
+        };
+
+        if azimuth <= first.1 {
+            gains[first.0] += 1.0;
+            return;
+        }
+        if azimuth >= last.1 {
+            gains[last.0] += 1.0;
+            return;
+        }
+
+        for pair in self.speakers.windows(2) {
+            let ((a, az_a), (b, az_b)) = (pair[0], pair[1]);
+
+            if azimuth >= az_a && azimuth <= az_b && az_b > az_a {
+                Self::pair(gains, a, b, (azimuth - az_a) / (az_b - az_a));
+                return;
+            }
+        }
+    }
+
+    // `t` is position between speakers from 0 to 1
+    fn pair(gains: &mut [f32], a: usize, b: usize, t: f32) {
+        let t = t * FRAC_PI_2;
+
+        gains[a] += t.cos();
+        gains[b] += t.sin();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libpulse_sys::pa_channel_position_t::*;
+    use crate::ChannelMap;
+    use super::Panner;
+
+    fn pan(output: &ChannelMap, azimuth: Option<f32>) -> Vec<f32> {
+        let mut gains = vec![0.0; output.len()];
+
+        Panner::new(output).pan(azimuth, &mut gains);
+
+        gains
+    }
+
+    fn assert_gains(gains: &[f32], expected: &[f32]) {
+        for (g, e) in gains.iter().zip(expected) {
+            assert!((g - e).abs() < 1e-5, "{gains:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn stereo() {
+        let stereo = ChannelMap::from_positions(&[FrontLeft, FrontRight]);
+        let half = core::f32::consts::FRAC_1_SQRT_2;
+
+        assert_gains(&pan(&stereo, Some(0.0)), &[half, half]);
+        assert_gains(&pan(&stereo, Some(90.0)), &[0.0, 1.0]);
+        assert_gains(&pan(&stereo, Some(-150.0)), &[1.0, 0.0]);
+        assert_gains(&pan(&stereo, Some(180.0)), &[half, half]);
+        assert_gains(&pan(&stereo, None), &[half, half]);
+    }
+
+    #[test]
+    fn surround() {
+        // 5.1
+        let output = ChannelMap::from_positions(&[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight]);
+
+        assert_gains(&pan(&output, Some(0.0)), &[0.0, 0.0, 1.0, 0.0, 0.0, 0.0]);
+        assert_gains(&pan(&output, Some(135.0)), &[0.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+        assert_gains(&pan(&output, Some(-135.0)), &[0.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+
+        // straight behind is between rear speakers
+        let behind = pan(&output, Some(180.0));
+
+        assert!((behind[4] - behind[5]).abs() < 1e-5);
+        assert_eq!(behind[..4], [0.0; 4]);
+
+        // 7.1, right side speaker
+        let output = ChannelMap::from_positions(&[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight, SideLeft, SideRight]);
+
+        assert_gains(&pan(&output, Some(90.0)), &[0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+    }
+}
@@ -21,6 +21,6 @@ fn main() {
     loop {
         server.update(| ev | println!("{ev:?}"));
 
-        println!("{} - {}", server.is_action_pressed("left"), server.get_action_force("left"));
+        println!("{:?} - {:?}", server.is_action_pressed("left"), server.get_action_force("left"));
     }
 }
\ No newline at end of file
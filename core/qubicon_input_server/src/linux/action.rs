@@ -0,0 +1,247 @@
+use std::{ops::{Add, Mul}, time::Duration};
+
+/// Value of 2D action. *x* goes right, *y* goes up. 1D actions have only *x*
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32
+}
+
+impl Vec2 {
+    pub const ZERO: Self = Self { x: 0.0, y: 0.0 };
+
+    pub fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    pub fn length(self) -> f32 {
+        self.x.hypot(self.y)
+    }
+
+    /// Same direction, length of *length*. Zero vector stays zero
+    pub fn with_length(self, length: f32) -> Self {
+        let current = self.length();
+
+        if current == 0.0 {
+            return Self::ZERO;
+        }
+
+        self * (length / current)
+    }
+}
+
+impl Add for Vec2 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl Mul<f32> for Vec2 {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self {
+        Self::new(self.x * rhs, self.y * rhs)
+    }
+}
+
+/// Values below *inner* are zero, values above *outer* are one, everything between is stretched to 0..1
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum DeadZone {
+    #[default]
+    None,
+    /// On length of the value. Good for sticks, keeps direction
+    Radial { inner: f32, outer: f32 },
+    /// On every axis separately. Makes it easy to move exactly along an axis
+    Axial { inner: f32, outer: f32 }
+}
+
+impl DeadZone {
+    pub fn apply(self, value: Vec2) -> Vec2 {
+        match self {
+            Self::None => value,
+            Self::Radial { inner, outer } => value.with_length(rescale(value.length(), inner, outer)),
+            Self::Axial { inner, outer } => Vec2::new(
+                rescale(value.x.abs(), inner, outer).copysign(value.x),
+                rescale(value.y.abs(), inner, outer).copysign(value.y)
+            )
+        }
+    }
+}
+
+fn rescale(value: f32, inner: f32, outer: f32) -> f32 {
+    match outer > inner {
+        true => ((value - inner) / (outer - inner)).clamp(0.0, 1.0),
+        false => (value >= inner) as u8 as f32
+    }
+}
+
+/// Maps strength of action after dead zone. Input and output are from 0 to 1
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ResponseCurve {
+    #[default]
+    Linear,
+    /// Exponent above 1 gives finer control near the center
+    Power(f32),
+    /// Slow near the center and near the edge
+    SmoothStep
+}
+
+impl ResponseCurve {
+    pub fn apply(self, value: f32) -> f32 {
+        let value = value.clamp(0.0, 1.0);
+
+        match self {
+            Self::Linear => value,
+            Self::Power(exponent) => value.powf(exponent.max(0.0)),
+            Self::SmoothStep => value * value * (3.0 - 2.0 * value)
+        }
+    }
+}
+
+/// When action fires, based on its bindings being active
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    /// While bindings are active
+    #[default]
+    Down,
+    /// On the update, when bindings became active
+    Press,
+    /// On the update, when bindings stopped being active. Value is the last active one
+    Release,
+    /// While bindings are active, after they were active for this long
+    Hold(Duration),
+    /// On the press, what comes within this time after the previous press
+    DoubleTap(Duration)
+}
+
+/// How bindings of one action become its value
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ActionSettings {
+    pub dead_zone: DeadZone,
+    pub curve: ResponseCurve,
+    pub trigger: Trigger
+}
+
+impl ActionSettings {
+    /// Dead zone and curve. Result is never longer than 1
+    pub(crate) fn shape(&self, value: Vec2) -> Vec2 {
+        let value = self.dead_zone.apply(value);
+        let length = value.length();
+
+        value.with_length(self.curve.apply(length))
+    }
+}
+
+/// Timing of trigger between updates
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct TriggerState {
+    active: bool,
+    value: Vec2,
+    pressed_at: Option<Duration>,
+    last_press: Option<Duration>
+}
+
+impl TriggerState {
+    /// Takes shaped value of bindings and gives value of action
+    pub(crate) fn update(&mut self, trigger: Trigger, value: Vec2, now: Duration) -> Vec2 {
+        let active = value != Vec2::ZERO;
+        let pressed = active && !self.active;
+        let released = !active && self.active;
+        let previous_value = self.value;
+        let previous_press = self.last_press;
+
+        if pressed {
+            self.pressed_at = Some(now);
+            self.last_press = Some(now);
+        }
+
+        if released {
+            self.pressed_at = None;
+        }
+
+        self.active = active;
+        self.value = value;
+
+        let fires = match trigger {
+            Trigger::Down => active,
+            Trigger::Press => pressed,
+            Trigger::Release => released,
+            Trigger::Hold(time) => self.pressed_at.is_some_and(| at | now.saturating_sub(at) >= time),
+            Trigger::DoubleTap(window) => {
+                let double = pressed && previous_press.is_some_and(| at | now.saturating_sub(at) <= window);
+
+                // third press starts a new double tap
+                if double {
+                    self.last_press = None;
+                }
+
+                double
+            }
+        };
+
+        match (fires, released) {
+            (false, _) => Vec2::ZERO,
+            (true, true) => previous_value,
+            (true, false) => value
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Vec2, DeadZone, ResponseCurve, Trigger, TriggerState, ActionSettings};
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        (a.x - b.x).abs() < 1e-5 && (a.y - b.y).abs() < 1e-5
+    }
+
+    #[test]
+    fn dead_zones_and_curves() {
+        let radial = DeadZone::Radial { inner: 0.2, outer: 0.8 };
+
+        assert_eq!(radial.apply(Vec2::new(0.1, 0.1)), Vec2::ZERO);
+        assert!(close(radial.apply(Vec2::new(0.0, -0.5)), Vec2::new(0.0, -0.5)));
+        assert!(close(radial.apply(Vec2::new(0.9, 0.0)), Vec2::new(1.0, 0.0)));
+
+        // small y is removed only by axial dead zone
+        let axial = DeadZone::Axial { inner: 0.2, outer: 1.0 };
+        assert!(close(axial.apply(Vec2::new(0.6, 0.1)), Vec2::new(0.5, 0.0)));
+
+        assert_eq!(ResponseCurve::Power(2.0).apply(0.5), 0.25);
+        assert_eq!(ResponseCurve::SmoothStep.apply(0.5), 0.5);
+
+        // diagonal of WASD is not faster
+        let settings = ActionSettings::default();
+        assert!(close(settings.shape(Vec2::new(1.0, 1.0)), Vec2::new(core::f32::consts::FRAC_1_SQRT_2, core::f32::consts::FRAC_1_SQRT_2)));
+    }
+
+    #[test]
+    fn triggers() {
+        let ms = Duration::from_millis;
+        let on = Vec2::new(1.0, 0.0);
+        let run = | trigger: Trigger, steps: &[(u64, Vec2)] | {
+            let mut state = TriggerState::default();
+
+            steps.iter()
+                .map(| &(t, v) | state.update(trigger, v, ms(t)) != Vec2::ZERO)
+                .collect::<Vec<_>>()
+        };
+
+        let steps = [(0, on), (10, on), (20, Vec2::ZERO), (30, on), (500, on), (510, Vec2::ZERO)];
+
+        assert_eq!(run(Trigger::Down, &steps), [true, true, false, true, true, false]);
+        assert_eq!(run(Trigger::Press, &steps), [true, false, false, true, false, false]);
+        assert_eq!(run(Trigger::Release, &steps), [false, false, true, false, false, true]);
+        assert_eq!(run(Trigger::Hold(ms(300)), &steps), [false, false, false, false, true, false]);
+        assert_eq!(run(Trigger::DoubleTap(ms(100)), &steps), [false, false, false, true, false, false]);
+
+        // released value is the last active one
+        let mut state = TriggerState::default();
+        state.update(Trigger::Release, Vec2::new(0.5, 0.0), ms(0));
+        assert_eq!(state.update(Trigger::Release, Vec2::ZERO, ms(1)), Vec2::new(0.5, 0.0));
+    }
+}
@@ -0,0 +1,320 @@
+use std::{collections::{HashMap, VecDeque}, time::Duration};
+
+use bitvec::{bitarr, BitArr};
+use keymaps::{Relative, Key, Abs, Ev};
+use nix::libc;
+
+use crate::input_device::AbsInfo;
+use super::{Device, InputBackend};
+
+/// Builds raw event, like the one what kernel gives. *time* is time since start of the script
+pub fn input_event(time: Duration, ev: Ev, code: u16, value: i32) -> libc::input_event {
+    libc::input_event {
+        time: timeval(time),
+        type_: ev.into(),
+        code,
+        value
+    }
+}
+
+fn timeval(time: Duration) -> libc::timeval {
+    libc::timeval {
+        tv_sec: time.as_secs() as libc::time_t,
+        tv_usec: time.subsec_micros() as libc::suseconds_t
+    }
+}
+
+fn event_time(event: &libc::input_event) -> Duration {
+    Duration::new(event.time.tv_sec as u64, event.time.tv_usec as u32 * 1000)
+}
+
+/// Device with given capabilities, what gives back scripted events.
+/// Event is given only when clock of [`FakeBackend`] reaches its time
+#[derive(Debug, Clone)]
+pub struct FakeDevice {
+    name: Box<str>,
+
+    supported_events: BitArr!(for Ev::MAX as usize),
+    supported_abs: Option<HashMap<Abs, AbsInfo>>,
+    supported_keys: Option<BitArr!(for Key::MAX as usize)>,
+    supported_rel: Option<BitArr!(for Relative::MAX as usize)>,
+
+    // event and clock time, when it is given
+    events: VecDeque<(Duration, libc::input_event)>,
+    clock: Duration
+}
+
+impl FakeDevice {
+    /// Device without any capabilities
+    pub fn new(name: impl AsRef<str>) -> Self {
+        Self {
+            name: name.as_ref().into(),
+
+            supported_events: bitarr![0; Ev::MAX as usize],
+            supported_abs: None,
+            supported_keys: None,
+            supported_rel: None,
+
+            events: VecDeque::new(),
+            clock: Duration::ZERO
+        }
+    }
+
+    /// Copy of capabilities and name of any device, without events
+    pub fn snapshot(device: &impl Device) -> Self {
+        Self {
+            name: device.name().into(),
+
+            supported_events: *device.supported_events(),
+            supported_abs: device.supported_abs().cloned(),
+            supported_keys: device.supported_keys().copied(),
+            supported_rel: device.supported_rel().copied(),
+
+            events: VecDeque::new(),
+            clock: Duration::ZERO
+        }
+    }
+
+    /// Keyboard with repeat and all the keys
+    pub fn keyboard(name: impl AsRef<str>) -> Self {
+        let mut device = Self::new(name);
+
+        device.set_event_supported(Ev::Key);
+        device.set_event_supported(Ev::Rep);
+        device.supported_keys = Some(bitarr![1; Key::MAX as usize]);
+
+        device
+    }
+
+    pub fn mouse(name: impl AsRef<str>) -> Self {
+        Self::new(name)
+            .with_keys(&[Key::BtnLeft, Key::BtnRight])
+            .with_rel(&[Relative::X, Relative::Y])
+    }
+
+    /// ABXY and two sticks from -32768 to 32767
+    pub fn gamepad(name: impl AsRef<str>) -> Self {
+        let stick = AbsInfo { min: -32768, max: 32767, res: 0, fuzz: 16, flat: 128 };
+
+        Self::new(name)
+            .with_keys(&[Key::BtnA, Key::BtnB, Key::BtnX, Key::BtnY])
+            .with_abs(Abs::LX, stick)
+            .with_abs(Abs::LY, stick)
+            .with_abs(Abs::RX, stick)
+            .with_abs(Abs::RY, stick)
+    }
+
+    pub fn with_events(mut self, events: &[Ev]) -> Self {
+        for &ev in events {
+            self.set_event_supported(ev);
+        }
+
+        self
+    }
+
+    pub fn with_keys(mut self, keys: &[Key]) -> Self {
+        self.set_event_supported(Ev::Key);
+
+        let supported = self.supported_keys.get_or_insert_with(|| bitarr![0; Key::MAX as usize]);
+
+        for &key in keys {
+            supported.set(Into::<u16>::into(key) as usize, true);
+        }
+
+        self
+    }
+
+    pub fn with_abs(mut self, abs: Abs, info: AbsInfo) -> Self {
+        self.set_event_supported(Ev::Abs);
+        self.supported_abs.get_or_insert_with(HashMap::new).insert(abs, info);
+
+        self
+    }
+
+    pub fn with_rel(mut self, rel: &[Relative]) -> Self {
+        self.set_event_supported(Ev::Rel);
+
+        let supported = self.supported_rel.get_or_insert_with(|| bitarr![0; Relative::MAX as usize]);
+
+        for &rel in rel {
+            supported.set(Into::<u16>::into(rel) as usize, true);
+        }
+
+        self
+    }
+
+    /// Adds raw events in the end of the script. Events should go in order of time
+    pub fn script(&mut self, events: impl IntoIterator<Item = libc::input_event>) {
+        self.events.extend(events.into_iter().map(| e | (event_time(&e), e)));
+    }
+
+    /// Adds raw event, what is given at clock time *at*. Time of the event itself is kept as is
+    pub(crate) fn script_at(&mut self, at: Duration, event: libc::input_event) {
+        self.events.push_back((at, event));
+    }
+
+    /// Key event and sync report at current time
+    pub fn push_key(&mut self, key: Key, pressed: bool) {
+        self.push_report(Ev::Key, key.into(), pressed as i32);
+    }
+
+    /// Raw abs value, it is normalized by server with range given in [`Self::with_abs`]
+    pub fn push_abs(&mut self, abs: Abs, value: i32) {
+        self.push_report(Ev::Abs, abs.into(), value);
+    }
+
+    pub fn push_rel(&mut self, rel: Relative, delta: i32) {
+        self.push_report(Ev::Rel, rel.into(), delta);
+    }
+
+    /// Events what are not given yet
+    pub fn pending_events(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn clock(&self) -> Duration {
+        self.clock
+    }
+}
+
+impl FakeDevice {
+    fn set_event_supported(&mut self, ev: Ev) {
+        self.supported_events.set(Into::<u16>::into(ev) as usize, true);
+    }
+
+    fn push_report(&mut self, ev: Ev, code: u16, value: i32) {
+        self.script([
+            input_event(self.clock, ev, code, value),
+            input_event(self.clock, Ev::Syn, 0, 0)
+        ]);
+    }
+}
+
+impl Device for FakeDevice {
+    fn next_event(&mut self) -> nix::Result<libc::input_event> {
+        match self.events.front() {
+            Some(&(at, event)) if at <= self.clock => {
+                self.events.pop_front();
+
+                Ok(event)
+            },
+            _ => Err(nix::Error::EAGAIN)
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn supported_events(&self) -> &BitArr!(for Ev::MAX as usize) {
+        &self.supported_events
+    }
+
+    fn supported_abs(&self) -> Option<&HashMap<Abs, AbsInfo>> {
+        self.supported_abs.as_ref()
+    }
+
+    fn supported_keys(&self) -> Option<&BitArr!(for Key::MAX as usize)> {
+        self.supported_keys.as_ref()
+    }
+
+    fn supported_rel(&self) -> Option<&BitArr!(for Relative::MAX as usize)> {
+        self.supported_rel.as_ref()
+    }
+}
+
+enum HotPlug {
+    Connect(u16, Box<FakeDevice>),
+    Disconnect(u16)
+}
+
+/// Backend without hardware. Devices are connected and disconnected on the next
+/// [`InputBackend::update_device_list`], like devices what are found by inotify
+#[derive(Default)]
+pub struct FakeBackend {
+    devices: HashMap<u16, FakeDevice>,
+    hot_plug: Vec<HotPlug>,
+
+    next_id: u16,
+    clock: Duration
+}
+
+impl FakeBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns id, what device will have
+    pub fn connect(&mut self, device: FakeDevice) -> u16 {
+        let id = self.next_id;
+
+        self.connect_with_id(id, device);
+
+        id
+    }
+
+    /// Device with the same id is replaced
+    pub fn connect_with_id(&mut self, id: u16, mut device: FakeDevice) {
+        device.clock = self.clock;
+
+        self.next_id = self.next_id.max(id.saturating_add(1));
+        self.hot_plug.push(HotPlug::Connect(id, Box::new(device)));
+    }
+
+    pub fn disconnect(&mut self, id: u16) {
+        self.hot_plug.push(HotPlug::Disconnect(id));
+    }
+
+    /// Connected device, so its script can be continued
+    pub fn device_mut(&mut self, id: u16) -> Option<&mut FakeDevice> {
+        self.devices.get_mut(&id)
+    }
+
+    /// Moves clock of all devices forward. Scripted events up to new time become available
+    pub fn advance(&mut self, duration: Duration) {
+        self.clock += duration;
+
+        let devices = self.devices.values_mut()
+            .chain(self.hot_plug.iter_mut().filter_map(| h | match h {
+                HotPlug::Connect(_, device) => Some(device.as_mut()),
+                HotPlug::Disconnect(_) => None
+            }));
+
+        for device in devices {
+            device.clock = self.clock;
+        }
+    }
+
+    pub fn clock(&self) -> Duration {
+        self.clock
+    }
+}
+
+impl InputBackend for FakeBackend {
+    type Device = FakeDevice;
+
+    fn update_device_list(&mut self) {
+        for hot_plug in self.hot_plug.drain(..) {
+            match hot_plug {
+                HotPlug::Connect(id, device) => {
+                    let _ = self.devices.insert(id, *device);
+                },
+                HotPlug::Disconnect(id) => {
+                    let _ = self.devices.remove(&id);
+                }
+            }
+        }
+    }
+
+    fn clock(&self) -> Duration {
+        self.clock
+    }
+
+    fn devices(&self) -> &HashMap<u16, FakeDevice> {
+        &self.devices
+    }
+
+    fn devices_mut(&mut self) -> &mut HashMap<u16, FakeDevice> {
+        &mut self.devices
+    }
+}
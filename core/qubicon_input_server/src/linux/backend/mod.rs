@@ -0,0 +1,39 @@
+//! Sources of devices for [`crate::LinuxInputServer`].
+//! [`crate::DeviceManager`] reads evdev files, [`FakeBackend`] replays scripted events without any hardware
+
+use std::{collections::HashMap, time::Duration};
+
+use bitvec::BitArr;
+use keymaps::{Relative, Key, Abs, Ev};
+use nix::libc;
+
+use crate::input_device::AbsInfo;
+
+pub use fake::{FakeBackend, FakeDevice, input_event};
+
+mod fake;
+
+/// Input device with its capabilities
+pub trait Device {
+    /// Results in EAGAIN, when no more events left
+    fn next_event(&mut self) -> nix::Result<libc::input_event>;
+
+    fn name(&self) -> &str;
+    fn supported_events(&self) -> &BitArr!(for Ev::MAX as usize);
+    fn supported_abs(&self) -> Option<&HashMap<Abs, AbsInfo>>;
+    fn supported_keys(&self) -> Option<&BitArr!(for Key::MAX as usize)>;
+    fn supported_rel(&self) -> Option<&BitArr!(for Relative::MAX as usize)>;
+}
+
+pub trait InputBackend {
+    type Device: Device;
+
+    /// Adds connected devices and removes disconnected ones
+    fn update_device_list(&mut self);
+
+    /// Time since start of the backend. Action triggers are timed with it
+    fn clock(&self) -> Duration;
+
+    fn devices(&self) -> &HashMap<u16, Self::Device>;
+    fn devices_mut(&mut self) -> &mut HashMap<u16, Self::Device>;
+}
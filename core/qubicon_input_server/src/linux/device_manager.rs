@@ -7,6 +7,7 @@ use nix::{
 };
 use std::{
     collections::HashMap,
+    time::{Duration, Instant},
     os::unix::ffi::OsStrExt,
     ops::{
         Deref,
@@ -14,7 +15,7 @@ use std::{
     }, fmt::Write
 };
 
-use crate::input_device::InputDevice;
+use crate::{input_device::InputDevice, backend::InputBackend};
 
 const MAX_TRIES: u8 = 3;
 
@@ -28,7 +29,8 @@ pub struct DeviceManager {
     notify: inotify::Inotify,
     input_dir_desc: inotify::WatchDescriptor,
 
-    event_files_in_init_process: Vec<EventFileInInitProcess>
+    event_files_in_init_process: Vec<EventFileInInitProcess>,
+    started: Instant
 }
 
 
@@ -76,7 +78,8 @@ impl DeviceManager {
                 devices,
                 notify,
                 input_dir_desc,
-                event_files_in_init_process: Vec::new()
+                event_files_in_init_process: Vec::new(),
+                started: Instant::now()
             }
         )
     }
@@ -161,6 +164,26 @@ impl DeviceManager {
     }
 }
 
+impl InputBackend for DeviceManager {
+    type Device = InputDevice;
+
+    fn update_device_list(&mut self) {
+        DeviceManager::update_device_list(self)
+    }
+
+    fn clock(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    fn devices(&self) -> &HashMap<u16, InputDevice> {
+        &self.devices
+    }
+
+    fn devices_mut(&mut self) -> &mut HashMap<u16, InputDevice> {
+        &mut self.devices
+    }
+}
+
 impl Drop for DeviceManager {
     fn drop(&mut self) {
         // Idk if this required. Let it be there
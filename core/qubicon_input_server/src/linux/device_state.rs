@@ -1,9 +1,15 @@
 use bitvec::BitArr;
 use keymaps::{Abs, Key};
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct DeviceState {
     pub abs_state: Option<HashMap<Abs, f32>>,
-    pub key_state: Option<Box<BitArr!(for Key::MAX as usize)>>
+    pub key_state: Option<Box<BitArr!(for Key::MAX as usize)>>,
+
+    /// Keys what changed on the last update. Repeats of held key are not here
+    pub just_pressed: Vec<Key>,
+    pub just_released: Vec<Key>,
+    /// Backend clock of the update, when held key was pressed
+    pub pressed_at: HashMap<Key, Duration>
 }
\ No newline at end of file
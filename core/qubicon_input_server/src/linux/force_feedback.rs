@@ -0,0 +1,350 @@
+use std::{sync::Arc, time::Duration};
+
+use keymaps::Ev;
+use nix::{libc, unistd, Result};
+
+use crate::input_device::{InputDevice, DeviceFile, ioctl};
+
+// linux/input.h. Bindgen of keymaps takes only input-event-codes.h, so they are not there
+const FF_RUMBLE: u16 = 0x50;
+const FF_PERIODIC: u16 = 0x51;
+const FF_CONSTANT: u16 = 0x52;
+const FF_RAMP: u16 = 0x57;
+
+const FF_SQUARE: u16 = 0x58;
+const FF_TRIANGLE: u16 = 0x59;
+const FF_SINE: u16 = 0x5a;
+const FF_SAW_UP: u16 = 0x5b;
+const FF_SAW_DOWN: u16 = 0x5c;
+
+const FF_GAIN: u16 = 0x60;
+const FF_AUTOCENTER: u16 = 0x61;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sine,
+    SawUp,
+    SawDown
+}
+
+impl Waveform {
+    fn code(self) -> u16 {
+        match self {
+            Self::Square => FF_SQUARE,
+            Self::Triangle => FF_TRIANGLE,
+            Self::Sine => FF_SINE,
+            Self::SawUp => FF_SAW_UP,
+            Self::SawDown => FF_SAW_DOWN
+        }
+    }
+}
+
+/// Fades effect in and out. Levels are from 0 to 1
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Envelope {
+    pub attack_length: Duration,
+    pub attack_level: f32,
+    pub fade_length: Duration,
+    pub fade_level: f32
+}
+
+/// Magnitudes are from 0 to 1, levels are from -1 to 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FfEffectKind {
+    /// Two motors of a gamepad
+    Rumble { strong: f32, weak: f32 },
+    Periodic { waveform: Waveform, period: Duration, magnitude: f32, offset: f32, envelope: Envelope },
+    Constant { level: f32, envelope: Envelope },
+    /// Force changes from *start* to *end* over length of the effect
+    Ramp { start: f32, end: f32, envelope: Envelope }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FfEffect {
+    pub kind: FfEffectKind,
+    /// Zero plays effect until it is stopped. Longest is about 65 seconds
+    pub length: Duration,
+    pub delay: Duration,
+    /// In degrees. 0 pulls down, 90 left, 180 up, 270 right. Rumble has no direction
+    pub direction: f32
+}
+
+impl FfEffect {
+    pub fn rumble(strong: f32, weak: f32, length: Duration) -> Self {
+        Self {
+            kind: FfEffectKind::Rumble { strong, weak },
+            length,
+            delay: Duration::ZERO,
+            direction: 0.0
+        }
+    }
+
+    fn code(&self) -> u16 {
+        match self.kind {
+            FfEffectKind::Rumble { .. } => FF_RUMBLE,
+            FfEffectKind::Periodic { .. } => FF_PERIODIC,
+            FfEffectKind::Constant { .. } => FF_CONSTANT,
+            FfEffectKind::Ramp { .. } => FF_RAMP
+        }
+    }
+
+    /// Effect for upload. *id* is -1 for a new one
+    pub(crate) fn to_raw(self, id: i16) -> libc::ff_effect {
+        let mut raw = libc::ff_effect {
+            type_: self.code(),
+            id,
+            direction: (self.direction.rem_euclid(360.0) / 360.0 * 65536.0) as u32 as u16,
+            trigger: libc::ff_trigger { button: 0, interval: 0 },
+            replay: libc::ff_replay { length: junk::millis(self.length), delay: junk::millis(self.delay) },
+            u: Default::default()
+        };
+
+        // u is a union of all effects in kernel. It's big and aligned enough for any of them
+        let u = raw.u.as_mut_ptr();
+
+        unsafe {
+            match self.kind {
+                FfEffectKind::Rumble { strong, weak } => u.cast::<libc::ff_rumble_effect>().write(
+                    libc::ff_rumble_effect {
+                        strong_magnitude: junk::unsigned(strong, u16::MAX),
+                        weak_magnitude: junk::unsigned(weak, u16::MAX)
+                    }
+                ),
+                FfEffectKind::Periodic { waveform, period, magnitude, offset, envelope } => u.cast::<libc::ff_periodic_effect>().write(
+                    libc::ff_periodic_effect {
+                        waveform: waveform.code(),
+                        period: junk::millis(period),
+                        magnitude: junk::signed(magnitude),
+                        offset: junk::signed(offset),
+                        phase: 0,
+                        envelope: junk::envelope(envelope),
+                        custom_len: 0,
+                        custom_data: core::ptr::null_mut()
+                    }
+                ),
+                FfEffectKind::Constant { level, envelope } => u.cast::<libc::ff_constant_effect>().write(
+                    libc::ff_constant_effect {
+                        level: junk::signed(level),
+                        envelope: junk::envelope(envelope)
+                    }
+                ),
+                FfEffectKind::Ramp { start, end, envelope } => u.cast::<libc::ff_ramp_effect>().write(
+                    libc::ff_ramp_effect {
+                        start_level: junk::signed(start),
+                        end_level: junk::signed(end),
+                        envelope: junk::envelope(envelope)
+                    }
+                )
+            }
+        }
+
+        raw
+    }
+}
+
+/// Force feedback of [`InputDevice`]. Made by [`InputDevice::force_feedback`]
+pub struct ForceFeedback<'a> {
+    device: &'a InputDevice
+}
+
+impl<'a> ForceFeedback<'a> {
+    pub(crate) fn new(device: &'a InputDevice) -> Self {
+        Self { device }
+    }
+
+    /// How many effects device can keep at once
+    pub fn effect_slots(&self) -> Result<usize> {
+        let mut slots = 0;
+
+        unsafe { ioctl::eviocgeffects(self.device.fd(), &mut slots)? };
+
+        Ok(slots.max(0) as usize)
+    }
+
+    /// Checks kind of effect and waveform of periodic one
+    pub fn supports(&self, effect: &FfEffect) -> bool {
+        let waveform = match effect.kind {
+            FfEffectKind::Periodic { waveform, .. } => self.supports_code(waveform.code()),
+            _ => true
+        };
+
+        self.supports_code(effect.code()) && waveform
+    }
+
+    pub fn supports_gain(&self) -> bool {
+        self.supports_code(FF_GAIN)
+    }
+
+    pub fn supports_autocenter(&self) -> bool {
+        self.supports_code(FF_AUTOCENTER)
+    }
+
+    /// Effect is kept by device until handle is dropped
+    pub fn upload(&self, effect: &FfEffect) -> Result<EffectHandle> {
+        let id = junk::upload(self.device.fd(), effect.to_raw(-1))?;
+
+        Ok(
+            EffectHandle {
+                file: self.device.file().clone(),
+                id
+            }
+        )
+    }
+
+    /// Overall strength of all effects, from 0 to 1
+    pub fn set_gain(&self, gain: f32) -> Result<()> {
+        junk::write_ff(self.device.fd(), FF_GAIN, junk::unsigned(gain, u16::MAX) as i32)
+    }
+
+    /// Strength of the spring, what pulls wheel or stick to the center. 0 disables it
+    pub fn set_autocenter(&self, strength: f32) -> Result<()> {
+        junk::write_ff(self.device.fd(), FF_AUTOCENTER, junk::unsigned(strength, u16::MAX) as i32)
+    }
+}
+
+impl ForceFeedback<'_> {
+    fn supports_code(&self, code: u16) -> bool {
+        self.device.supported_ff()
+            .is_some_and(| ff | ff.get(code as usize).is_some_and(| b | *b))
+    }
+}
+
+/// Effect uploaded to device. Slot is freed on drop
+pub struct EffectHandle {
+    file: Arc<DeviceFile>,
+    id: i16
+}
+
+impl EffectHandle {
+    pub fn id(&self) -> i16 {
+        self.id
+    }
+
+    /// Plays effect *count* times in a row
+    pub fn play(&self, count: u32) -> Result<()> {
+        junk::write_ff(self.file.0, self.id as u16, count.min(i32::MAX as u32) as i32)
+    }
+
+    pub fn stop(&self) -> Result<()> {
+        junk::write_ff(self.file.0, self.id as u16, 0)
+    }
+
+    /// Changes effect in the same slot. Playing effect is changed on the fly
+    pub fn update(&mut self, effect: &FfEffect) -> Result<()> {
+        junk::upload(self.file.0, effect.to_raw(self.id)).map(drop)
+    }
+}
+
+impl Drop for EffectHandle {
+    fn drop(&mut self) {
+        let _ = unsafe { ioctl::eviocrmff(self.file.0, self.id as libc::c_ulong) };
+    }
+}
+
+impl InputDevice {
+    /// None if device can't do force feedback
+    pub fn force_feedback(&self) -> Option<ForceFeedback<'_>> {
+        self.supported_events()[Into::<u16>::into(Ev::FF) as usize]
+            .then(|| ForceFeedback::new(self))
+    }
+}
+
+mod junk {
+    use super::*;
+
+    pub(super) fn millis(duration: Duration) -> u16 {
+        duration.as_millis().min(u16::MAX as u128) as u16
+    }
+
+    pub(super) fn unsigned(value: f32, max: u16) -> u16 {
+        (value.clamp(0.0, 1.0) * max as f32).round() as u16
+    }
+
+    pub(super) fn signed(value: f32) -> i16 {
+        (value.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+    }
+
+    pub(super) fn envelope(envelope: Envelope) -> libc::ff_envelope {
+        // kernel takes envelope levels up to 0x7fff
+        libc::ff_envelope {
+            attack_length: millis(envelope.attack_length),
+            attack_level: unsigned(envelope.attack_level, i16::MAX as u16),
+            fade_length: millis(envelope.fade_length),
+            fade_level: unsigned(envelope.fade_level, i16::MAX as u16)
+        }
+    }
+
+    // Returns id, what kernel gave to effect
+    pub(super) fn upload(fd: i32, mut effect: libc::ff_effect) -> Result<i16> {
+        let effect_ptr = core::ptr::addr_of_mut!(effect);
+
+        unsafe { ioctl::eviocsff(fd, effect_ptr)? };
+
+        Ok(effect.id)
+    }
+
+    pub(super) fn write_ff(fd: i32, code: u16, value: i32) -> Result<()> {
+        let event = libc::input_event {
+            time: libc::timeval { tv_sec: 0, tv_usec: 0 },
+            type_: Ev::FF.into(),
+            code,
+            value
+        };
+
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                core::ptr::addr_of!(event).cast::<u8>(),
+                core::mem::size_of::<libc::input_event>()
+            )
+        };
+
+        unistd::write(fd, bytes).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use nix::libc;
+
+    use super::{FfEffect, FfEffectKind, Waveform, Envelope, FF_RUMBLE, FF_PERIODIC, FF_RAMP, FF_SINE};
+
+    #[test]
+    fn rumble_to_raw() {
+        let raw = FfEffect::rumble(1.0, 0.5, Duration::from_millis(250)).to_raw(-1);
+        let rumble = unsafe { raw.u.as_ptr().cast::<libc::ff_rumble_effect>().read() };
+
+        assert_eq!((raw.type_, raw.id, raw.replay.length, raw.replay.delay), (FF_RUMBLE, -1, 250, 0));
+        assert_eq!((rumble.strong_magnitude, rumble.weak_magnitude), (0xffff, 0x8000));
+    }
+
+    #[test]
+    fn periodic_and_ramp_to_raw() {
+        let envelope = Envelope { attack_length: Duration::from_millis(10), attack_level: 1.0, ..Default::default() };
+        let effect = FfEffect {
+            kind: FfEffectKind::Periodic { waveform: Waveform::Sine, period: Duration::from_millis(50), magnitude: 2.0, offset: -0.5, envelope },
+            // longer than kernel can take
+            length: Duration::from_secs(100),
+            delay: Duration::from_millis(5),
+            direction: 90.0
+        };
+
+        let raw = effect.to_raw(3);
+        let periodic = unsafe { raw.u.as_ptr().cast::<libc::ff_periodic_effect>().read() };
+
+        assert_eq!((raw.type_, raw.id, raw.direction), (FF_PERIODIC, 3, 0x4000));
+        assert_eq!((raw.replay.length, raw.replay.delay), (u16::MAX, 5));
+        assert_eq!((periodic.waveform, periodic.period, periodic.magnitude, periodic.offset), (FF_SINE, 50, i16::MAX, -16384));
+        assert_eq!((periodic.envelope.attack_length, periodic.envelope.attack_level), (10, 0x7fff));
+
+        let ramp = FfEffect { kind: FfEffectKind::Ramp { start: -1.0, end: 1.0, envelope: Envelope::default() }, direction: -90.0, ..effect };
+        let raw = ramp.to_raw(-1);
+        let levels = unsafe { raw.u.as_ptr().cast::<libc::ff_ramp_effect>().read() };
+
+        assert_eq!((raw.type_, raw.direction), (FF_RAMP, 0xc000));
+        assert_eq!((levels.start_level, levels.end_level), (-i16::MAX, i16::MAX));
+    }
+}
@@ -1,10 +1,12 @@
-use std::{collections::HashMap, path::Path};
+use std::{collections::HashMap, path::Path, sync::Arc};
 use arrayvec::ArrayString;
 
 use bitvec::{bitarr, BitArr};
 use keymaps::{Relative, Key, Abs, Ev};
 use nix::{libc, unistd, fcntl, sys, Result};
 
+use crate::backend::Device;
+
 mod check_sets {
     use super::AbsInfo;
     use std::collections::HashMap;
@@ -92,7 +94,7 @@ mod check_sets {
 }
 
 #[allow(dead_code)]
-mod ioctl {
+pub(crate) mod ioctl {
     use nix::libc;
     use nix::{ioctl_read, ioctl_write_int, ioctl_read_buf, ioctl_write_ptr};
 
@@ -215,10 +217,19 @@ impl From<libc::input_absinfo> for AbsInfo {
 
 const EVENT_BUF_CAPACITY: u8 = 16;
 
+// Force feedback effects are owned by the open file, so it is shared with their handles
+pub(crate) struct DeviceFile(pub i32);
+
+impl Drop for DeviceFile {
+    fn drop(&mut self) {
+        let _ = unistd::close(self.0);
+    }
+}
+
 // TODO: Add device type from constants
 /// Input device and also an endless iterator over input events!
 pub struct InputDevice {
-    fd: i32,
+    file: Arc<DeviceFile>,
 
     // maybe use Arc ?
     name: Box<str>,
@@ -232,6 +243,7 @@ pub struct InputDevice {
     supported_abs: Option<HashMap<Abs, AbsInfo>>,
     supported_keys: Option<BitArr!(for Key::MAX as usize)>,
     supported_rel: Option<BitArr!(for Relative::MAX as usize)>,
+    supported_ff: Option<BitArr!(for libc::FF_MAX as usize)>,
 
     event_buf: Vec<libc::input_event>,
     current_event_idx: u8,
@@ -277,11 +289,17 @@ impl InputDevice {
 
 
 
-        let fd = fcntl::open(
+        let open = | flags: fcntl::OFlag | fcntl::open(
             path.as_ref(),
-            fcntl::OFlag::O_NONBLOCK,
+            flags | fcntl::OFlag::O_NONBLOCK,
             sys::stat::Mode::S_IWGRP | sys::stat::Mode::S_IRGRP
-        )?;
+        );
+
+        // Force feedback is played by writing to device. Without write access device is still readable
+        let fd = match open(fcntl::OFlag::O_RDWR) {
+            Err(nix::Error::EACCES) => open(fcntl::OFlag::O_RDONLY)?,
+            fd => fd?
+        };
 
         let _final = Final(fd);
 
@@ -323,9 +341,9 @@ impl InputDevice {
             let abs: HashMap<_, _> = buf
                 .into_iter()
                 .enumerate()
-                .map(| (abs, s) | (unsafe { Abs::from_raw(abs as u16) }, s))
                 .filter(| &(_, s) | s)
-                .map(| (abs, _) | abs)
+                // Kernel knows more axes than keymaps, e.g. ABS_MT_SLOT
+                .filter_map(| (abs, _) | Abs::try_from_raw(abs as u16))
                 .filter_map(| abs | {
                     let mut abs_into_raw: core::mem::MaybeUninit<libc::input_absinfo> = core::mem::MaybeUninit::uninit();
 
@@ -372,6 +390,22 @@ impl InputDevice {
         } else {
             None
         };
+        let supported_ff = if supported_events[Into::<u16>::into(Ev::FF) as usize] {
+            let mut buf = bitarr![0; libc::FF_MAX as usize];
+            
+            unsafe {
+                ioctl::eviocgbit(
+                    fd,
+                    Ev::FF.into(),
+                    libc::FF_MAX as usize,
+                    buf.as_mut_bitptr().pointer().cast()
+                )?;
+            }
+
+            Some(buf)
+        } else {
+            None
+        };
 
         // TODO: Delete this shit. This piece of code is here only for one reason: filter out
         // devices what not a keyboard, gamepad or mouse
@@ -393,7 +427,7 @@ impl InputDevice {
 
         Ok(
             Self {
-                fd,
+                file: Arc::new(DeviceFile(fd)),
                 name,
                 unique_name,
                 physical_path,
@@ -405,6 +439,7 @@ impl InputDevice {
                 supported_abs,
                 supported_keys,
                 supported_rel,
+                supported_ff,
 
                 event_buf,
                 current_event_idx: 0,
@@ -446,6 +481,11 @@ impl InputDevice {
         self.supported_rel.as_ref()
     }
 
+    /// Effects and properties, what [`Self::force_feedback`] can use
+    pub fn supported_ff(&self) -> Option<&BitArr!(for libc::FF_MAX as usize)> {
+        self.supported_ff.as_ref()
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -459,7 +499,41 @@ impl InputDevice {
     }
 }
 
+impl Device for InputDevice {
+    fn next_event(&mut self) -> Result<libc::input_event> {
+        InputDevice::next_event(self)
+    }
+
+    fn name(&self) -> &str {
+        InputDevice::name(self)
+    }
+
+    fn supported_events(&self) -> &BitArr!(for Ev::MAX as usize) {
+        InputDevice::supported_events(self)
+    }
+
+    fn supported_abs(&self) -> Option<&HashMap<Abs, AbsInfo>> {
+        InputDevice::supported_abs(self)
+    }
+
+    fn supported_keys(&self) -> Option<&BitArr!(for Key::MAX as usize)> {
+        InputDevice::supported_keys(self)
+    }
+
+    fn supported_rel(&self) -> Option<&BitArr!(for Relative::MAX as usize)> {
+        InputDevice::supported_rel(self)
+    }
+}
+
 impl InputDevice {
+    pub(crate) fn fd(&self) -> i32 {
+        self.file.0
+    }
+
+    pub(crate) fn file(&self) -> &Arc<DeviceFile> {
+        &self.file
+    }
+
     fn update_event_buf(&mut self) -> Result<()> {
         unsafe {
             let buf = core::slice::from_raw_parts_mut(
@@ -467,17 +541,11 @@ impl InputDevice {
                 EVENT_BUF_CAPACITY as usize * core::mem::size_of::<libc::input_event>()
             );
 
-            let len = unistd::read(self.fd, buf)?;
+            let len = unistd::read(self.fd(), buf)?;
 
             self.event_buf.set_len(len / core::mem::size_of::<libc::input_event>());
         }
 
         Ok(())
     }
-}
-
-impl Drop for InputDevice {
-    fn drop(&mut self) {
-        let _ = unistd::close(self.fd);
-    }
 }
\ No newline at end of file
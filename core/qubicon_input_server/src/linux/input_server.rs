@@ -2,9 +2,17 @@ use nix::sys;
 use bitvec::bitarr;
 use std::ops::Range;
 use std::collections::HashMap;
+use std::io::{self, Write, BufWriter};
+use std::time::Duration;
 use keymaps::{Relative, Abs, Key, Ev};
 
-use super::{device_manager::DeviceManager, device_state::DeviceState};
+use super::{
+    device_manager::DeviceManager,
+    device_state::DeviceState,
+    backend::{InputBackend, Device},
+    recording::Recorder,
+    action::{Vec2, ActionSettings, TriggerState}
+};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum InputEventData {
@@ -21,12 +29,109 @@ pub struct InputEvent {
     data: InputEventData
 }
 
+impl InputEvent {
+    pub fn device_id(&self) -> u16 {
+        self.device_id
+    }
+
+    /// Kernel time of the event. Replayed events have the recorded time
+    pub fn time(&self) -> sys::time::TimeVal {
+        self.time
+    }
+
+    pub fn data(&self) -> InputEventData {
+        self.data
+    }
+}
+
+/// Edge of action, what [`ActionEvent`] reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionPhase {
+    /// Action became active
+    Pressed,
+    /// Action stopped being active
+    Released
+}
+
+/// Change of action on update. Given after raw events of the same update
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActionEvent<'a> {
+    action: &'a str,
+    phase: ActionPhase,
+    value: Vec2
+}
+
+impl ActionEvent<'_> {
+    pub fn action(&self) -> &str {
+        self.action
+    }
+
+    pub fn phase(&self) -> ActionPhase {
+        self.phase
+    }
+
+    /// Value of action after update. Zero for released action
+    pub fn value(&self) -> Vec2 {
+        self.value
+    }
+}
+
+/// Binding of action. Every binding gives value for every device it is checked on.
+/// Buttons give 1 or 0, 1D bindings give only *x*
 #[derive(Debug, Clone, PartialEq)]
 pub enum ActionEventType {
     /// *key* is a key what being checked, and *pressed* tells if key should be pressed or released to activate action
     Key { key: Key, pressed: bool },
     /// *abs* - axis, *range* is an activation range. If axis value is in range, action will be active
-    Abs { abs: Abs, range: Range<f32>}
+    Abs { abs: Abs, range: Range<f32>},
+    /// All *keys* are held on the same device, like Ctrl+S. Order of pressing does not matter
+    Chord { keys: Vec<Key> },
+    /// Signed value of *abs*
+    Axis { abs: Abs },
+    /// -1 when *negative* is held, 1 when *positive* is held, 0 when both
+    KeyAxis { negative: Key, positive: Key },
+    /// Two axes, like a stick. Y of evdev goes down, so it is flipped
+    Stick { x: Abs, y: Abs },
+    /// Four keys, like WASD
+    Keys2d { up: Key, down: Key, left: Key, right: Key }
+}
+
+impl ActionEventType {
+    fn value(&self, state: &DeviceState) -> Vec2 {
+        let key = | key: Key | state.key_state.as_ref()
+            .is_some_and(| k | k[Into::<u16>::into(key) as usize]);
+        let abs = | abs: Abs | state.abs_state.as_ref()
+            .and_then(| a | a.get(&abs).copied())
+            .unwrap_or(0.0);
+        let key_axis = | negative: Key, positive: Key | key(positive) as u8 as f32 - key(negative) as u8 as f32;
+
+        match self {
+            Self::Key { key: k, pressed } => {
+                // Released key counts only if device has keys at all
+                let active = state.key_state.is_some() && key(*k) == *pressed;
+
+                Vec2::new(active as u8 as f32, 0.0)
+            },
+            Self::Abs { abs: a, range } => {
+                let active = state.abs_state.as_ref().is_some_and(| s | s.contains_key(a));
+                let value = abs(*a);
+
+                match active && range.contains(&value) {
+                    true => Vec2::new(value.abs(), 0.0),
+                    false => Vec2::ZERO
+                }
+            },
+            Self::Chord { keys } => {
+                let active = !keys.is_empty() && keys.iter().all(| &k | key(k));
+
+                Vec2::new(active as u8 as f32, 0.0)
+            },
+            Self::Axis { abs: a } => Vec2::new(abs(*a), 0.0),
+            Self::KeyAxis { negative, positive } => Vec2::new(key_axis(*negative, *positive), 0.0),
+            Self::Stick { x, y } => Vec2::new(abs(*x), -abs(*y)),
+            Self::Keys2d { up, down, left, right } => Vec2::new(key_axis(*left, *right), key_axis(*down, *up))
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -38,94 +143,254 @@ pub struct ActionInputEntry {
 
 #[derive(Debug, Default, Clone, PartialEq)]
 struct ActionState {
-    /// f32 for analog events
-    pub action_force: f32,
-    pub input_events: Vec<ActionInputEntry>
+    pub value: Vec2,
+    /// Value before the last update
+    pub previous: Vec2,
+    /// Backend clock of the update, when action became active
+    pub active_since: Option<Duration>,
+    pub input_events: Vec<ActionInputEntry>,
+
+    pub settings: ActionSettings,
+    pub trigger: TriggerState
+}
+
+impl ActionState {
+    fn just_pressed(&self) -> bool {
+        self.value != Vec2::ZERO && self.previous == Vec2::ZERO
+    }
+
+    fn just_released(&self) -> bool {
+        self.value == Vec2::ZERO && self.previous != Vec2::ZERO
+    }
 }
 
-pub struct LinuxInputServer {
-    device_manager: DeviceManager,
+pub struct LinuxInputServer<B: InputBackend = DeviceManager> {
+    backend: B,
     devices_state: HashMap<u16, DeviceState>,
-    input_actions: HashMap<Box<str>, ActionState>
+    input_actions: HashMap<Box<str>, ActionState>,
+    recorder: Option<Recorder<BufWriter<Box<dyn Write>>>>,
+    /// Clock of backend on the last update
+    now: Duration
 }
 
 impl LinuxInputServer {
+    /// Server on evdev devices from /dev/input
     pub fn new() -> nix::Result<Self> {
-        Ok(
-            Self {
-                device_manager: DeviceManager::new()?,
-                devices_state: HashMap::new(),
-                input_actions: HashMap::new()
-            }
-        )
+        Ok(Self::with_backend(DeviceManager::new()?))
+    }
+}
+
+impl<B: InputBackend> LinuxInputServer<B> {
+    pub fn with_backend(backend: B) -> Self {
+        Self {
+            backend,
+            devices_state: HashMap::new(),
+            input_actions: HashMap::new(),
+            recorder: None,
+            now: Duration::ZERO
+        }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
     }
  
     pub fn update(&mut self, event_handler: impl Fn(&InputEvent)) {
-        self.device_manager.update_device_list();
+        self.update_with_actions(event_handler, | _ | {});
+    }
+
+    /// Same as [`Self::update`], but also gives events of actions what were pressed or released
+    pub fn update_with_actions(&mut self, event_handler: impl Fn(&InputEvent), action_handler: impl Fn(&ActionEvent)) {
+        self.backend.update_device_list();
+        self.now = self.backend.clock();
+
+        // State of disconnected device should not keep actions pressed
+        let devices = self.backend.devices();
+        self.devices_state.retain(| id, _ | devices.contains_key(id));
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record_devices(devices);
+        }
 
         self.read_device_events(event_handler);
         self.update_actions();
+
+        for (action, state) in self.input_actions.iter() {
+            let phase = match (state.just_pressed(), state.just_released()) {
+                (true, _) => ActionPhase::Pressed,
+                (_, true) => ActionPhase::Released,
+                _ => continue
+            };
+
+            action_handler(&ActionEvent { action, phase, value: state.value });
+        }
     }
 
+    /// Records devices and raw events of every next update. Connected devices are recorded at once.
+    /// Recording what is already running is stopped. If it failed, its error is returned and new one is not started
+    pub fn start_recording(&mut self, output: impl Write + 'static) -> io::Result<()> {
+        if let Some(Err(e)) = self.stop_recording() {
+            return Err(e);
+        }
+
+        // every event is a separate line, so unbuffered file gets a syscall for each of them
+        let mut recorder = Recorder::new(BufWriter::new(Box::new(output) as Box<dyn Write>))?;
+        recorder.record_devices(self.backend.devices());
+
+        self.recorder = Some(recorder);
+
+        Ok(())
+    }
+
+    /// None if there is no recording. Otherwise result of writing it
+    pub fn stop_recording(&mut self) -> Option<io::Result<()>> {
+        self.recorder.take().map(| r | r.finish().map(drop))
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Action with default settings: no dead zone, linear curve, active while bindings are active
     pub fn add_input_action(&mut self, action: impl AsRef<str>, input_events: impl Into<Vec<ActionInputEntry>>) {
+        self.add_input_action_with_settings(action, input_events, ActionSettings::default());
+    }
+
+    /// When several bindings are active, the strongest one (with the longest value) is used.
+    /// Bindings are not summed, so keyboard and stick together don't move faster
+    pub fn add_input_action_with_settings(
+        &mut self,
+        action: impl AsRef<str>,
+        input_events: impl Into<Vec<ActionInputEntry>>,
+        settings: ActionSettings
+    ) {
         self.input_actions.insert(
             action.as_ref().into(),
             ActionState {
-                action_force: 0.0,
-                input_events: input_events.into()
+                input_events: input_events.into(),
+                settings,
+                ..Default::default()
             }
         );
     }
 
-    pub fn is_action_pressed(&self, action: impl AsRef<str>) -> bool {
-        self.input_actions[action.as_ref()].action_force != 0.0
+    /// None for unknown action
+    pub fn is_action_pressed(&self, action: impl AsRef<str>) -> Option<bool> {
+        self.input_actions.get(action.as_ref()).map(| a | a.value != Vec2::ZERO)
+    }
+
+    /// Action became active on the last update
+    pub fn is_action_just_pressed(&self, action: impl AsRef<str>) -> Option<bool> {
+        self.input_actions.get(action.as_ref()).map(ActionState::just_pressed)
+    }
+
+    /// Action stopped being active on the last update
+    pub fn is_action_just_released(&self, action: impl AsRef<str>) -> Option<bool> {
+        self.input_actions.get(action.as_ref()).map(ActionState::just_released)
+    }
+
+    /// Time from the update, when action became active. Zero if it is not active
+    pub fn action_held_duration(&self, action: impl AsRef<str>) -> Option<Duration> {
+        self.input_actions.get(action.as_ref())
+            .map(| a | a.active_since.map_or(Duration::ZERO, | at | self.now.saturating_sub(at)))
+    }
+
+    /// Strength of action from 0 to 1. For 2D actions it is length of the value
+    pub fn get_action_force(&self, action: impl AsRef<str>) -> Option<f32> {
+        self.input_actions.get(action.as_ref()).map(| a | a.value.length())
     }
 
-    pub fn get_action_force(&self, action: impl AsRef<str>) -> f32 {
-        self.input_actions[action.as_ref()].action_force
+    /// Value with direction. 1D actions have only *x*
+    pub fn get_action_value(&self, action: impl AsRef<str>) -> Option<Vec2> {
+        self.input_actions.get(action.as_ref()).map(| a | a.value)
+    }
+
+    /// Key was pressed on any device during the last update
+    pub fn is_key_just_pressed(&self, key: Key) -> bool {
+        self.devices_state.values().any(| s | s.just_pressed.contains(&key))
+    }
+
+    /// Key was released on any device during the last update
+    pub fn is_key_just_released(&self, key: Key) -> bool {
+        self.devices_state.values().any(| s | s.just_released.contains(&key))
+    }
+
+    /// The longest time key is held on any device. Zero if it is not held
+    pub fn key_held_duration(&self, key: Key) -> Duration {
+        self.devices_state.values()
+            .filter_map(| s | s.pressed_at.get(&key))
+            .map(| &at | self.now.saturating_sub(at))
+            .max()
+            .unwrap_or_default()
     }
 }
 
-impl LinuxInputServer {
+impl<B: InputBackend> LinuxInputServer<B> {
     fn read_device_events(&mut self, event_handler: impl Fn(&InputEvent)) {
-        for (&device_id, device) in self.device_manager.iter_mut() {  
+        let now = self.now;
+
+        for (&device_id, device) in self.backend.devices_mut().iter_mut() {  
             let device_state = self.devices_state
                 .entry(device_id)
                 .or_default();
 
+            device_state.just_pressed.clear();
+            device_state.just_released.clear();
+
             while let Ok(event) = device.next_event() {
-                let ev = unsafe { Ev::from_raw(event.type_) };
+                if let Some(recorder) = self.recorder.as_mut() {
+                    recorder.record_event(device_id, &event);
+                }
+
                 let time = sys::time::TimeVal::new(
                     event.time.tv_sec,
                     event.time.tv_usec
                 );
 
-                let data = match ev {
-                    Ev::Key => InputEventData::Key { key: unsafe { Key::from_raw(event.code) }, state: event.value > 0 },
-                    Ev::Rel => InputEventData::Rel { rel: unsafe { Relative::from_raw(event.code) }, delta: event.value },
-                    Ev::Abs => {
-                        let abs = unsafe { Abs::from_raw(event.code) };
-                        let abs_info = unsafe {
-                            device
-                                .supported_abs()
-                                .unwrap_unchecked()
-                                .get(&abs)
-                                .unwrap_unchecked()
-                        };
-
+                // Codes what keymaps don't know are skipped
+                let data = match Ev::try_from_raw(event.type_) {
+                    Some(Ev::Key) => Key::try_from_raw(event.code)
+                        .map(| key | InputEventData::Key { key, state: event.value > 0 }),
+                    Some(Ev::Rel) => Relative::try_from_raw(event.code)
+                        .map(| rel | InputEventData::Rel { rel, delta: event.value }),
+                    Some(Ev::Abs) => Abs::try_from_raw(event.code).and_then(| abs | {
+                        // Backend may give abs what device does not declare
+                        let abs_info = device.supported_abs()?.get(&abs)?;
                         let value = junk::normalize_abs_value(abs_info.min, abs_info.max, event.value);
 
-                        InputEventData::Abs { abs, value }
-                    },
+                        Some(InputEventData::Abs { abs, value })
+                    }),
+
+                    _ => None
+                };
 
-                    _ => continue
+                let data = match data {
+                    Some(d) => d,
+                    None => continue
                 };
 
                 match data {
                     InputEventData::Key { key, state } => {
-                        device_state.key_state
-                            .get_or_insert_with(|| Box::new(bitarr!(0; Key::MAX as usize)))
-                            .set(Into::<u16>::into(key) as usize, state)
+                        let keys = device_state.key_state
+                            .get_or_insert_with(|| Box::new(bitarr!(0; Key::MAX as usize)));
+                        let index = Into::<u16>::into(key) as usize;
+
+                        // Autorepeat gives held key again
+                        if keys[index] != state {
+                            if state {
+                                device_state.just_pressed.push(key);
+                                device_state.pressed_at.insert(key, now);
+                            } else {
+                                device_state.just_released.push(key);
+                                device_state.pressed_at.remove(&key);
+                            }
+                        }
+
+                        keys.set(index, state)
                     },
                     InputEventData::Abs { abs, value } => {
                         let _ = device_state.abs_state
@@ -148,9 +413,7 @@ impl LinuxInputServer {
 
     pub fn update_actions(&mut self) {
         for (_action_name, action_state) in self.input_actions.iter_mut() {
-            // Action force may be not zero from previous update
-            // so we need to reset it. Otherwise action will be always enabled
-            action_state.action_force = 0.0;
+            let mut strongest = Vec2::ZERO;
 
             for event in action_state.input_events.iter() {
                 let devices: &mut dyn Iterator<Item = u16>;
@@ -166,7 +429,7 @@ impl LinuxInputServer {
                         devices = &mut _single_device;
                     },
                     None => {
-                        _all_devices = self.device_manager.keys().copied();
+                        _all_devices = self.backend.devices().keys().copied();
 
                         devices = &mut _all_devices
                     }
@@ -174,32 +437,32 @@ impl LinuxInputServer {
 
 
                 for device_id in devices {
-                    let state = &self.devices_state[&device_id];
-
-                    match event.r#type.clone() {
-                        ActionEventType::Abs{ abs, range } => {
-                            if let Some(state) = state.abs_state.as_ref() {
-                                if !state.contains_key(&abs) {
-                                    continue;
-                                }
+                    // Device with this id may be not connected
+                    let state = match self.devices_state.get(&device_id) {
+                        Some(s) => s,
+                        None => continue
+                    };
 
-                                let value = state[&abs];
+                    let value = event.r#type.value(state);
 
-                                if range.contains(&value) {
-                                    action_state.action_force = value.abs();
-                                }
-                            }
-                        },
-                        ActionEventType::Key{ key, pressed } => {
-                            if let Some(key_state) = state.key_state.as_ref() {
-                                if pressed == key_state[Into::<u16>::into(key) as usize] {
-                                    action_state.action_force = 1.0;
-                                }
-                            }
-                        }
+                    // on tie the first binding stays
+                    if value.length() > strongest.length() {
+                        strongest = value;
                     }
                 }
             }
+
+            let value = action_state.settings.shape(strongest);
+            let value = action_state.trigger.update(action_state.settings.trigger, value, self.now);
+
+            action_state.previous = action_state.value;
+            action_state.value = value;
+
+            if action_state.just_pressed() {
+                action_state.active_since = Some(self.now);
+            } else if value == Vec2::ZERO {
+                action_state.active_since = None;
+            }
         }
     }
 }
@@ -217,4 +480,264 @@ mod junk {
             (val - min) as f32 / (max - min) as f32
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, time::Duration};
+
+    use keymaps::{Abs, Key, Ev};
+
+    use crate::backend::{InputBackend, FakeBackend, FakeDevice, input_event};
+    use crate::action::{Vec2, ActionSettings, DeadZone, Trigger};
+    use super::{LinuxInputServer, ActionInputEntry, ActionEventType, ActionPhase, InputEventData};
+
+    fn key_action(device_id: Option<u16>, key: Key) -> [ActionInputEntry; 1] {
+        [ActionInputEntry { device_id, r#type: ActionEventType::Key { key, pressed: true } }]
+    }
+
+    #[test]
+    fn key_actions() {
+        let mut server = LinuxInputServer::with_backend(FakeBackend::new());
+        let keyboard = server.backend_mut().connect(FakeDevice::keyboard("keyboard"));
+
+        server.add_input_action("jump", key_action(None, Key::Space));
+        server.update(| _ | {});
+
+        server.backend_mut().device_mut(keyboard).unwrap().push_key(Key::Space, true);
+
+        let events = RefCell::new(Vec::new());
+        server.update(| e | events.borrow_mut().push(e.data));
+
+        assert_eq!(events.into_inner(), [InputEventData::Key { key: Key::Space, state: true }]);
+        assert_eq!(server.is_action_pressed("jump"), Some(true));
+
+        server.backend_mut().device_mut(keyboard).unwrap().push_key(Key::Space, false);
+        server.update(| _ | {});
+
+        assert_eq!(server.is_action_pressed("jump"), Some(false));
+    }
+
+    #[test]
+    fn abs_actions() {
+        let mut server = LinuxInputServer::with_backend(FakeBackend::new());
+        let gamepad = server.backend_mut().connect(FakeDevice::gamepad("gamepad"));
+
+        server.add_input_action(
+            "right",
+            [ActionInputEntry { device_id: Some(gamepad), r#type: ActionEventType::Abs { abs: Abs::LX, range: 0.1..1.1 } }]
+        );
+        server.update(| _ | {});
+
+        server.backend_mut().device_mut(gamepad).unwrap().push_abs(Abs::LX, 32767 / 2);
+
+        let events = RefCell::new(Vec::new());
+        server.update(| e | events.borrow_mut().push(e.data));
+
+        match events.into_inner()[..] {
+            [InputEventData::Abs { abs: Abs::LX, value }] => assert!((value - 0.5).abs() < 1e-3),
+            ref e => panic!("{e:?}")
+        }
+
+        assert!((server.get_action_force("right").unwrap() - 0.5).abs() < 1e-3);
+
+        // left half of the stick is out of range
+        server.backend_mut().device_mut(gamepad).unwrap().push_abs(Abs::LX, -16384);
+        server.update(| _ | {});
+
+        assert_eq!(server.is_action_pressed("right"), Some(false));
+    }
+
+    #[test]
+    fn scripted_timing() {
+        let mut server = LinuxInputServer::with_backend(FakeBackend::new());
+        let mut keyboard = FakeDevice::keyboard("keyboard");
+
+        keyboard.script([
+            input_event(Duration::from_millis(10), Ev::Key, Key::A.into(), 1),
+            input_event(Duration::from_millis(10), Ev::Syn, 0, 0),
+            input_event(Duration::from_millis(30), Ev::Key, Key::A.into(), 0),
+            input_event(Duration::from_millis(30), Ev::Syn, 0, 0)
+        ]);
+
+        server.backend_mut().connect(keyboard);
+        server.add_input_action("a", key_action(None, Key::A));
+
+        server.update(| _ | {});
+        assert_eq!(server.is_action_pressed("a"), Some(false));
+
+        server.backend_mut().advance(Duration::from_millis(10));
+        server.update(| _ | {});
+        assert_eq!(server.is_action_pressed("a"), Some(true));
+
+        server.backend_mut().advance(Duration::from_millis(20));
+        server.update(| _ | {});
+        assert_eq!(server.is_action_pressed("a"), Some(false));
+    }
+
+    #[test]
+    fn hot_plug() {
+        let mut server = LinuxInputServer::with_backend(FakeBackend::new());
+
+        // device is not connected yet
+        server.add_input_action("fire", key_action(Some(3), Key::BtnLeft));
+        server.update(| _ | {});
+        assert_eq!(server.is_action_pressed("fire"), Some(false));
+
+        let mut mouse = FakeDevice::mouse("mouse");
+        mouse.push_key(Key::BtnLeft, true);
+
+        server.backend_mut().connect_with_id(3, mouse);
+        server.update(| _ | {});
+        assert_eq!(server.is_action_pressed("fire"), Some(true));
+
+        // button is still held, but device is gone
+        server.backend_mut().disconnect(3);
+        server.update(| _ | {});
+        assert_eq!(server.is_action_pressed("fire"), Some(false));
+        assert!(server.backend().devices().is_empty());
+    }
+
+    #[test]
+    fn chords_and_composites() {
+        let mut server = LinuxInputServer::with_backend(FakeBackend::new());
+        let keyboard = server.backend_mut().connect(FakeDevice::keyboard("keyboard"));
+
+        server.add_input_action("save", [ActionInputEntry {
+            device_id: None,
+            r#type: ActionEventType::Chord { keys: vec![Key::LeftCtrl, Key::S] }
+        }]);
+        server.add_input_action("move", [ActionInputEntry {
+            device_id: None,
+            r#type: ActionEventType::Keys2d { up: Key::W, down: Key::S, left: Key::A, right: Key::D }
+        }]);
+        server.update(| _ | {});
+
+        let device = server.backend_mut().device_mut(keyboard).unwrap();
+        device.push_key(Key::S, true);
+        server.update(| _ | {});
+
+        assert_eq!(server.is_action_pressed("save"), Some(false));
+        assert_eq!(server.get_action_value("move").unwrap(), Vec2::new(0.0, -1.0));
+
+        let device = server.backend_mut().device_mut(keyboard).unwrap();
+        device.push_key(Key::LeftCtrl, true);
+        device.push_key(Key::D, true);
+        server.update(| _ | {});
+
+        assert_eq!(server.is_action_pressed("save"), Some(true));
+
+        // diagonal has the same length as straight move
+        let value = server.get_action_value("move").unwrap();
+        assert!(value.x > 0.0 && value.y < 0.0);
+        assert!((server.get_action_force("move").unwrap() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn strongest_binding_wins() {
+        let mut server = LinuxInputServer::with_backend(FakeBackend::new());
+        let keyboard = server.backend_mut().connect(FakeDevice::keyboard("keyboard"));
+        let gamepad = server.backend_mut().connect(FakeDevice::gamepad("gamepad"));
+
+        server.add_input_action_with_settings(
+            "move",
+            [
+                ActionInputEntry { device_id: Some(gamepad), r#type: ActionEventType::Stick { x: Abs::LX, y: Abs::LY } },
+                ActionInputEntry { device_id: Some(keyboard), r#type: ActionEventType::KeyAxis { negative: Key::Left, positive: Key::Right } }
+            ],
+            ActionSettings { dead_zone: DeadZone::Radial { inner: 0.2, outer: 1.0 }, ..Default::default() }
+        );
+        server.update(| _ | {});
+
+        // inside of dead zone
+        server.backend_mut().device_mut(gamepad).unwrap().push_abs(Abs::LX, 32767 / 10);
+        server.update(| _ | {});
+        assert_eq!(server.is_action_pressed("move"), Some(false));
+
+        // stick up is positive y
+        server.backend_mut().device_mut(gamepad).unwrap().push_abs(Abs::LX, 0);
+        server.backend_mut().device_mut(gamepad).unwrap().push_abs(Abs::LY, -32768 * 6 / 10);
+        server.update(| _ | {});
+
+        let value = server.get_action_value("move").unwrap();
+        assert!(value.x == 0.0 && (value.y - 0.5).abs() < 1e-3);
+
+        // full key is stronger than half of the stick
+        server.backend_mut().device_mut(keyboard).unwrap().push_key(Key::Left, true);
+        server.update(| _ | {});
+        assert_eq!(server.get_action_value("move").unwrap(), Vec2::new(-1.0, 0.0));
+    }
+
+    #[test]
+    fn hold_trigger() {
+        let mut server = LinuxInputServer::with_backend(FakeBackend::new());
+        let keyboard = server.backend_mut().connect(FakeDevice::keyboard("keyboard"));
+
+        server.add_input_action_with_settings(
+            "charge",
+            key_action(None, Key::E),
+            ActionSettings { trigger: Trigger::Hold(Duration::from_millis(500)), ..Default::default() }
+        );
+        server.update(| _ | {});
+
+        server.backend_mut().device_mut(keyboard).unwrap().push_key(Key::E, true);
+        server.update(| _ | {});
+        assert_eq!(server.is_action_pressed("charge"), Some(false));
+
+        server.backend_mut().advance(Duration::from_millis(499));
+        server.update(| _ | {});
+        assert_eq!(server.is_action_pressed("charge"), Some(false));
+
+        server.backend_mut().advance(Duration::from_millis(1));
+        server.update(| _ | {});
+        assert_eq!(server.is_action_pressed("charge"), Some(true));
+    }
+
+    #[test]
+    fn edges_and_action_events() {
+        let mut server = LinuxInputServer::with_backend(FakeBackend::new());
+        let keyboard = server.backend_mut().connect(FakeDevice::keyboard("keyboard"));
+
+        server.add_input_action("jump", key_action(None, Key::Space));
+        server.update(| _ | {});
+
+        assert_eq!(server.is_action_pressed("fly"), None);
+        assert_eq!(server.is_action_just_pressed("fly"), None);
+        assert_eq!(server.action_held_duration("fly"), None);
+
+        let actions = RefCell::new(Vec::new());
+        let update = | server: &mut LinuxInputServer<FakeBackend> | {
+            server.update_with_actions(| _ | {}, | a | actions.borrow_mut().push((a.action().to_owned(), a.phase())));
+        };
+
+        server.backend_mut().device_mut(keyboard).unwrap().push_key(Key::Space, true);
+        update(&mut server);
+
+        assert!(server.is_key_just_pressed(Key::Space));
+        assert_eq!(server.is_action_just_pressed("jump"), Some(true));
+        assert_eq!(actions.borrow()[..], [("jump".to_owned(), ActionPhase::Pressed)]);
+
+        // autorepeat is not a new press
+        server.backend_mut().advance(Duration::from_millis(250));
+        server.backend_mut().device_mut(keyboard).unwrap().script([
+            input_event(Duration::from_millis(250), Ev::Key, Key::Space.into(), 2),
+            input_event(Duration::from_millis(250), Ev::Syn, 0, 0)
+        ]);
+        update(&mut server);
+
+        assert!(!server.is_key_just_pressed(Key::Space));
+        assert_eq!(server.is_action_just_pressed("jump"), Some(false));
+        assert_eq!(server.key_held_duration(Key::Space), Duration::from_millis(250));
+        assert_eq!(server.action_held_duration("jump"), Some(Duration::from_millis(250)));
+        assert_eq!(actions.borrow().len(), 1);
+
+        server.backend_mut().device_mut(keyboard).unwrap().push_key(Key::Space, false);
+        update(&mut server);
+
+        assert!(server.is_key_just_released(Key::Space));
+        assert_eq!(server.is_action_just_released("jump"), Some(true));
+        assert_eq!(server.key_held_duration(Key::Space), Duration::ZERO);
+        assert_eq!(server.action_held_duration("jump"), Some(Duration::ZERO));
+        assert_eq!(actions.borrow()[1..], [("jump".to_owned(), ActionPhase::Released)]);
+    }
+}
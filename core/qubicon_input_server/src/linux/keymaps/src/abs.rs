@@ -61,6 +61,20 @@ impl Abs {
     pub unsafe fn from_raw(value: u16) -> Self {
         core::mem::transmute(value)
     }
+
+    /// None, if *value* is not presentable via this enum
+    pub fn try_from_raw(value: u16) -> Option<Self> {
+        match value {
+            ABS_RESERVED | ABS_X | ABS_Y | ABS_Z | ABS_RX | ABS_RY | ABS_RZ | ABS_THROTTLE | ABS_RUDDER |
+            ABS_WHEEL | ABS_GAS | ABS_BRAKE | ABS_HAT0X | ABS_HAT0Y | ABS_HAT1X | ABS_HAT1Y | ABS_HAT2X |
+            ABS_HAT2Y | ABS_HAT3X | ABS_HAT3Y | ABS_PRESSURE | ABS_DISTANCE | ABS_TILT_X | ABS_TILT_Y |
+            ABS_VOLUME | ABS_PROFILE | ABS_MISC | ABS_MT_TOUCH_MAJOR | ABS_MT_TOUCH_MINOR |
+            ABS_MT_WIDTH_MAJOR | ABS_MT_WIDTH_MINOR | ABS_MT_ORIENTATION | ABS_MT_POSITION_X |
+            ABS_MT_POSITION_Y | ABS_MT_TOOL_TYPE | ABS_MT_BLOB_ID | ABS_MT_TRACKING_ID | ABS_MT_PRESSURE |
+            ABS_MT_DISTANCE | ABS_MT_TOOL_X | ABS_MT_TOOL_Y => Some(unsafe { Self::from_raw(value) }),
+            _ => None
+        }
+    }
 }
 
 impl Into<u16> for Abs {
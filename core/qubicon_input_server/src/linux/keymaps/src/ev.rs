@@ -25,6 +25,15 @@ impl Ev {
     pub unsafe fn from_raw(value: u16) -> Self {
         core::mem::transmute(value)
     }
+
+    /// None, if *value* is not presentable via this enum
+    pub fn try_from_raw(value: u16) -> Option<Self> {
+        match value {
+            EV_SYN | EV_KEY | EV_REL | EV_ABS | EV_MSC | EV_SW | EV_LED | EV_SND | EV_REP | EV_FF | EV_PWR |
+            EV_FF_STATUS => Some(unsafe { Self::from_raw(value) }),
+            _ => None
+        }
+    }
 }
 
 impl Into<u16> for Ev {
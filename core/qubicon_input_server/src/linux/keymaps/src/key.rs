@@ -341,6 +341,56 @@ impl Key {
     pub unsafe fn from_raw(value: u16) -> Self {
         core::mem::transmute(value)
     }
+
+    /// None, if *value* is not presentable via this enum
+    pub fn try_from_raw(value: u16) -> Option<Self> {
+        match value {
+            KEY_RESERVED | KEY_ESC | KEY_F1 | KEY_F2 | KEY_F3 | KEY_F4 | KEY_F5 | KEY_F6 | KEY_F7 | KEY_F8 |
+            KEY_F9 | KEY_F10 | KEY_F11 | KEY_F12 | KEY_INSERT | KEY_DELETE | KEY_HOME | KEY_END |
+            KEY_PAGEUP | KEY_PAGEDOWN | KEY_MINUS | KEY_EQUAL | KEY_LEFTBRACE | KEY_RIGHTBRACE |
+            KEY_SEMICOLON | KEY_APOSTROPHE | KEY_GRAVE | KEY_BACKSLASH | KEY_COMMA | KEY_DOT | KEY_SLASH |
+            KEY_KPASTERISK | KEY_SCROLLDOWN | KEY_SCROLLUP | KEY_KP0 | KEY_KP1 | KEY_KP2 | KEY_KP3 |
+            KEY_KP4 | KEY_KP5 | KEY_KP6 | KEY_KP7 | KEY_KP8 | KEY_KP9 | KEY_KPMINUS | KEY_KPPLUS |
+            KEY_KPDOT | KEY_KPENTER | KEY_KPSLASH | KEY_KPEQUAL | KEY_KPPLUSMINUS | KEY_KPCOMMA |
+            KEY_ZENKAKUHANKAKU | KEY_102ND | KEY_RO | KEY_KATAKANA | KEY_HIRAGANA | KEY_HENKAN |
+            KEY_KATAKANAHIRAGANA | KEY_MUHENKAN | KEY_KPJPCOMMA | KEY_HANJA | KEY_YEN | KEY_SYSRQ |
+            KEY_LINEFEED | KEY_MACRO | KEY_MACRO1 | KEY_MACRO2 | KEY_MACRO3 | KEY_MACRO4 | KEY_MACRO5 |
+            KEY_MACRO6 | KEY_MACRO7 | KEY_MACRO8 | KEY_MACRO9 | KEY_MACRO10 | KEY_MACRO11 | KEY_MACRO12 |
+            KEY_MACRO13 | KEY_MACRO14 | KEY_MACRO15 | KEY_MACRO16 | KEY_MACRO17 | KEY_MACRO18 | KEY_MACRO19 |
+            KEY_MACRO20 | KEY_MACRO21 | KEY_MACRO22 | KEY_MACRO23 | KEY_MACRO24 | KEY_MACRO25 | KEY_MACRO26 |
+            KEY_MACRO27 | KEY_MACRO28 | KEY_MACRO29 | KEY_MACRO30 | KEY_MACRO_PRESET_CYCLE |
+            KEY_MACRO_PRESET1 | KEY_MACRO_PRESET2 | KEY_MACRO_PRESET3 | KEY_MUTE | KEY_VOLUMEDOWN |
+            KEY_VOLUMEUP | KEY_POWER | KEY_POWER2 | KEY_PAUSE | KEY_SCALE | KEY_STOP | KEY_AGAIN |
+            KEY_PROPS | KEY_UNDO | KEY_FRONT | KEY_COPY | KEY_OPEN | KEY_PASTE | KEY_FIND | KEY_CUT |
+            KEY_HELP | KEY_MENU | KEY_CALC | KEY_SETUP | KEY_SLEEP | KEY_WAKEUP | KEY_FILE | KEY_SENDFILE |
+            KEY_DELETEFILE | KEY_XFER | KEY_PROG1 | KEY_PROG2 | KEY_PROG3 | KEY_PROG4 | KEY_PROGRAM |
+            KEY_WWW | KEY_MSDOS | KEY_COFFEE | KEY_SCROLLLOCK | KEY_DIRECTION | KEY_CYCLEWINDOWS | KEY_MAIL |
+            KEY_BOOKMARKS | KEY_COMPUTER | KEY_BACK | KEY_FORWARD | KEY_COMPOSE | KEY_0 | KEY_1 | KEY_2 |
+            KEY_3 | KEY_4 | KEY_5 | KEY_6 | KEY_7 | KEY_8 | KEY_9 | KEY_BACKSPACE | KEY_NUMLOCK | KEY_TAB |
+            KEY_CAPSLOCK | KEY_RIGHTSHIFT | KEY_RIGHTCTRL | KEY_RIGHTMETA | KEY_RIGHTALT | KEY_LEFTSHIFT |
+            KEY_LEFTCTRL | KEY_LEFTMETA | KEY_LEFTALT | KEY_ENTER | KEY_SPACE | KEY_UP | KEY_DOWN |
+            KEY_LEFT | KEY_RIGHT | KEY_Q | KEY_W | KEY_E | KEY_R | KEY_T | KEY_Y | KEY_U | KEY_I | KEY_O |
+            KEY_P | KEY_A | KEY_S | KEY_D | KEY_F | KEY_G | KEY_H | KEY_J | KEY_K | KEY_L | KEY_Z | KEY_X |
+            KEY_C | KEY_V | KEY_B | KEY_N | KEY_M | BTN_0 | BTN_1 | BTN_2 | BTN_3 | BTN_4 | BTN_5 | BTN_6 |
+            BTN_7 | BTN_8 | BTN_9 | BTN_LEFT | BTN_RIGHT | BTN_MIDDLE | BTN_SIDE | BTN_EXTRA | BTN_FORWARD |
+            BTN_BACK | BTN_TASK | BTN_TRIGGER | BTN_THUMB | BTN_THUMB2 | BTN_THUMBL | BTN_THUMBR | BTN_TOP |
+            BTN_TOP2 | BTN_PINKIE | BTN_BASE | BTN_BASE2 | BTN_BASE3 | BTN_BASE4 | BTN_BASE5 | BTN_BASE6 |
+            BTN_DEAD | BTN_A | BTN_B | BTN_C | BTN_X | BTN_Y | BTN_DPAD_LEFT | BTN_DPAD_RIGHT | BTN_DPAD_UP |
+            BTN_DPAD_DOWN | BTN_TL | BTN_TR | BTN_TL2 | BTN_TR2 | BTN_SELECT | BTN_START | BTN_MODE |
+            BTN_DIGI | BTN_GEAR_DOWN | BTN_GEAR_UP | BTN_TRIGGER_HAPPY1 | BTN_TRIGGER_HAPPY2 |
+            BTN_TRIGGER_HAPPY3 | BTN_TRIGGER_HAPPY4 | BTN_TRIGGER_HAPPY5 | BTN_TRIGGER_HAPPY6 |
+            BTN_TRIGGER_HAPPY7 | BTN_TRIGGER_HAPPY8 | BTN_TRIGGER_HAPPY9 | BTN_TRIGGER_HAPPY10 |
+            BTN_TRIGGER_HAPPY11 | BTN_TRIGGER_HAPPY12 | BTN_TRIGGER_HAPPY13 | BTN_TRIGGER_HAPPY14 |
+            BTN_TRIGGER_HAPPY15 | BTN_TRIGGER_HAPPY16 | BTN_TRIGGER_HAPPY17 | BTN_TRIGGER_HAPPY18 |
+            BTN_TRIGGER_HAPPY19 | BTN_TRIGGER_HAPPY20 | BTN_TRIGGER_HAPPY21 | BTN_TRIGGER_HAPPY22 |
+            BTN_TRIGGER_HAPPY23 | BTN_TRIGGER_HAPPY24 | BTN_TRIGGER_HAPPY25 | BTN_TRIGGER_HAPPY26 |
+            BTN_TRIGGER_HAPPY27 | BTN_TRIGGER_HAPPY28 | BTN_TRIGGER_HAPPY29 | BTN_TRIGGER_HAPPY30 |
+            BTN_TRIGGER_HAPPY31 | BTN_TRIGGER_HAPPY32 | BTN_TRIGGER_HAPPY33 | BTN_TRIGGER_HAPPY34 |
+            BTN_TRIGGER_HAPPY35 | BTN_TRIGGER_HAPPY36 | BTN_TRIGGER_HAPPY37 | BTN_TRIGGER_HAPPY38 |
+            BTN_TRIGGER_HAPPY39 | BTN_TRIGGER_HAPPY40 => Some(unsafe { Self::from_raw(value) }),
+            _ => None
+        }
+    }
 }
 
 impl Into<u16> for Key {
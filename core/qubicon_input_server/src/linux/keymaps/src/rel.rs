@@ -30,6 +30,15 @@ impl Relative {
     pub unsafe fn from_raw(value: u16) -> Self {
         core::mem::transmute(value)
     }
+
+    /// None, if *value* is not presentable via this enum
+    pub fn try_from_raw(value: u16) -> Option<Self> {
+        match value {
+            REL_RESERVED | REL_X | REL_Y | REL_Z | REL_RX | REL_RY | REL_RZ | REL_HWHEEL |
+            REL_HWHEEL_HI_RES | REL_WHEEL | REL_WHEEL_HI_RES | REL_DIAL | REL_MISC => Some(unsafe { Self::from_raw(value) }),
+            _ => None
+        }
+    }
 }
 
 impl Into<u16> for Relative {
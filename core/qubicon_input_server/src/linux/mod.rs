@@ -2,8 +2,16 @@ pub use keymaps;
 pub use input_server::*;
 pub use input_device::*;
 pub use device_manager::DeviceManager;
+pub use backend::{InputBackend, Device, FakeBackend, FakeDevice, input_event};
+pub use action::{Vec2, DeadZone, ResponseCurve, Trigger, ActionSettings};
+pub use force_feedback::{ForceFeedback, EffectHandle, FfEffect, FfEffectKind, Envelope, Waveform};
+pub use recording::{Recorder, Recording, RecordedEntry, ReplayBackend, ReplaySpeed};
 
+pub(crate) mod action;
+pub(crate) mod backend;
 pub(crate) mod device_manager;
 pub(crate) mod device_state;
+pub(crate) mod force_feedback;
 pub(crate) mod input_device;
-pub(crate) mod input_server;
\ No newline at end of file
+pub(crate) mod input_server;
+pub(crate) mod recording;
\ No newline at end of file
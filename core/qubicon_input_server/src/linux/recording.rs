@@ -0,0 +1,595 @@
+//! Recording and replay of input sessions.
+//! [`Recorder`] writes everything what [`crate::LinuxInputServer::update`] sees to a text file,
+//! [`ReplayBackend`] gives it back to the server.
+//!
+//! Every line starts with time in microseconds since start of recording:
+//! ```text
+//! <time> connect <id> <ev codes> <key codes> <abs code:min:max:res:fuzz:flat> <rel codes> <name>
+//! <time> disconnect <id>
+//! <time> event <id> <sec>.<usec> <type> <code> <value>
+//! ```
+//! Lists are comma separated, "-" is an empty list. Event has raw kernel time of the event
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::{self, BufRead, Write},
+    time::{Duration, Instant},
+    fmt::Write as _
+};
+
+use bitvec::slice::BitSlice;
+use keymaps::{Relative, Key, Abs, Ev};
+use nix::libc;
+
+use crate::{
+    input_device::AbsInfo,
+    backend::{Device, InputBackend, FakeBackend, FakeDevice}
+};
+
+const HEADER: &str = "qubicon-input-recording 1";
+
+/// Writes session to any output. Write errors stop the recording, the first one is returned by [`Self::finish`]
+pub struct Recorder<W: Write> {
+    output: W,
+    start: Instant,
+    devices: HashSet<u16>,
+    error: Option<io::Error>
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(mut output: W) -> io::Result<Self> {
+        writeln!(output, "{HEADER}")?;
+
+        Ok(
+            Self {
+                output,
+                start: Instant::now(),
+                devices: HashSet::new(),
+                error: None
+            }
+        )
+    }
+
+    /// Writes devices what are connected or disconnected since the last call
+    pub fn record_devices<D: Device>(&mut self, devices: &HashMap<u16, D>) {
+        let mut removed: Vec<u16> = self.devices.iter()
+            .copied()
+            .filter(| id | !devices.contains_key(id))
+            .collect();
+
+        removed.sort_unstable();
+
+        for id in removed {
+            self.devices.remove(&id);
+            self.write_line(format_args!("disconnect {id}"));
+        }
+
+        let mut added: Vec<(&u16, &D)> = devices.iter()
+            .filter(| (id, _) | !self.devices.contains(id))
+            .collect();
+
+        added.sort_unstable_by_key(| &(&id, _) | id);
+
+        for (&id, device) in added {
+            self.devices.insert(id);
+
+            let line = junk::format_capabilities(device);
+            self.write_line(format_args!("connect {id} {line}"));
+        }
+    }
+
+    pub fn record_event(&mut self, device_id: u16, event: &libc::input_event) {
+        self.write_line(format_args!(
+            "event {device_id} {}.{:06} {} {} {}",
+            event.time.tv_sec,
+            event.time.tv_usec,
+            event.type_,
+            event.code,
+            event.value
+        ));
+    }
+
+    /// Flushes output and gives it back
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        self.output.flush()?;
+
+        Ok(self.output)
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    fn write_line(&mut self, args: core::fmt::Arguments) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let time = self.start.elapsed().as_micros();
+
+        if let Err(e) = writeln!(self.output, "{time} {args}") {
+            self.error = Some(e);
+        }
+    }
+}
+
+/// One line of recording
+#[derive(Debug, Clone)]
+pub enum RecordedEntry {
+    Connect { time: Duration, device_id: u16, device: Box<FakeDevice> },
+    Disconnect { time: Duration, device_id: u16 },
+    Event { time: Duration, device_id: u16, event: libc::input_event }
+}
+
+impl RecordedEntry {
+    pub fn time(&self) -> Duration {
+        match self {
+            Self::Connect { time, .. } |
+            Self::Disconnect { time, .. } |
+            Self::Event { time, .. } => *time
+        }
+    }
+}
+
+/// Parsed recording
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    entries: Vec<RecordedEntry>
+}
+
+impl Recording {
+    /// Fails with [`io::ErrorKind::InvalidData`] on malformed lines
+    pub fn read(input: impl BufRead) -> io::Result<Self> {
+        let mut lines = input.lines();
+
+        match lines.next() {
+            Some(Ok(header)) if header.trim_end() == HEADER => {},
+            Some(Err(e)) => return Err(e),
+            _ => return Err(junk::invalid(1, "not an input recording"))
+        }
+
+        let mut entries = Vec::new();
+
+        for (n, line) in lines.enumerate() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            // header is the first line
+            let entry = junk::parse_entry(&line).ok_or_else(|| junk::invalid(n + 2, &line))?;
+
+            entries.push(entry);
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> &[RecordedEntry] {
+        &self.entries
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.entries.last()
+            .map(| e | e.time())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Wall clock multiplied by this value. 2.0 replays twice as fast, 0.0 pauses. Should be finite and not negative
+    RealTime(f32),
+    /// Only [`ReplayBackend::advance`] moves the clock, so replay does not depend on frame rate
+    Manual
+}
+
+/// Gives recorded devices and events back. Entries are replayed at their time since start of recording,
+/// events keep the original kernel time
+pub struct ReplayBackend {
+    devices: FakeBackend,
+    entries: VecDeque<RecordedEntry>,
+
+    speed: ReplaySpeed,
+    started: Option<Instant>
+}
+
+impl ReplayBackend {
+    /// Panics on negative, infinite or NaN [`ReplaySpeed::RealTime`]
+    pub fn new(recording: Recording, speed: ReplaySpeed) -> Self {
+        if let ReplaySpeed::RealTime(speed) = speed {
+            assert!(speed.is_finite() && speed >= 0.0, "replay speed should be finite and not negative, got {speed}");
+        }
+
+        Self {
+            devices: FakeBackend::new(),
+            entries: recording.entries.into(),
+
+            speed,
+            started: None
+        }
+    }
+
+    /// Moves clock in [`ReplaySpeed::Manual`]
+    pub fn advance(&mut self, duration: Duration) {
+        self.devices.advance(duration);
+    }
+
+    pub fn clock(&self) -> Duration {
+        self.devices.clock()
+    }
+
+    /// All entries are replayed and all events are read
+    pub fn is_finished(&self) -> bool {
+        self.entries.is_empty() && self.devices.devices()
+            .values()
+            .all(| d | d.pending_events() == 0)
+    }
+}
+
+impl InputBackend for ReplayBackend {
+    type Device = FakeDevice;
+
+    fn update_device_list(&mut self) {
+        if let ReplaySpeed::RealTime(speed) = self.speed {
+            let elapsed = self.started.get_or_insert_with(Instant::now).elapsed().mul_f32(speed);
+
+            self.devices.advance(elapsed.saturating_sub(self.devices.clock()));
+        }
+
+        while let Some(entry) = self.entries.front() {
+            if entry.time() > self.devices.clock() {
+                break;
+            }
+
+            match entry {
+                // Events of device should be read before it is gone. It goes on the next update
+                RecordedEntry::Disconnect { device_id, .. } if self.devices.devices()
+                    .get(device_id)
+                    .is_some_and(| d | d.pending_events() > 0) => break,
+
+                RecordedEntry::Connect { device_id, device, .. } => {
+                    self.devices.connect_with_id(*device_id, device.as_ref().clone());
+                    self.devices.update_device_list();
+                },
+                RecordedEntry::Disconnect { device_id, .. } => {
+                    self.devices.disconnect(*device_id);
+                    self.devices.update_device_list();
+                },
+                RecordedEntry::Event { time, device_id, event } => {
+                    if let Some(device) = self.devices.device_mut(*device_id) {
+                        device.script_at(*time, *event);
+                    }
+                }
+            }
+
+            self.entries.pop_front();
+        }
+    }
+
+    fn clock(&self) -> Duration {
+        self.devices.clock()
+    }
+
+    fn devices(&self) -> &HashMap<u16, FakeDevice> {
+        self.devices.devices()
+    }
+
+    fn devices_mut(&mut self) -> &mut HashMap<u16, FakeDevice> {
+        self.devices.devices_mut()
+    }
+}
+
+mod junk {
+    use super::*;
+
+    pub(super) fn invalid(line: usize, what: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, format!("line {line}: {what}"))
+    }
+
+    fn format_codes(out: &mut String, bits: Option<&BitSlice<usize>>) {
+        let mut codes = bits.into_iter().flat_map(| b | b.iter_ones()).peekable();
+
+        if codes.peek().is_none() {
+            out.push('-');
+        }
+
+        for (i, code) in codes.enumerate() {
+            if i != 0 {
+                out.push(',');
+            }
+
+            let _ = write!(out, "{code}");
+        }
+    }
+
+    pub(super) fn format_capabilities(device: &impl Device) -> String {
+        let mut line = String::new();
+
+        format_codes(&mut line, Some(device.supported_events()));
+        line.push(' ');
+        format_codes(&mut line, device.supported_keys().map(| k | k.as_bitslice()));
+        line.push(' ');
+
+        let mut abs: Vec<(u16, &AbsInfo)> = device.supported_abs()
+            .into_iter()
+            .flatten()
+            .map(| (&abs, info) | (abs.into(), info))
+            .collect();
+
+        abs.sort_unstable_by_key(| &(code, _) | code);
+
+        if abs.is_empty() {
+            line.push('-');
+        }
+
+        for (i, (code, info)) in abs.into_iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            let _ = write!(line, "{separator}{code}:{}:{}:{}:{}:{}", info.min, info.max, info.res, info.fuzz, info.flat);
+        }
+
+        line.push(' ');
+        format_codes(&mut line, device.supported_rel().map(| r | r.as_bitslice()));
+
+        // name is the rest of the line
+        line.push(' ');
+        line.extend(device.name().chars().map(| c | if c.is_control() { ' ' } else { c }));
+
+        line
+    }
+
+    fn parse_codes(list: &str) -> Option<Vec<u16>> {
+        match list {
+            "-" => Some(Vec::new()),
+            _ => list.split(',').map(| c | c.parse().ok()).collect()
+        }
+    }
+
+    fn parse_abs(list: &str) -> Option<Vec<(u16, AbsInfo)>> {
+        if list == "-" {
+            return Some(Vec::new());
+        }
+
+        list.split(',')
+            .map(| entry | {
+                let mut fields = entry.split(':');
+                let code = fields.next()?.parse().ok()?;
+                let mut next = || fields.next()?.parse::<i32>().ok();
+
+                Some((code, AbsInfo { min: next()?, max: next()?, res: next()?, fuzz: next()?, flat: next()? }))
+            })
+            .collect()
+    }
+
+    fn parse_device(rest: &str) -> Option<FakeDevice> {
+        let mut fields = rest.splitn(5, ' ');
+
+        let events = parse_codes(fields.next()?)?;
+        let keys = fields.next()?;
+        let abs = parse_abs(fields.next()?)?;
+        let rel = fields.next()?;
+        let name = fields.next().unwrap_or("");
+
+        let events: Vec<Ev> = events.into_iter().filter_map(Ev::try_from_raw).collect();
+        let mut device = FakeDevice::new(name).with_events(&events);
+
+        // Codes what keymaps don't know are dropped
+        if keys != "-" {
+            let keys: Vec<Key> = parse_codes(keys)?.into_iter().filter_map(Key::try_from_raw).collect();
+            device = device.with_keys(&keys);
+        }
+
+        if rel != "-" {
+            let rel: Vec<Relative> = parse_codes(rel)?.into_iter().filter_map(Relative::try_from_raw).collect();
+            device = device.with_rel(&rel);
+        }
+
+        for (code, info) in abs {
+            if let Some(abs) = Abs::try_from_raw(code) {
+                device = device.with_abs(abs, info);
+            }
+        }
+
+        Some(device)
+    }
+
+    pub(super) fn parse_entry(line: &str) -> Option<RecordedEntry> {
+        let mut fields = line.splitn(4, ' ');
+
+        let time = Duration::from_micros(fields.next()?.parse().ok()?);
+        let kind = fields.next()?;
+        let device_id = fields.next()?.parse().ok()?;
+        let rest = fields.next().unwrap_or("");
+
+        match kind {
+            "connect" => Some(RecordedEntry::Connect { time, device_id, device: Box::new(parse_device(rest)?) }),
+            "disconnect" => Some(RecordedEntry::Disconnect { time, device_id }),
+            "event" => {
+                let mut fields = rest.split(' ');
+                let (sec, usec) = fields.next()?.split_once('.')?;
+
+                let event = libc::input_event {
+                    time: libc::timeval {
+                        tv_sec: sec.parse().ok()?,
+                        tv_usec: usec.parse().ok()?
+                    },
+                    type_: fields.next()?.parse().ok()?,
+                    code: fields.next()?.parse().ok()?,
+                    value: fields.next()?.parse().ok()?
+                };
+
+                Some(RecordedEntry::Event { time, device_id, event })
+            },
+
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, io::{self, Write}, rc::Rc, time::Duration};
+
+    use keymaps::{Abs, Key};
+
+    use crate::{
+        input_server::{LinuxInputServer, ActionInputEntry, ActionEventType},
+        backend::{Device, InputBackend, FakeBackend, FakeDevice}
+    };
+    use super::{Recording, RecordedEntry, ReplayBackend, ReplaySpeed};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn add_jump<B: InputBackend>(server: &mut LinuxInputServer<B>) {
+        server.add_input_action(
+            "jump",
+            [ActionInputEntry { device_id: None, r#type: ActionEventType::Key { key: Key::Space, pressed: true } }]
+        );
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let mut server = LinuxInputServer::with_backend(FakeBackend::new());
+        let buffer = SharedBuffer::default();
+        let log = RefCell::new(Vec::new());
+
+        add_jump(&mut server);
+
+        let gamepad = server.backend_mut().connect(FakeDevice::gamepad("Pad \"A\" 2"));
+        server.update(| _ | {});
+        server.start_recording(buffer.clone()).unwrap();
+
+        let keyboard = server.backend_mut().connect(FakeDevice::keyboard("keyboard"));
+        server.update(| e | log.borrow_mut().push((e.device_id(), e.time(), e.data())));
+
+        // kernel time of events is far from time since start of recording
+        server.backend_mut().advance(Duration::from_secs(1000));
+        server.backend_mut().device_mut(keyboard).unwrap().push_key(Key::Space, true);
+        server.backend_mut().device_mut(gamepad).unwrap().push_abs(Abs::LX, -32768);
+        server.update(| e | log.borrow_mut().push((e.device_id(), e.time(), e.data())));
+        assert_eq!(server.is_action_pressed("jump"), Some(true));
+
+        server.backend_mut().disconnect(keyboard);
+        server.update(| e | log.borrow_mut().push((e.device_id(), e.time(), e.data())));
+        assert_eq!(server.is_action_pressed("jump"), Some(false));
+
+        assert!(server.stop_recording().unwrap().is_ok());
+        assert!(!server.is_recording());
+
+        let recording = Recording::read(&buffer.0.borrow()[..]).unwrap();
+
+        // already connected gamepad is recorded first
+        match &recording.entries()[0] {
+            RecordedEntry::Connect { device_id, device, .. } => {
+                assert_eq!(*device_id, gamepad);
+                assert_eq!(device.name(), "Pad \"A\" 2");
+                assert_eq!(device.supported_abs(), FakeDevice::gamepad("").supported_abs());
+            },
+            e => panic!("{e:?}")
+        }
+
+        let mut replay = LinuxInputServer::with_backend(ReplayBackend::new(recording, ReplaySpeed::Manual));
+        let replayed = RefCell::new(Vec::new());
+        let mut was_pressed = false;
+
+        add_jump(&mut replay);
+
+        // one big step. Disconnect waits for events of keyboard to be read
+        replay.backend_mut().advance(Duration::from_secs(60));
+
+        for _ in 0..3 {
+            replay.update(| e | replayed.borrow_mut().push((e.device_id(), e.time(), e.data())));
+            was_pressed |= replay.is_action_pressed("jump").unwrap();
+        }
+
+        assert!(replay.backend().is_finished());
+
+        // devices are read in any order, events of one device are in order. Kernel time is kept
+        let (mut replayed, mut log) = (replayed.into_inner(), log.into_inner());
+
+        replayed.sort_by_key(| &(id, ..) | id);
+        log.sort_by_key(| &(id, ..) | id);
+
+        assert_eq!(replayed, log);
+        assert!(was_pressed);
+        assert_eq!(replay.is_action_pressed("jump"), Some(false));
+        assert_eq!(replay.backend().devices().keys().collect::<Vec<_>>(), [&gamepad]);
+
+        match replay.backend().devices()[&gamepad].supported_abs() {
+            Some(abs) => assert_eq!(abs[&Abs::LX].min, -32768),
+            None => panic!()
+        }
+    }
+
+    #[test]
+    fn malformed_recording() {
+        let error = Recording::read(&b"qubicon-input-recording 1\n10 disconnect 2\n20 teleport 2\n"[..]).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("line 3"));
+
+        assert!(Recording::read(&b"something else\n"[..]).is_err());
+
+        let recording = Recording::read(&b"qubicon-input-recording 1\n5 event 1 100.000020 1 57 1\n"[..]).unwrap();
+
+        match recording.entries() {
+            [RecordedEntry::Event { time, device_id: 1, event }] => {
+                assert_eq!(*time, Duration::from_micros(5));
+                assert_eq!((event.time.tv_sec, event.time.tv_usec, event.type_, event.code, event.value), (100, 20, 1, 57, 1));
+            },
+            e => panic!("{e:?}")
+        }
+
+        assert_eq!(recording.duration(), Duration::from_micros(5));
+    }
+
+    struct BrokenOutput;
+
+    impl Write for BrokenOutput {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn restart_returns_error_of_failed_recording() {
+        let mut server = LinuxInputServer::with_backend(FakeBackend::new());
+
+        server.start_recording(BrokenOutput).unwrap();
+
+        // output is buffered, so error comes out only when recording is stopped
+        let error = server.start_recording(SharedBuffer::default()).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+        assert!(!server.is_recording());
+
+        server.start_recording(SharedBuffer::default()).unwrap();
+        assert!(server.stop_recording().unwrap().is_ok());
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_replay_speed() {
+        ReplayBackend::new(Recording::read(&b"qubicon-input-recording 1
+"[..]).unwrap(), ReplaySpeed::RealTime(-1.0));
+    }
+}